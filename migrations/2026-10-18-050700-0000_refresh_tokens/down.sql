@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS refresh_tokens_user_idx;
+
+DROP INDEX IF EXISTS refresh_tokens_family_idx;
+
+DROP TABLE IF EXISTS "refresh_tokens";
@@ -0,0 +1,17 @@
+-- opaque refresh tokens , every login starts a new family and every refresh rotates the token inside that family
+-- we only store sha256 of the token , so a db leak does not give usable refresh tokens
+
+CREATE TABLE "refresh_tokens" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    family_id UUID NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX refresh_tokens_family_idx ON refresh_tokens (family_id);
+
+CREATE INDEX refresh_tokens_user_idx ON refresh_tokens (user_id);
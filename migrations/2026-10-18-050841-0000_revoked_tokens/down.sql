@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS revoked_tokens_expires_at_idx;
+
+DROP TABLE IF EXISTS "revoked_tokens";
@@ -0,0 +1,11 @@
+-- denylist of access tokens (by jti) which are logged out before their expiry
+-- rows are useless after expires_at , as the jwt itself is expired then , so we prune them
+
+CREATE TABLE "revoked_tokens" (
+    jti UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON revoked_tokens (expires_at);
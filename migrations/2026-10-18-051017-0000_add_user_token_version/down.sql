@@ -0,0 +1,5 @@
+ALTER TABLE users
+ALTER COLUMN verification_token TYPE VARCHAR(255);
+
+ALTER TABLE users
+DROP COLUMN IF EXISTS token_version;
@@ -0,0 +1,9 @@
+-- every jwt carries the token_version of the user at the time it was issued
+-- bumping it (password change/reset , logout from all devices) makes all older tokens invalid
+
+ALTER TABLE users
+ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
+
+-- jwt now has jti and ver claims , it does not fit in 255 chars anymore
+ALTER TABLE users
+ALTER COLUMN verification_token TYPE TEXT;
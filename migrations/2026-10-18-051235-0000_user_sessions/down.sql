@@ -0,0 +1,7 @@
+ALTER TABLE users
+    ADD COLUMN verification_token TEXT,
+    ADD COLUMN token_expires_at TIMESTAMP WITH TIME ZONE;
+
+DROP INDEX IF EXISTS user_sessions_user_idx;
+
+DROP TABLE IF EXISTS "user_sessions";
@@ -0,0 +1,23 @@
+-- one row for every login (device) of the user
+-- refresh token family of a login is the session id , so revoking a session revokes its refresh tokens also
+
+CREATE TABLE "user_sessions" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    device_name VARCHAR(100),
+    user_agent TEXT,
+    ip_address VARCHAR(45),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX user_sessions_user_idx ON user_sessions (user_id);
+
+-- old refresh tokens do not belong to any session , so they are of no use now
+DELETE FROM refresh_tokens;
+
+-- single token slot on users is replaced by user_sessions
+ALTER TABLE users
+    DROP COLUMN IF EXISTS verification_token,
+    DROP COLUMN IF EXISTS token_expires_at;
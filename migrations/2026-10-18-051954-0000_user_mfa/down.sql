@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS "mfa_challenges";
+
+DROP INDEX IF EXISTS mfa_recovery_codes_user_idx;
+
+DROP TABLE IF EXISTS "mfa_recovery_codes";
+
+DROP TABLE IF EXISTS "user_mfa";
@@ -0,0 +1,34 @@
+-- totp (authenticator app) second factor of the user
+-- secret is saved on enroll , but it is used on login only after user confirms one code (enabled_at is set)
+
+CREATE TABLE "user_mfa" (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    totp_secret TEXT NOT NULL,
+    enabled_at TIMESTAMP WITH TIME ZONE,
+    last_used_step BIGINT, -- 30 sec time step of the last accepted code , same code cannot be used twice
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- one time recovery codes , only argon2 hash is saved
+CREATE TABLE "mfa_recovery_codes" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX mfa_recovery_codes_user_idx ON mfa_recovery_codes (user_id);
+
+-- password was correct , now user has to give the second factor
+-- client only gets the random challenge token , we save its sha256
+CREATE TABLE "mfa_challenges" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    device_name VARCHAR(100),
+    attempts INT NOT NULL DEFAULT 0,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS "webauthn_ceremonies";
+
+DROP TYPE IF EXISTS webauthn_ceremony_type;
+
+DROP INDEX IF EXISTS webauthn_credentials_user_idx;
+
+DROP TABLE IF EXISTS "webauthn_credentials";
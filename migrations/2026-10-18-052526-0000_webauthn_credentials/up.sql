@@ -0,0 +1,25 @@
+-- passkeys of the user , passkey column keeps the serialized webauthn credential (public key , counter)
+CREATE TABLE "webauthn_credentials" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id TEXT NOT NULL UNIQUE, -- base64url credential id given by the authenticator
+    passkey TEXT NOT NULL,
+    name VARCHAR(100),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX webauthn_credentials_user_idx ON webauthn_credentials (user_id);
+
+CREATE TYPE webauthn_ceremony_type AS ENUM ('registration', 'authentication');
+
+-- server side state of a started registration/login , it must not be given to the client
+-- every ceremony can be finished only once
+CREATE TABLE "webauthn_ceremonies" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ceremony_type webauthn_ceremony_type NOT NULL,
+    state TEXT NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS user_login_codes_email_idx;
+
+DROP TABLE IF EXISTS "user_login_codes";
@@ -0,0 +1,13 @@
+-- passwordless login , one row for every email code / magic link sent
+-- same row is used by both , code is checked by its sha256 and the magic link carries the row id in a signed token
+CREATE TABLE "user_login_codes" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_email VARCHAR(255) NOT NULL,
+    otp_hash VARCHAR(64) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX user_login_codes_email_idx ON user_login_codes (user_email);
@@ -0,0 +1,49 @@
+CREATE TABLE "user_email_verifications" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_email VARCHAR(255) NOT NULL REFERENCES users(email) ON DELETE CASCADE,
+    otp VARCHAR(6) NOT NULL,
+    expires_at TIMESTAMP with TIME ZONE ,
+    used BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX idx_email_verification_user_id on user_email_verifications (user_email);
+
+CREATE TABLE "user_reset_password_email_verifications" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_email VARCHAR(255) NOT NULL REFERENCES users(email) ON DELETE CASCADE,
+    otp VARCHAR(6) NOT NULL,
+    expires_at TIMESTAMP with TIME ZONE ,
+    used BOOLEAN NOT NULL DEFAULT FALSE ,
+    created_at TIMESTAMP with TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX user_reset_pass_email_verification_idx on user_reset_password_email_verifications (user_email);
+
+CREATE TABLE "user_reset_pass_validations" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_email VARCHAR(255) not NULL REFERENCES users(email) ON DELETE CASCADE,
+    hashed_reset_token VARCHAR(100) NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX user_reset_pass_validation_idx on user_reset_pass_validations (user_email);
+
+CREATE TABLE "user_login_codes" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_email VARCHAR(255) NOT NULL,
+    otp_hash VARCHAR(64) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX user_login_codes_email_idx ON user_login_codes (user_email);
+
+DROP INDEX IF EXISTS one_time_codes_purpose_email_idx;
+
+DROP TABLE IF EXISTS "one_time_codes";
+
+DROP TYPE IF EXISTS otp_purpose;
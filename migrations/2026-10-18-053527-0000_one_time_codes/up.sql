@@ -0,0 +1,23 @@
+-- one table for every short lived code we send/give to the user (email verification , reset password , login codes)
+-- only sha256 of the code is saved , a code can be used once and allows only max_attempts wrong tries
+CREATE TYPE otp_purpose AS ENUM ('email_verification', 'password_reset', 'password_reset_token', 'login_code');
+
+CREATE TABLE "one_time_codes" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    purpose otp_purpose NOT NULL,
+    user_email VARCHAR(255) NOT NULL,
+    code_hash VARCHAR(64) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    consumed_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX one_time_codes_purpose_email_idx ON one_time_codes (purpose, user_email);
+
+-- old tables kept plain otps , codes in them live only for few minutes , so they are not moved , user can ask for a new code
+DROP TABLE IF EXISTS "user_email_verifications";
+DROP TABLE IF EXISTS "user_reset_password_email_verifications";
+DROP TABLE IF EXISTS "user_reset_pass_validations";
+DROP TABLE IF EXISTS "user_login_codes";
@@ -0,0 +1,12 @@
+-- failed attempts of one thing (eg otp of an email) , after too many failures it is locked for some time
+-- lockout time doubles for every lockout in a row , so guessing codes/passwords becomes very slow
+CREATE TABLE "auth_throttles" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    scope VARCHAR(50) NOT NULL, -- what is throttled (eg otp:password_reset)
+    throttle_key VARCHAR(255) NOT NULL, -- whom it is throttled for (eg email)
+    failed_attempts INT NOT NULL DEFAULT 0, -- failures since last lockout
+    lockout_count INT NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP WITH TIME ZONE,
+    last_failed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (scope, throttle_key)
+);
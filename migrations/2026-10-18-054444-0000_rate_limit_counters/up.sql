@@ -0,0 +1,9 @@
+-- request counters of the rate limiter , used when RATE_LIMIT_STORE=postgres (many server instances share them)
+-- bucket_key has the rule , the key (ip / user / email) and the window , so a new window starts a new row
+CREATE TABLE "rate_limit_counters" (
+    bucket_key VARCHAR(512) PRIMARY KEY,
+    hits INT NOT NULL DEFAULT 1,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX rate_limit_counters_expires_idx ON rate_limit_counters (expires_at);
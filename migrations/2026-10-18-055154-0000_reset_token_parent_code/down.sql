@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS one_time_codes_parent_idx;
+
+ALTER TABLE one_time_codes DROP COLUMN IF EXISTS parent_id;
@@ -0,0 +1,6 @@
+-- code issued after another code was verified (eg reset token after reset otp) points to it
+-- child code is usable only while its parent row is there , so one otp gives one reset token
+ALTER TABLE one_time_codes
+    ADD COLUMN parent_id UUID NULL REFERENCES one_time_codes(id) ON DELETE CASCADE;
+
+CREATE INDEX one_time_codes_parent_idx ON one_time_codes (parent_id);
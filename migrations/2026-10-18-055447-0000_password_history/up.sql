@@ -0,0 +1,10 @@
+-- older password hashes of the user , so a password change can not go back to a recently used password
+-- only the latest few rows per user are kept (PASSWORD_HISTORY_SIZE)
+CREATE TABLE "password_history" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_V4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    password_hash VARCHAR NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX password_history_user_idx ON password_history (user_id, created_at);
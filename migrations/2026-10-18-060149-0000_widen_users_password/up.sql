@@ -0,0 +1,2 @@
+-- argon2 hashes with keyid (pepper) and bigger costs are longer than 100 chars
+ALTER TABLE users ALTER COLUMN password TYPE VARCHAR(255);
@@ -0,0 +1,5 @@
+-- time of the last real authentication (login or reauthenticate) of the session , given as auth_time claim
+-- refresh keeps it , so an old login can not become recent by refreshing tokens
+ALTER TABLE user_sessions ADD COLUMN authenticated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+UPDATE user_sessions SET authenticated_at = created_at;
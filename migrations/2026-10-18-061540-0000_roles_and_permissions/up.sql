@@ -0,0 +1,34 @@
+-- custom roles made by admins , every role is a set of permissions and a user can have many roles
+-- users.role (user/admin) stays , admins have every permission without any role
+CREATE TABLE roles (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_V4()),
+    name VARCHAR(50) NOT NULL UNIQUE,
+    description VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- permissions are checked by the code (require_permission) , so new ones come with migrations , not from the api
+-- name is resource:action:scope , eg notes:read:any
+CREATE TABLE permissions (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_V4()),
+    name VARCHAR(100) NOT NULL UNIQUE,
+    description VARCHAR(255)
+);
+
+CREATE TABLE role_permissions (
+    role_id UUID NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    permission_id UUID NOT NULL REFERENCES permissions(id) ON DELETE CASCADE,
+    PRIMARY KEY (role_id, permission_id)
+);
+
+CREATE TABLE user_roles (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role_id UUID NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role_id)
+);
+
+CREATE INDEX user_roles_role_idx ON user_roles(role_id);
+
+INSERT INTO permissions (name, description) VALUES
+    ('notes:read:any', 'read notes of any user');
@@ -0,0 +1,4 @@
+-- suspended user can not login or use his tokens until an admin unsuspends him , his data is kept
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMPTZ;
+
+CREATE INDEX users_created_at_idx ON users(created_at);
@@ -0,0 +1,3 @@
+-- time the session last passed a second factor (totp or recovery code) , given as mfa claim
+-- admin apis need it , having totp enrolled on the account is not enough
+ALTER TABLE user_sessions ADD COLUMN mfa_authenticated_at TIMESTAMPTZ;
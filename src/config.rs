@@ -2,12 +2,41 @@
 
 use std::env;
 
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+
+use crate::utils::password_policy::PasswordPolicy;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
-    pub jwt_secret: String,
-    pub jwt_maxage: i64,
+    pub jwt_secret: Option<String>,
+    pub jwt_maxage: i64, // access token life in minutes
+    pub jwt_issuer: String,
+    pub jwt_audience: String,
     pub port: i16,
+    pub jwt_algorithm: String, // HS256 (with jwt_secret) , RS256 or EdDSA (with pem key files)
+    pub jwt_signing_key_id: Option<String>,
+    pub jwt_signing_key_path: Option<String>,
+    pub jwt_verification_keys: Vec<(String, String)>, // (kid , pem path) of older keys which are still accepted
+    pub jwt_legacy_hs256_until: Option<DateTime<Utc>>, // RS256/EdDSA mode still accepts old JWT_SECRET tokens (no kid) until this time
+    pub webauthn_rp_id: String, // domain passkeys are bound to (eg example.com)
+    pub webauthn_rp_origin: String, // full origin of the frontend (eg https://app.example.com)
+    pub webauthn_rp_name: String, // name shown by the browser/authenticator
+    pub magic_link_url: String, // frontend page which posts the magic link token to our api
+    pub rate_limit_store: String, // memory (one server) or postgres (counters shared by all instances)
+    pub trusted_proxies: Vec<IpNet>, // x-forwarded-for is read only when the req comes from one of these , else socket ip is the client ip
+    pub enumeration_safe_auth: bool, // same responses for known and unknown emails in register , login (password , passkey) and reset password
+    pub password_policy: PasswordPolicy, // rules for new passwords (register , password change , reset)
+    pub breached_passwords_path: Option<String>, // hibp corpus , directory of range files (ABCDE.txt) or the single ordered by hash file , breached check is off without it
+    pub breached_password_min_count: u64, // password seen these many times in breaches is rejected
+    pub argon2_memory_kib: u32, // argon2 costs of new hashes , older hashes are rehashed on login
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub password_hash_concurrency: usize, // argon2 jobs running at once , each takes argon2_memory_kib of memory
+    pub password_pepper_file: Option<String>, // file with the secret pepper (kept out of env and db)
+    pub password_pepper_id: String, // saved in every peppered hash , change it with the pepper
+    pub password_old_pepper_files: Vec<(String, String)>, // (pepper id , file) of older peppers , their hashes are verified and rehashed on login
 }
 
 impl Config {
@@ -16,14 +45,178 @@ impl Config {
         // constructing cnfig struct and returning
 
         let database_url = env::var("DATABASE_URL").expect("database url must be set");
-        let jwt_secret = env::var("JWT_SECRET").expect("jwt secret must be set");
+        let jwt_secret = env::var("JWT_SECRET").ok();
         let jwt_maxage = env::var("JWT_MAXAGE").expect("max age must be set");
+        let jwt_issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| "rust_auth_full".to_string());
+        let jwt_audience =
+            env::var("JWT_AUDIENCE").unwrap_or_else(|_| "rust_auth_full".to_string());
+        let jwt_algorithm = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+        let jwt_signing_key_id = env::var("JWT_SIGNING_KEY_ID").ok();
+        let jwt_signing_key_path = env::var("JWT_SIGNING_KEY_PATH").ok();
 
-        return Config {
-            database_url: database_url,
-            jwt_secret: jwt_secret,
-            jwt_maxage: jwt_maxage.parse::<i64>().unwrap(),
-            port: 8080,
+        // format => kid1=/keys/old1.pem,kid2=/keys/old2.pem
+        let jwt_verification_keys = env::var("JWT_VERIFICATION_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (kid, path) = entry
+                    .split_once('=')
+                    .expect("JWT_VERIFICATION_KEYS entries must be kid=path");
+                (kid.trim().to_string(), path.trim().to_string())
+            })
+            .collect();
+
+        // only set while moving from HS256 to asymmetric keys , rfc3339 time (eg 2026-11-01T00:00:00Z)
+        let jwt_legacy_hs256_until = env::var("JWT_LEGACY_HS256_UNTIL").ok().map(|value| {
+            DateTime::parse_from_rfc3339(&value)
+                .expect("JWT_LEGACY_HS256_UNTIL must be an rfc3339 time")
+                .with_timezone(&Utc)
+        });
+
+        let webauthn_rp_id = env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| "localhost".to_string());
+        let webauthn_rp_origin =
+            env::var("WEBAUTHN_RP_ORIGIN").unwrap_or_else(|_| "http://localhost:8080".to_string());
+        let webauthn_rp_name =
+            env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "rust_auth_full".to_string());
+
+        let magic_link_url = env::var("MAGIC_LINK_URL")
+            .unwrap_or_else(|_| "http://localhost:3000/magic-login".to_string());
+
+        let rate_limit_store =
+            env::var("RATE_LIMIT_STORE").unwrap_or_else(|_| "memory".to_string());
+
+        // format => 10.0.0.0/8,127.0.0.1 , empty means no proxy in front and x-forwarded-for is ignored
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<std::net::IpAddr>().map(IpNet::from))
+                    .unwrap_or_else(|_| {
+                        panic!("TRUSTED_PROXIES entry {} must be an ip or cidr", entry)
+                    })
+            })
+            .collect();
+
+        let enumeration_safe_auth = env::var("ENUMERATION_SAFE_AUTH")
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false);
+
+        // PASSWORD_* env vars , every rule has a default so nothing has to be set
+        let env_number = |name: &str, default: usize| {
+            env::var(name)
+                .map(|value| {
+                    value
+                        .parse::<usize>()
+                        .unwrap_or_else(|_| panic!("{} must be a number", name))
+                })
+                .unwrap_or(default)
         };
+        let env_flag = |name: &str, default: bool| {
+            env::var(name)
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(default)
+        };
+
+        let password_policy = PasswordPolicy {
+            min_length: env_number("PASSWORD_MIN_LENGTH", 8),
+            max_length: env_number("PASSWORD_MAX_LENGTH", 64).min(64),
+            require_lowercase: env_flag("PASSWORD_REQUIRE_LOWERCASE", false),
+            require_uppercase: env_flag("PASSWORD_REQUIRE_UPPERCASE", false),
+            require_digit: env_flag("PASSWORD_REQUIRE_DIGIT", false),
+            require_symbol: env_flag("PASSWORD_REQUIRE_SYMBOL", false),
+            min_strength_score: env_number("PASSWORD_MIN_STRENGTH", 2).min(4) as u8,
+            reject_personal_info: env_flag("PASSWORD_REJECT_PERSONAL_INFO", true),
+            history_size: env_number("PASSWORD_HISTORY_SIZE", 5),
+        };
+
+        let breached_passwords_path = env::var("BREACHED_PASSWORDS_PATH").ok();
+        let breached_password_min_count = env::var("BREACHED_PASSWORD_MIN_COUNT")
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .expect("BREACHED_PASSWORD_MIN_COUNT must be a number")
+            })
+            .unwrap_or(1);
+
+        // defaults are argon2 crate defaults (19 MiB , 2 iterations , 1 lane)
+        let env_u32 = |name: &str, default: u32| {
+            env::var(name)
+                .map(|value| {
+                    value
+                        .parse::<u32>()
+                        .unwrap_or_else(|_| panic!("{} must be a number", name))
+                })
+                .unwrap_or(default)
+        };
+
+        let argon2_memory_kib = env_u32("ARGON2_MEMORY_KIB", 19 * 1024);
+        let argon2_iterations = env_u32("ARGON2_ITERATIONS", 2);
+        let argon2_parallelism = env_u32("ARGON2_PARALLELISM", 1);
+        // one job per cpu core by default , more would only wait for cpu and take more memory
+        let password_hash_concurrency = env::var("PASSWORD_HASH_CONCURRENCY")
+            .map(|value| {
+                value
+                    .parse::<usize>()
+                    .expect("PASSWORD_HASH_CONCURRENCY must be a number")
+            })
+            .unwrap_or_else(|_| {
+                std::thread::available_parallelism()
+                    .map(|cores| cores.get())
+                    .unwrap_or(1)
+            });
+        let password_pepper_file = env::var("PASSWORD_PEPPER_FILE").ok();
+        let password_pepper_id =
+            env::var("PASSWORD_PEPPER_ID").unwrap_or_else(|_| "p1".to_string());
+
+        // kept after a pepper rotation (or removal) till every user has logged in again , format => p1=/secrets/pepper1,p2=/secrets/pepper2
+        let password_old_pepper_files = env::var("PASSWORD_OLD_PEPPER_FILES")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (id, path) = entry
+                    .split_once('=')
+                    .expect("PASSWORD_OLD_PEPPER_FILES entries must be id=path");
+                (id.trim().to_string(), path.trim().to_string())
+            })
+            .collect();
+
+        Config {
+            database_url,
+            jwt_secret,
+            jwt_maxage: jwt_maxage
+                .parse::<i64>()
+                .expect("JWT_MAXAGE must be minutes (number)"),
+            jwt_issuer,
+            jwt_audience,
+            port: 8080,
+            jwt_algorithm,
+            jwt_signing_key_id,
+            jwt_signing_key_path,
+            jwt_verification_keys,
+            jwt_legacy_hs256_until,
+            webauthn_rp_id,
+            webauthn_rp_origin,
+            webauthn_rp_name,
+            magic_link_url,
+            rate_limit_store,
+            trusted_proxies,
+            enumeration_safe_auth,
+            password_policy,
+            breached_passwords_path,
+            breached_password_min_count,
+            argon2_memory_kib,
+            argon2_iterations,
+            argon2_parallelism,
+            password_hash_concurrency,
+            password_pepper_file,
+            password_pepper_id,
+            password_old_pepper_files,
+        }
     }
 }
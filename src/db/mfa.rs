@@ -0,0 +1,361 @@
+// we will add all the db functions related to two factor login (totp , recovery codes , login challenges) here
+
+use chrono::Utc;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::{
+    MfaChallenges, MfaRecoveryCodes, NewMfaChallenge, NewMfaRecoveryCode, UserMfa,
+};
+use crate::schema::{mfa_challenges, mfa_recovery_codes, user_mfa};
+
+// wrong codes allowed for one login challenge , after this user has to login with password again
+pub const MFA_CHALLENGE_MAX_ATTEMPTS: i32 = 5;
+
+// manager which have db connection and all the mfa functions
+pub struct MfaRepository {
+    pub db_con: DbPool,
+}
+
+impl MfaRepository {
+    pub fn new(con: DbPool) -> Self {
+        MfaRepository { db_con: con }
+    }
+
+    /**
+     * totp row of the user , it can be pending (enabled_at is none) or enabled
+     */
+    pub async fn get_user_mfa(&mut self, user_id: Uuid) -> Result<Option<UserMfa>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let mfa = tokio::task::spawn_blocking(move || {
+            user_mfa::table
+                .find(user_id)
+                .first::<UserMfa>(&mut con)
+                .optional()
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching user mfa"))?;
+
+        Ok(mfa)
+    }
+
+    /**
+     * saving a new totp secret which is not enabled yet
+     * enrolling again before confirm replaces the old pending secret
+     */
+    pub async fn save_pending_totp_secret(
+        &mut self,
+        user_id: Uuid,
+        secret: impl Into<String>,
+    ) -> Result<bool, HttpError> {
+        let secret = secret.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            diesel::insert_into(user_mfa::table)
+                .values((
+                    user_mfa::user_id.eq(user_id),
+                    user_mfa::totp_secret.eq(&secret),
+                ))
+                .on_conflict(user_mfa::user_id)
+                .do_update()
+                .set((
+                    user_mfa::totp_secret.eq(&secret),
+                    user_mfa::enabled_at.eq(None::<chrono::DateTime<Utc>>),
+                    user_mfa::last_used_step.eq(None::<i64>),
+                    user_mfa::created_at.eq(Utc::now()),
+                ))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while saving totp secret"))?;
+
+        Ok(true)
+    }
+
+    /**
+     * enabling the pending totp of the user and replacing his recovery codes , in one transaction
+     * @inputs => user id , time step of the confirmed code , argon2 hashes of new recovery codes
+     * @result => false if there is no pending totp (already enabled or never enrolled)
+     */
+    pub async fn enable_totp(
+        &mut self,
+        user_id: Uuid,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let enabled = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| {
+                let updated = diesel::update(user_mfa::table.find(user_id))
+                    .filter(user_mfa::enabled_at.is_null())
+                    .set((
+                        user_mfa::enabled_at.eq(Some(Utc::now())),
+                        user_mfa::last_used_step.eq(Some(step)),
+                    ))
+                    .execute(conn)?;
+
+                if updated == 0 {
+                    return Ok(false);
+                }
+
+                diesel::delete(mfa_recovery_codes::table)
+                    .filter(mfa_recovery_codes::user_id.eq(user_id))
+                    .execute(conn)?;
+
+                let new_codes: Vec<NewMfaRecoveryCode> = recovery_code_hashes
+                    .into_iter()
+                    .map(|code_hash| NewMfaRecoveryCode { user_id, code_hash })
+                    .collect();
+
+                diesel::insert_into(mfa_recovery_codes::table)
+                    .values(&new_codes)
+                    .execute(conn)?;
+
+                Ok(true)
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("error while enabling totp"))?;
+
+        Ok(enabled)
+    }
+
+    /**
+     * turning totp off , totp row , recovery codes and open login challenges of the user are deleted
+     * @result => false if totp was not enabled
+     */
+    pub async fn disable_totp(&mut self, user_id: Uuid) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let disabled = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| {
+                let deleted = diesel::delete(user_mfa::table.find(user_id))
+                    .filter(user_mfa::enabled_at.is_not_null())
+                    .execute(conn)?;
+
+                if deleted == 0 {
+                    return Ok(false);
+                }
+
+                diesel::delete(mfa_recovery_codes::table)
+                    .filter(mfa_recovery_codes::user_id.eq(user_id))
+                    .execute(conn)?;
+
+                diesel::delete(mfa_challenges::table)
+                    .filter(mfa_challenges::user_id.eq(user_id))
+                    .execute(conn)?;
+
+                Ok(true)
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("error while disabling totp"))?;
+
+        Ok(disabled)
+    }
+
+    /**
+     * saving the time step of an accepted totp code
+     * @result => false if this step (or newer) was already used , so a code can be used only once even with parallel reqs
+     */
+    pub async fn use_totp_step(&mut self, user_id: Uuid, step: i64) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let updated = tokio::task::spawn_blocking(move || {
+            diesel::update(user_mfa::table.find(user_id))
+                .filter(user_mfa::enabled_at.is_not_null())
+                .filter(
+                    user_mfa::last_used_step
+                        .is_null()
+                        .or(user_mfa::last_used_step.lt(step)),
+                )
+                .set(user_mfa::last_used_step.eq(Some(step)))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while saving totp step"))?;
+
+        Ok(updated > 0)
+    }
+
+    /**
+     * recovery codes of the user which are not used yet
+     */
+    pub async fn get_unused_recovery_codes(
+        &mut self,
+        user_id: Uuid,
+    ) -> Result<Vec<MfaRecoveryCodes>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let codes = tokio::task::spawn_blocking(move || {
+            mfa_recovery_codes::table
+                .filter(mfa_recovery_codes::user_id.eq(user_id))
+                .filter(mfa_recovery_codes::used_at.is_null())
+                .load::<MfaRecoveryCodes>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching recovery codes"))?;
+
+        Ok(codes)
+    }
+
+    /**
+     * marking a recovery code used
+     * @result => false if it was already used (parallel req)
+     */
+    pub async fn use_recovery_code(&mut self, code_id: Uuid) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let updated = tokio::task::spawn_blocking(move || {
+            diesel::update(mfa_recovery_codes::table.find(code_id))
+                .filter(mfa_recovery_codes::used_at.is_null())
+                .set(mfa_recovery_codes::used_at.eq(Some(Utc::now())))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while using recovery code"))?;
+
+        Ok(updated > 0)
+    }
+
+    /**
+     * saving a login challenge , after correct password of a user with totp enabled
+     */
+    pub async fn create_challenge(
+        &mut self,
+        new_challenge: NewMfaChallenge,
+    ) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            diesel::insert_into(mfa_challenges::table)
+                .values(&new_challenge)
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while saving mfa challenge"))?;
+
+        Ok(true)
+    }
+
+    /**
+     * counting one attempt on the challenge before we check the code
+     * @result => challenge if it is not used , not expired and has attempts left , else none
+     * single update , so parallel reqs cannot get more attempts than allowed
+     */
+    pub async fn start_challenge_attempt(
+        &mut self,
+        token_hash: impl Into<String>,
+    ) -> Result<Option<MfaChallenges>, HttpError> {
+        let hash = token_hash.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let challenge = tokio::task::spawn_blocking(move || {
+            diesel::update(mfa_challenges::table)
+                .filter(mfa_challenges::token_hash.eq(&hash))
+                .filter(mfa_challenges::used.eq(false))
+                .filter(mfa_challenges::expires_at.gt(Utc::now()))
+                .filter(mfa_challenges::attempts.lt(MFA_CHALLENGE_MAX_ATTEMPTS))
+                .set(mfa_challenges::attempts.eq(mfa_challenges::attempts + 1))
+                .returning(MfaChallenges::as_returning())
+                .get_result(&mut con)
+                .optional()
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while checking mfa challenge"))?;
+
+        Ok(challenge)
+    }
+
+    /**
+     * marking challenge used after correct code , so it cannot give a second login
+     * @result => false if it was already used
+     */
+    pub async fn complete_challenge(&mut self, challenge_id: Uuid) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let updated = tokio::task::spawn_blocking(move || {
+            diesel::update(mfa_challenges::table.find(challenge_id))
+                .filter(mfa_challenges::used.eq(false))
+                .set(mfa_challenges::used.eq(true))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while completing mfa challenge"))?;
+
+        Ok(updated > 0)
+    }
+
+    /**
+     * deleting used and expired login challenges
+     * @result => number of deleted rows
+     */
+    pub async fn prune_expired_challenges(&mut self) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(mfa_challenges::table)
+                .filter(
+                    mfa_challenges::used
+                        .eq(true)
+                        .or(mfa_challenges::expires_at.lt(Utc::now())),
+                )
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning mfa challenges"))?;
+
+        Ok(deleted)
+    }
+}
@@ -1,3 +1,18 @@
 pub mod users;
 
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+
+pub mod tokens;
+
+pub mod mfa;
+pub mod sessions;
+
+pub mod webauthn;
+
+pub mod otp;
+
+pub mod throttle;
+
+pub mod rate_limits;
+
+pub mod roles;
@@ -0,0 +1,623 @@
+// one time codes of every flow (email verification , reset password , email login) are handled here
+// only sha256 of the code is saved , checking and using up the code is done inside one locked db transaction
+// wrong codes are also counted per email (auth_throttles) , so asking for new codes does not give unlimited guesses
+
+// reset password is a chain of codes , every step needs the row of the step before it
+//   send-otp          => PasswordReset otp (sent on email)
+//   verify-otp        => otp is used up , PasswordResetToken is issued with parent_id = otp row
+//   save-new-password => token is used up and password is saved in the same transaction
+// new otp for the email removes every unused otp and token of the old chain
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::DbPool;
+use crate::db::throttle::{FailureResult, LockoutPolicy, ThrottleRepository};
+use crate::errors::HttpError;
+use crate::models::{NewOneTimeCode, OneTimeCodes, OtpPurposeKind};
+use crate::schema::one_time_codes;
+use crate::utils::{password::generate_otp, token::generate_refresh_token};
+
+// code which is given to the user , only its hash is saved
+pub struct IssuedCode {
+    pub id: Uuid,
+    pub code: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+// when a code is checked , one of these things can happen
+pub enum OtpVerification {
+    // code was correct , it is used up now
+    Verified(OneTimeCodes),
+    // wrong code , one attempt is used
+    Invalid,
+    Expired,
+    // too many wrong codes , code is not usable anymore , user has to ask for a new code
+    TooManyAttempts,
+    // no code was sent or it is already used
+    NotFound,
+    // too many failures for this email , nothing is checked till the lockout ends
+    Locked(DateTime<Utc>),
+}
+
+// every failed check (wrong , expired , missing code) of an email counts here
+// 5 failures lock the email for 1 minute , every next lockout doubles it (max 1 hour)
+const OTP_LOCKOUT_POLICY: LockoutPolicy = LockoutPolicy {
+    delay_after_failures: 5,
+    max_failures: 5,
+    base_lockout_seconds: 60,
+    max_lockout_seconds: 60 * 60,
+    reset_after_seconds: 24 * 60 * 60,
+};
+
+/**
+ * life (minutes) and allowed wrong attempts of the code of every purpose
+ */
+fn purpose_policy(purpose: OtpPurposeKind) -> (i64, i32) {
+    match purpose {
+        OtpPurposeKind::EmailVerification => (5, 5),
+        OtpPurposeKind::PasswordReset => (5, 5),
+        OtpPurposeKind::PasswordResetToken => (5, 5),
+        OtpPurposeKind::LoginCode => (10, 5),
+    }
+}
+
+/**
+ * unused codes of these purposes stop working when a new code of this purpose is issued
+ */
+fn superseded_purposes(purpose: OtpPurposeKind) -> Vec<OtpPurposeKind> {
+    match purpose {
+        // new reset otp starts the reset flow again , old reset token should not work anymore
+        OtpPurposeKind::PasswordReset => vec![
+            OtpPurposeKind::PasswordReset,
+            OtpPurposeKind::PasswordResetToken,
+        ],
+        other => vec![other],
+    }
+}
+
+// error of the transaction which checks the code , Refused rolls back what apply (and the check) did
+enum ApplyError<R> {
+    Db(diesel::result::Error),
+    Refused(OneTimeCodes, R),
+}
+
+impl<R> From<diesel::result::Error> for ApplyError<R> {
+    fn from(e: diesel::result::Error) -> Self {
+        ApplyError::Db(e)
+    }
+}
+
+// used and expired codes are deleted after this , it is longer than life of any code
+// so a parent row (eg used reset otp) stays while its child (reset token) can be used
+const PRUNE_AFTER_MINUTES: i64 = 60;
+
+// what the check of a code row decides , the transaction writes it
+#[derive(Debug, PartialEq)]
+enum CodeCheck {
+    Expired,
+    // row already has max attempts
+    TooManyAttempts,
+    // wrong code , attempts after this one and whether the code is used up now
+    Wrong { attempts: i32, exhausted: bool },
+    Matched,
+}
+
+/**
+ * checking the entered code (its hash) against the saved row , nothing is written here
+ * expired or exhausted row is refused before the hash is compared
+ */
+fn check_code_row(otp: &OneTimeCodes, code_hash: &str, now: DateTime<Utc>) -> CodeCheck {
+    if otp.expires_at < now {
+        return CodeCheck::Expired;
+    }
+
+    if otp.attempts >= otp.max_attempts {
+        return CodeCheck::TooManyAttempts;
+    }
+
+    // constant time compare , so response time does not tell how much of the hash matched
+    let matched: bool = otp.code_hash.as_bytes().ct_eq(code_hash.as_bytes()).into();
+
+    if matched {
+        return CodeCheck::Matched;
+    }
+
+    let attempts = otp.attempts + 1;
+
+    CodeCheck::Wrong {
+        attempts,
+        exhausted: attempts >= otp.max_attempts,
+    }
+}
+
+fn hash_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+fn throttle_scope(purpose: OtpPurposeKind) -> &'static str {
+    match purpose {
+        OtpPurposeKind::EmailVerification => "otp:email_verification",
+        OtpPurposeKind::PasswordReset => "otp:password_reset",
+        OtpPurposeKind::PasswordResetToken => "otp:password_reset_token",
+        OtpPurposeKind::LoginCode => "otp:login_code",
+    }
+}
+
+// manager which have db connection and all the one time code functions
+pub struct OtpService {
+    pub db_con: DbPool,
+}
+
+impl OtpService {
+    pub fn new(con: DbPool) -> Self {
+        OtpService { db_con: con }
+    }
+
+    /**
+     * creating a new code for the email , older not used codes of same purpose stop working
+     * reset token is a long random token (it is not typed by user) , every other code is 6 digit otp
+     * @result => plain code (to send to the user) , row id and expiry
+     */
+    pub async fn issue_code(
+        &mut self,
+        purpose: OtpPurposeKind,
+        email: impl Into<String>,
+    ) -> Result<IssuedCode, HttpError> {
+        self.save_new_code(purpose, email.into(), None).await
+    }
+
+    /**
+     * creating the next code of a chain , after the parent code was verified (eg reset token after reset otp)
+     * child is for the same email and works only while the parent row is there
+     */
+    pub async fn issue_code_after(
+        &mut self,
+        parent: &OneTimeCodes,
+        purpose: OtpPurposeKind,
+    ) -> Result<IssuedCode, HttpError> {
+        self.save_new_code(purpose, parent.user_email.clone(), Some(parent.id))
+            .await
+    }
+
+    async fn save_new_code(
+        &mut self,
+        purpose: OtpPurposeKind,
+        user_email: String,
+        parent_id: Option<Uuid>,
+    ) -> Result<IssuedCode, HttpError> {
+        let code = match purpose {
+            OtpPurposeKind::PasswordResetToken => generate_refresh_token(),
+            _ => generate_otp(),
+        };
+
+        let (maxage_minutes, max_attempts) = purpose_policy(purpose);
+        let expires_at = Utc::now() + Duration::minutes(maxage_minutes);
+
+        let new_code = NewOneTimeCode {
+            purpose,
+            user_email,
+            code_hash: hash_code(&code),
+            max_attempts,
+            expires_at,
+            parent_id,
+        };
+
+        let superseded = superseded_purposes(purpose);
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let id = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::delete(one_time_codes::table)
+                    .filter(one_time_codes::purpose.eq_any(superseded))
+                    .filter(one_time_codes::user_email.eq(&new_code.user_email))
+                    .filter(one_time_codes::consumed_at.is_null())
+                    .execute(conn)?;
+
+                diesel::insert_into(one_time_codes::table)
+                    .values(&new_code)
+                    .returning(one_time_codes::id)
+                    .get_result::<Uuid>(conn)
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("error while saving one time code"))?;
+
+        Ok(IssuedCode {
+            id,
+            code,
+            expires_at,
+        })
+    }
+
+    /**
+     * checking the code entered by user against the latest code of this purpose and email
+     * locked email is rejected before the code is even looked at , every failure is counted for the email
+     * @result => verification result , Locked if this failure started a lockout
+     */
+    pub async fn verify_code(
+        &mut self,
+        purpose: OtpPurposeKind,
+        email: impl Into<String>,
+        code: impl AsRef<str>,
+    ) -> Result<OtpVerification, HttpError> {
+        let (verification, _) = self
+            .verify_code_and_apply(purpose, email, code, |_, _| Ok(Ok::<(), ()>(())))
+            .await?;
+
+        Ok(verification)
+    }
+
+    /**
+     * checking the code without using it up , eg reset token is checked before the slow password hashing
+     * wrong code is counted (attempts and email throttle) same as in verify_code , correct code stays usable
+     * @result => verification result , Verified code is still not used
+     */
+    pub async fn check_code(
+        &mut self,
+        purpose: OtpPurposeKind,
+        email: impl Into<String>,
+        code: impl AsRef<str>,
+    ) -> Result<OtpVerification, HttpError> {
+        let (verification, _) = self
+            .verify(purpose, email.into(), code.as_ref(), false, |_, _| {
+                Ok(Ok::<(), ()>(()))
+            })
+            .await?;
+
+        Ok(verification)
+    }
+
+    /**
+     * same as verify_code , but apply runs inside the transaction which uses up the code
+     * apply can also refuse (Err) the verified code (eg new password breaks the policy) , then whole transaction is rolled back
+     * if apply fails or refuses , the code is not used up , if the code is wrong apply is not run
+     * @result => verification result and result of apply (only for verified code)
+     */
+    pub async fn verify_code_and_apply<T, R, F>(
+        &mut self,
+        purpose: OtpPurposeKind,
+        email: impl Into<String>,
+        code: impl AsRef<str>,
+        apply: F,
+    ) -> Result<(OtpVerification, Option<Result<T, R>>), HttpError>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut PgConnection, &OneTimeCodes) -> QueryResult<Result<T, R>> + Send + 'static,
+    {
+        self.verify(purpose, email.into(), code.as_ref(), true, apply)
+            .await
+    }
+
+    // throttle around check_and_use_code , use_up is false only for check_code
+    async fn verify<T, R, F>(
+        &mut self,
+        purpose: OtpPurposeKind,
+        user_email: String,
+        code: &str,
+        use_up: bool,
+        apply: F,
+    ) -> Result<(OtpVerification, Option<Result<T, R>>), HttpError>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut PgConnection, &OneTimeCodes) -> QueryResult<Result<T, R>> + Send + 'static,
+    {
+        let scope = throttle_scope(purpose);
+
+        let mut throttle_repo = ThrottleRepository::new(self.db_con.clone());
+
+        if let Some(locked_until) = throttle_repo.locked_until(scope, &user_email).await? {
+            return Ok((OtpVerification::Locked(locked_until), None));
+        }
+
+        let (result, applied) = self
+            .check_and_use_code(purpose, &user_email, code, use_up, apply)
+            .await?;
+
+        if let OtpVerification::Verified(_) = result {
+            throttle_repo.clear(scope, &user_email).await?;
+            return Ok((result, applied));
+        }
+
+        match throttle_repo
+            .record_failure(scope, &user_email, &OTP_LOCKOUT_POLICY)
+            .await?
+        {
+            FailureResult::Delayed(locked_until) | FailureResult::LockedOut(locked_until) => {
+                Ok((OtpVerification::Locked(locked_until), None))
+            }
+            FailureResult::Counted => Ok((result, None)),
+        }
+    }
+
+    /**
+     * row is locked , so two parallel reqs can not both use the same code or go past max attempts
+     * every check counts as an attempt , correct code is used up in the same transaction
+     * code which reaches max attempts is used up also , so it can never be verified later
+     * child code (with parent_id) is not found if its parent row is gone or is of another email
+     * with use_up false correct code is only reported (no attempt , not used up) and apply is not run
+     */
+    async fn check_and_use_code<T, R, F>(
+        &mut self,
+        purpose: OtpPurposeKind,
+        email: &str,
+        code: &str,
+        use_up: bool,
+        apply: F,
+    ) -> Result<(OtpVerification, Option<Result<T, R>>), HttpError>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(&mut PgConnection, &OneTimeCodes) -> QueryResult<Result<T, R>> + Send + 'static,
+    {
+        let user_email = email.to_string();
+        let code_hash = hash_code(code);
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let result = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, ApplyError<R>, _>(|conn| {
+                let latest = one_time_codes::table
+                    .filter(one_time_codes::purpose.eq(purpose))
+                    .filter(one_time_codes::user_email.eq(&user_email))
+                    .filter(one_time_codes::consumed_at.is_null())
+                    .order_by(one_time_codes::created_at.desc())
+                    .for_update()
+                    .first::<OneTimeCodes>(conn)
+                    .optional()?;
+
+                let Some(otp) = latest else {
+                    return Ok((OtpVerification::NotFound, None));
+                };
+
+                if let Some(parent_id) = otp.parent_id {
+                    let parent_found = one_time_codes::table
+                        .find(parent_id)
+                        .filter(one_time_codes::user_email.eq(&user_email))
+                        .filter(one_time_codes::consumed_at.is_not_null())
+                        .select(one_time_codes::id)
+                        .first::<Uuid>(conn)
+                        .optional()?
+                        .is_some();
+
+                    if !parent_found {
+                        return Ok((OtpVerification::NotFound, None));
+                    }
+                }
+
+                match check_code_row(&otp, &code_hash, Utc::now()) {
+                    CodeCheck::Expired => return Ok((OtpVerification::Expired, None)),
+                    CodeCheck::TooManyAttempts => {
+                        return Ok((OtpVerification::TooManyAttempts, None));
+                    }
+                    CodeCheck::Wrong {
+                        attempts,
+                        exhausted,
+                    } => {
+                        diesel::update(one_time_codes::table.find(otp.id))
+                            .set((
+                                one_time_codes::attempts.eq(attempts),
+                                one_time_codes::consumed_at.eq(exhausted.then(Utc::now)),
+                            ))
+                            .execute(conn)?;
+
+                        if exhausted {
+                            return Ok((OtpVerification::TooManyAttempts, None));
+                        }
+
+                        return Ok((OtpVerification::Invalid, None));
+                    }
+                    CodeCheck::Matched => {}
+                }
+
+                if !use_up {
+                    return Ok((OtpVerification::Verified(otp), None));
+                }
+
+                let used = diesel::update(one_time_codes::table.find(otp.id))
+                    .set((
+                        one_time_codes::attempts.eq(one_time_codes::attempts + 1),
+                        one_time_codes::consumed_at.eq(Some(Utc::now())),
+                    ))
+                    .returning(OneTimeCodes::as_returning())
+                    .get_result(conn)?;
+
+                match apply(conn, &used)? {
+                    Ok(applied) => Ok((OtpVerification::Verified(used), Some(Ok(applied)))),
+                    Err(refused) => Err(ApplyError::Refused(used, refused)),
+                }
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?;
+
+        match result {
+            Ok(result) => Ok(result),
+            // code was correct , but nothing is saved and it is not used up
+            Err(ApplyError::Refused(code, refused)) => {
+                Ok((OtpVerification::Verified(code), Some(Err(refused))))
+            }
+            Err(ApplyError::Db(e)) => {
+                tracing::error!("error while verifying one time code {}", e);
+                Err(HttpError::server_error(
+                    "error while verifying one time code",
+                ))
+            }
+        }
+    }
+
+    /**
+     * using up a code by its row id , when the user is already proved by something else (eg signed magic link)
+     * one conditional update , so only one req can use it
+     * @result => false if code is not found , expired , locked or already used
+     */
+    pub async fn consume_code(
+        &mut self,
+        purpose: OtpPurposeKind,
+        code_id: Uuid,
+        email: impl Into<String>,
+    ) -> Result<bool, HttpError> {
+        let user_email = email.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let updated = tokio::task::spawn_blocking(move || {
+            diesel::update(one_time_codes::table.find(code_id))
+                .filter(one_time_codes::purpose.eq(purpose))
+                .filter(one_time_codes::user_email.eq(user_email))
+                .filter(one_time_codes::consumed_at.is_null())
+                .filter(one_time_codes::expires_at.gt(Utc::now()))
+                .filter(one_time_codes::attempts.lt(one_time_codes::max_attempts))
+                .set(one_time_codes::consumed_at.eq(Some(Utc::now())))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while using one time code"))?;
+
+        Ok(updated > 0)
+    }
+
+    /**
+     * deleting codes which are used or expired since PRUNE_AFTER_MINUTES
+     * @result => number of deleted rows
+     */
+    pub async fn prune_expired_codes(&mut self) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let prune_before = Utc::now() - Duration::minutes(PRUNE_AFTER_MINUTES);
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(one_time_codes::table)
+                .filter(
+                    one_time_codes::consumed_at
+                        .lt(prune_before)
+                        .or(one_time_codes::expires_at.lt(prune_before)),
+                )
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning one time codes"))?;
+
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_row(code: &str, attempts: i32, expires_in_minutes: i64) -> OneTimeCodes {
+        let (_, max_attempts) = purpose_policy(OtpPurposeKind::PasswordReset);
+
+        OneTimeCodes {
+            id: Uuid::new_v4(),
+            purpose: OtpPurposeKind::PasswordReset,
+            user_email: "user@example.com".to_string(),
+            code_hash: hash_code(code),
+            attempts,
+            max_attempts,
+            expires_at: Utc::now() + Duration::minutes(expires_in_minutes),
+            consumed_at: None,
+            created_at: Utc::now(),
+            parent_id: None,
+        }
+    }
+
+    #[test]
+    fn only_the_hash_of_the_code_is_kept() {
+        let hash = hash_code("123456");
+
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, "123456");
+        assert_eq!(hash, hash_code("123456"));
+        assert_ne!(hash, hash_code("123457"));
+    }
+
+    #[test]
+    fn correct_code_matches() {
+        let otp = code_row("123456", 0, 5);
+
+        assert_eq!(
+            check_code_row(&otp, &hash_code("123456"), Utc::now()),
+            CodeCheck::Matched
+        );
+    }
+
+    #[test]
+    fn wrong_code_counts_an_attempt_and_last_attempt_uses_up_the_code() {
+        let otp = code_row("123456", 0, 5);
+        assert_eq!(
+            check_code_row(&otp, &hash_code("654321"), Utc::now()),
+            CodeCheck::Wrong {
+                attempts: 1,
+                exhausted: false
+            }
+        );
+
+        let otp = code_row("123456", otp.max_attempts - 1, 5);
+        assert_eq!(
+            check_code_row(&otp, &hash_code("654321"), Utc::now()),
+            CodeCheck::Wrong {
+                attempts: otp.max_attempts,
+                exhausted: true
+            }
+        );
+    }
+
+    #[test]
+    fn exhausted_code_is_refused_even_with_the_right_code() {
+        let otp = code_row("123456", 5, 5);
+
+        assert_eq!(
+            check_code_row(&otp, &hash_code("123456"), Utc::now()),
+            CodeCheck::TooManyAttempts
+        );
+    }
+
+    #[test]
+    fn expired_code_is_refused_even_with_the_right_code() {
+        let otp = code_row("123456", 0, -1);
+
+        assert_eq!(
+            check_code_row(&otp, &hash_code("123456"), Utc::now()),
+            CodeCheck::Expired
+        );
+    }
+
+    #[test]
+    fn hash_of_other_length_does_not_match() {
+        let otp = code_row("123456", 0, 5);
+
+        // ct_eq of different lengths is false , not a panic
+        assert!(matches!(
+            check_code_row(&otp, "", Utc::now()),
+            CodeCheck::Wrong { .. }
+        ));
+        assert!(matches!(
+            check_code_row(&otp, &otp.code_hash[..32], Utc::now()),
+            CodeCheck::Wrong { .. }
+        ));
+    }
+}
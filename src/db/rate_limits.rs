@@ -0,0 +1,78 @@
+// we will add all the db functions of the postgres rate limit store here
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::NewRateLimitCounter;
+use crate::schema::rate_limit_counters;
+
+// manager which have db connection and all the rate limit counter functions
+pub struct RateLimitRepository {
+    pub db_con: DbPool,
+}
+
+impl RateLimitRepository {
+    pub fn new(con: DbPool) -> Self {
+        RateLimitRepository { db_con: con }
+    }
+
+    /**
+     * counting one req in the bucket (rule + key + window) , one upsert so parallel reqs are all counted
+     * @result => reqs in this bucket including this one
+     */
+    pub async fn hit(
+        &mut self,
+        bucket_key: impl Into<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<i32, HttpError> {
+        let new_counter = NewRateLimitCounter {
+            bucket_key: bucket_key.into(),
+            hits: 1,
+            expires_at,
+        };
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let hits = tokio::task::spawn_blocking(move || {
+            diesel::insert_into(rate_limit_counters::table)
+                .values(&new_counter)
+                .on_conflict(rate_limit_counters::bucket_key)
+                .do_update()
+                .set(rate_limit_counters::hits.eq(rate_limit_counters::hits + 1))
+                .returning(rate_limit_counters::hits)
+                .get_result::<i32>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while counting the req"))?;
+
+        Ok(hits)
+    }
+
+    /**
+     * deleting counters of windows which are over
+     * @result => number of deleted rows
+     */
+    pub async fn prune_expired_counters(&mut self) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(rate_limit_counters::table)
+                .filter(rate_limit_counters::expires_at.lt(Utc::now()))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning rate limit counters"))?;
+
+        Ok(deleted)
+    }
+}
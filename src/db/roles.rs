@@ -0,0 +1,235 @@
+// we will add all the db functions of custom roles and permissions here
+// user gets permissions of every role given to him , admins (users.role = admin) do not need any role
+
+use std::collections::{HashMap, HashSet};
+
+use axum::http::StatusCode;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use uuid::Uuid;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::{NewRole, NewRolePermission, NewUserRole, Permissions, Roles};
+use crate::schema::{permissions, role_permissions, roles, user_roles};
+
+pub struct RoleRepository {
+    pub db_con: DbPool,
+}
+
+impl RoleRepository {
+    pub fn new(con: DbPool) -> Self {
+        RoleRepository { db_con: con }
+    }
+
+    /**
+     * @result => names of all permissions the user gets from his roles
+     */
+    pub async fn get_user_permissions(
+        &mut self,
+        user_id: Uuid,
+    ) -> Result<HashSet<String>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let names = tokio::task::spawn_blocking(move || {
+            let user_role_ids = user_roles::table
+                .filter(user_roles::user_id.eq(user_id))
+                .select(user_roles::role_id);
+
+            permissions::table
+                .inner_join(role_permissions::table)
+                .filter(role_permissions::role_id.eq_any(user_role_ids))
+                .select(permissions::name)
+                .distinct()
+                .load::<String>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching user permissions"))?;
+
+        Ok(names.into_iter().collect())
+    }
+
+    /**
+     * @result => every permission which can be given to a role
+     */
+    pub async fn get_permissions(&mut self) -> Result<Vec<Permissions>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            permissions::table
+                .order_by(permissions::name)
+                .select(Permissions::as_select())
+                .load(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching permissions"))
+    }
+
+    /**
+     * @result => every role with names of its permissions
+     */
+    pub async fn get_roles(&mut self) -> Result<Vec<(Roles, Vec<String>)>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let (all_roles, grants) = tokio::task::spawn_blocking(move || {
+            let all_roles = roles::table
+                .order_by(roles::name)
+                .select(Roles::as_select())
+                .load(&mut con)?;
+
+            let grants = role_permissions::table
+                .inner_join(permissions::table)
+                .order_by(permissions::name)
+                .select((role_permissions::role_id, permissions::name))
+                .load::<(Uuid, String)>(&mut con)?;
+
+            Ok::<_, Error>((all_roles, grants))
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching roles"))?;
+
+        let mut role_permission_names: HashMap<Uuid, Vec<String>> = HashMap::new();
+
+        for (role_id, name) in grants {
+            role_permission_names.entry(role_id).or_default().push(name);
+        }
+
+        Ok(all_roles
+            .into_iter()
+            .map(|role| {
+                let names = role_permission_names.remove(&role.id).unwrap_or_default();
+                (role, names)
+            })
+            .collect())
+    }
+
+    /**
+     * saving a new role with its permissions
+     * @inputs => role name , description and permission names
+     * @result => saved role , 400 if some permission does not exist , 409 if the role name is taken
+     */
+    pub async fn create_role(
+        &mut self,
+        new_role: NewRole,
+        permission_names: Vec<String>,
+    ) -> Result<Roles, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let saved = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, Error, _>(|conn| {
+                let found = permissions::table
+                    .filter(permissions::name.eq_any(&permission_names))
+                    .select((permissions::id, permissions::name))
+                    .load::<(Uuid, String)>(conn)?;
+
+                let unknown = permission_names
+                    .iter()
+                    .filter(|name| !found.iter().any(|(_, found_name)| found_name == *name))
+                    .cloned()
+                    .collect::<Vec<String>>();
+
+                // nothing is saved yet , so returning here needs no rollback
+                if !unknown.is_empty() {
+                    return Ok(Err(unknown));
+                }
+
+                let role = diesel::insert_into(roles::table)
+                    .values(&new_role)
+                    .returning(Roles::as_returning())
+                    .get_result(conn)?;
+
+                let grants = found
+                    .into_iter()
+                    .map(|(permission_id, _)| NewRolePermission {
+                        role_id: role.id,
+                        permission_id,
+                    })
+                    .collect::<Vec<NewRolePermission>>();
+
+                if !grants.is_empty() {
+                    diesel::insert_into(role_permissions::table)
+                        .values(&grants)
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                }
+
+                Ok(Ok(role))
+            })
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|e| match e {
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                HttpError::new("role with this name already exists", StatusCode::CONFLICT)
+            }
+            _ => HttpError::server_error("error while saving role"),
+        })?;
+
+        saved.map_err(|unknown| {
+            HttpError::bad_request(format!("unknown permissions {}", unknown.join(" , ")))
+        })
+    }
+
+    /**
+     * giving a role to the user
+     * @result => false if the user already has it , 404 if the user or the role does not exist
+     */
+    pub async fn assign_role(&mut self, user_id: Uuid, role_id: Uuid) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let inserted = tokio::task::spawn_blocking(move || {
+            diesel::insert_into(user_roles::table)
+                .values(&NewUserRole { user_id, role_id })
+                .on_conflict_do_nothing()
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|e| match e {
+            Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                HttpError::new("user or role not found", StatusCode::NOT_FOUND)
+            }
+            _ => HttpError::server_error("error while assigning role"),
+        })?;
+
+        Ok(inserted > 0)
+    }
+
+    /**
+     * taking a role back from the user
+     * @result => false if the user did not have it
+     */
+    pub async fn remove_role(&mut self, user_id: Uuid, role_id: Uuid) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(user_roles::table.find((user_id, role_id))).execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while removing role"))?;
+
+        Ok(deleted > 0)
+    }
+}
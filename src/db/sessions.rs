@@ -0,0 +1,273 @@
+// we will add all the db functions related to login sessions (devices) of the user here
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::{NewUserSession, UserSessions};
+use crate::schema::{refresh_tokens, user_sessions};
+
+// we do not write last_seen_at on every req , only when it is older than this
+const LAST_SEEN_UPDATE_INTERVAL_SECONDS: i64 = 60;
+
+// (authenticated_at , mfa_authenticated_at) of a session , they become auth_time and mfa claims
+pub type SessionAuthTimes = (DateTime<Utc>, Option<DateTime<Utc>>);
+
+// manager which have db connection and all the session functions
+pub struct SessionRepository {
+    pub db_con: DbPool,
+}
+
+impl SessionRepository {
+    pub fn new(con: DbPool) -> Self {
+        SessionRepository { db_con: con }
+    }
+
+    /**
+     * saving a new login session of the user
+     * @result => saved session , its id is used as sid claim and refresh token family id
+     */
+    pub async fn create_session(
+        &mut self,
+        new_session: NewUserSession,
+    ) -> Result<UserSessions, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let session = tokio::task::spawn_blocking(move || {
+            diesel::insert_into(user_sessions::table)
+                .values(&new_session)
+                .returning(UserSessions::as_returning())
+                .get_result(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while saving user session"))?;
+
+        Ok(session)
+    }
+
+    /**
+     * called by auth middleware on every req
+     * @inputs => session id (sid claim) and user id (sub claim)
+     * @result => error if session is not found or revoked , else we bump last_seen_at (at most once a minute) and return true
+     */
+    pub async fn touch_session(
+        &mut self,
+        session_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let session = tokio::task::spawn_blocking(move || {
+            let session = user_sessions::table
+                .filter(user_sessions::id.eq(session_id))
+                .filter(user_sessions::user_id.eq(user_id))
+                .first::<UserSessions>(&mut con)
+                .optional()?;
+
+            if let Some(session) = &session {
+                let stale_before =
+                    Utc::now() - Duration::seconds(LAST_SEEN_UPDATE_INTERVAL_SECONDS);
+
+                if session.revoked_at.is_none() && session.last_seen_at < stale_before {
+                    diesel::update(user_sessions::table.find(session.id))
+                        .set(user_sessions::last_seen_at.eq(Utc::now()))
+                        .execute(&mut con)?;
+                }
+            }
+
+            Ok::<Option<UserSessions>, diesel::result::Error>(session)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching user session"))?;
+
+        match session {
+            Some(session) if session.revoked_at.is_none() => Ok(true),
+            _ => Err(HttpError::unauthorized(
+                "session has been revoked , please login again",
+            )),
+        }
+    }
+
+    /**
+     * all active (not revoked) sessions of the user , latest used first
+     */
+    pub async fn get_user_sessions(
+        &mut self,
+        user_id: Uuid,
+    ) -> Result<Vec<UserSessions>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let sessions = tokio::task::spawn_blocking(move || {
+            user_sessions::table
+                .filter(user_sessions::user_id.eq(user_id))
+                .filter(user_sessions::revoked_at.is_null())
+                .order_by(user_sessions::last_seen_at.desc())
+                .load::<UserSessions>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching user sessions"))?;
+
+        Ok(sessions)
+    }
+
+    /**
+     * @result => when the user last authenticated in this session and when he last passed a second factor in it
+     * refreshed access tokens keep these (auth_time and mfa claims)
+     */
+    pub async fn get_auth_times(
+        &mut self,
+        session_id: Uuid,
+    ) -> Result<SessionAuthTimes, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            user_sessions::table
+                .find(session_id)
+                .select((
+                    user_sessions::authenticated_at,
+                    user_sessions::mfa_authenticated_at,
+                ))
+                .first::<SessionAuthTimes>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::unauthorized("session not found , please login again"))
+    }
+
+    /**
+     * user proved again who he is (reauthenticate) , so the session counts as freshly authenticated
+     * @inputs => mfa is true if he proved it with a second factor (totp) , then the session counts as mfa authenticated too
+     * @result => new auth time and mfa auth time , None if the session is revoked or not of this user
+     */
+    pub async fn mark_authenticated(
+        &mut self,
+        user_id: Uuid,
+        session_id: Uuid,
+        mfa: bool,
+    ) -> Result<Option<SessionAuthTimes>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            let now = Utc::now();
+
+            let query = diesel::update(user_sessions::table)
+                .filter(user_sessions::id.eq(session_id))
+                .filter(user_sessions::user_id.eq(user_id))
+                .filter(user_sessions::revoked_at.is_null());
+
+            let returning = (
+                user_sessions::authenticated_at,
+                user_sessions::mfa_authenticated_at,
+            );
+
+            if mfa {
+                query
+                    .set((
+                        user_sessions::authenticated_at.eq(now),
+                        user_sessions::mfa_authenticated_at.eq(Some(now)),
+                    ))
+                    .returning(returning)
+                    .get_result::<SessionAuthTimes>(&mut con)
+                    .optional()
+            } else {
+                query
+                    .set(user_sessions::authenticated_at.eq(now))
+                    .returning(returning)
+                    .get_result::<SessionAuthTimes>(&mut con)
+                    .optional()
+            }
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while updating user session"))
+    }
+
+    /**
+     * revoking one session of the user and every refresh token of it
+     * @result => false if there is no active session with this id for this user
+     */
+    pub async fn revoke_session(
+        &mut self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let revoked = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| {
+                let updated = diesel::update(user_sessions::table)
+                    .filter(user_sessions::id.eq(session_id))
+                    .filter(user_sessions::user_id.eq(user_id))
+                    .filter(user_sessions::revoked_at.is_null())
+                    .set(user_sessions::revoked_at.eq(Some(Utc::now())))
+                    .execute(conn)?;
+
+                // refresh token family of the session is the session id
+                diesel::update(refresh_tokens::table)
+                    .filter(refresh_tokens::family_id.eq(session_id))
+                    .filter(refresh_tokens::user_id.eq(user_id))
+                    .set(refresh_tokens::revoked.eq(true))
+                    .execute(conn)?;
+
+                Ok(updated > 0)
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("error while revoking user session"))?;
+
+        Ok(revoked)
+    }
+
+    /**
+     * deleting sessions which are revoked or not used since the given time
+     * @result => number of deleted rows
+     */
+    pub async fn prune_stale_sessions(
+        &mut self,
+        inactive_since: DateTime<Utc>,
+    ) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(user_sessions::table)
+                .filter(
+                    user_sessions::revoked_at
+                        .is_not_null()
+                        .or(user_sessions::last_seen_at.lt(inactive_since)),
+                )
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning user sessions"))?;
+
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,355 @@
+// we will add all the db functions related to failed attempts and temporary lockouts here
+// one row for every (scope , key) , eg (otp:password_reset , email)
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::{AuthThrottles, NewAuthThrottle};
+use crate::schema::auth_throttles;
+
+// how many failures are allowed and for how long we lock after that
+#[derive(Clone, Copy)]
+pub struct LockoutPolicy {
+    pub delay_after_failures: i32, // after these failures , every next failure waits 1s , 2s , 4s ... (same as max_failures for no delays)
+    pub max_failures: i32,         // failures allowed before a lockout
+    pub base_lockout_seconds: i64, // first lockout , it doubles for every next lockout
+    pub max_lockout_seconds: i64,
+    pub reset_after_seconds: i64, // after this much quiet time , counters start again from zero
+}
+
+impl LockoutPolicy {
+    /**
+     * counting one more failure on the saved counters of a row
+     * when failures reach max_failures , we lock for base * 2^(lockouts before) seconds (capped at max)
+     * before that (after delay_after_failures) every failure gets a short doubling delay
+     * @inputs => saved failed attempts , lockouts and time of the last failure , now
+     * @result => new (failed attempts , lockouts) and what happened
+     */
+    pub fn count_failure(
+        &self,
+        failed_attempts: i32,
+        lockout_count: i32,
+        last_failed_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> (i32, i32, FailureResult) {
+        // old failures are forgiven after a quiet period
+        let (mut failed_attempts, mut lockout_count) =
+            if last_failed_at < now - Duration::seconds(self.reset_after_seconds) {
+                (0, 0)
+            } else {
+                (failed_attempts, lockout_count)
+            };
+
+        failed_attempts += 1;
+
+        let result = if failed_attempts >= self.max_failures {
+            let lockout_seconds = self
+                .base_lockout_seconds
+                .saturating_mul(1_i64 << lockout_count.min(20))
+                .min(self.max_lockout_seconds);
+
+            lockout_count += 1;
+            failed_attempts = 0;
+
+            FailureResult::LockedOut(now + Duration::seconds(lockout_seconds))
+        } else if failed_attempts >= self.delay_after_failures {
+            let delay_seconds = (1_i64 << (failed_attempts - self.delay_after_failures).min(20))
+                .min(self.base_lockout_seconds);
+
+            FailureResult::Delayed(now + Duration::seconds(delay_seconds))
+        } else {
+            FailureResult::Counted
+        };
+
+        (failed_attempts, lockout_count, result)
+    }
+}
+
+// what happened after a failure is counted
+#[derive(Debug, PartialEq)]
+pub enum FailureResult {
+    Counted,
+    // short wait before the next try
+    Delayed(DateTime<Utc>),
+    // max failures reached , locked for a longer time
+    LockedOut(DateTime<Utc>),
+}
+
+// manager which have db connection and all the throttle functions
+pub struct ThrottleRepository {
+    pub db_con: DbPool,
+}
+
+/**
+ * seconds left in the lockout , this goes in the Retry-After header
+ */
+pub fn retry_after_seconds(locked_until: DateTime<Utc>) -> u64 {
+    (locked_until - Utc::now()).num_seconds().max(1) as u64
+}
+
+impl ThrottleRepository {
+    pub fn new(con: DbPool) -> Self {
+        ThrottleRepository { db_con: con }
+    }
+
+    /**
+     * @result => end of the lockout , if (scope , key) is locked right now
+     */
+    pub async fn locked_until(
+        &mut self,
+        scope: impl Into<String>,
+        key: impl Into<String>,
+    ) -> Result<Option<DateTime<Utc>>, HttpError> {
+        let scope = scope.into();
+        let key = key.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let locked_until = tokio::task::spawn_blocking(move || {
+            auth_throttles::table
+                .filter(auth_throttles::scope.eq(scope))
+                .filter(auth_throttles::throttle_key.eq(key))
+                .filter(auth_throttles::locked_until.gt(Utc::now()))
+                .select(auth_throttles::locked_until)
+                .first::<Option<DateTime<Utc>>>(&mut con)
+                .optional()
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while checking lockout"))?;
+
+        Ok(locked_until.flatten())
+    }
+
+    /**
+     * counting one failure (see LockoutPolicy::count_failure) , row is locked so parallel failures are all counted
+     */
+    pub async fn record_failure(
+        &mut self,
+        scope: impl Into<String>,
+        key: impl Into<String>,
+        policy: &LockoutPolicy,
+    ) -> Result<FailureResult, HttpError> {
+        let new_throttle = NewAuthThrottle {
+            scope: scope.into(),
+            throttle_key: key.into(),
+        };
+
+        let policy = *policy;
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let result = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::insert_into(auth_throttles::table)
+                    .values(&new_throttle)
+                    .on_conflict((auth_throttles::scope, auth_throttles::throttle_key))
+                    .do_nothing()
+                    .execute(conn)?;
+
+                let throttle = auth_throttles::table
+                    .filter(auth_throttles::scope.eq(&new_throttle.scope))
+                    .filter(auth_throttles::throttle_key.eq(&new_throttle.throttle_key))
+                    .for_update()
+                    .first::<AuthThrottles>(conn)?;
+
+                let now = Utc::now();
+
+                let (failed_attempts, lockout_count, result) = policy.count_failure(
+                    throttle.failed_attempts,
+                    throttle.lockout_count,
+                    throttle.last_failed_at,
+                    now,
+                );
+
+                let locked_until = match result {
+                    FailureResult::Delayed(until) | FailureResult::LockedOut(until) => Some(until),
+                    FailureResult::Counted => throttle.locked_until,
+                };
+
+                diesel::update(auth_throttles::table.find(throttle.id))
+                    .set((
+                        auth_throttles::failed_attempts.eq(failed_attempts),
+                        auth_throttles::lockout_count.eq(lockout_count),
+                        auth_throttles::locked_until.eq(locked_until),
+                        auth_throttles::last_failed_at.eq(now),
+                    ))
+                    .execute(conn)?;
+
+                Ok(result)
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("error while saving failed attempt"))?;
+
+        Ok(result)
+    }
+
+    /**
+     * forgetting the failures , after a successful attempt
+     */
+    pub async fn clear(
+        &mut self,
+        scope: impl Into<String>,
+        key: impl Into<String>,
+    ) -> Result<bool, HttpError> {
+        let scope = scope.into();
+        let key = key.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(auth_throttles::table)
+                .filter(auth_throttles::scope.eq(scope))
+                .filter(auth_throttles::throttle_key.eq(key))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while clearing failed attempts"))?;
+
+        Ok(deleted > 0)
+    }
+
+    /**
+     * deleting rows with no failure since the given time and no running lockout
+     * @result => number of deleted rows
+     */
+    pub async fn prune_stale_throttles(
+        &mut self,
+        inactive_since: DateTime<Utc>,
+    ) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(auth_throttles::table)
+                .filter(auth_throttles::last_failed_at.lt(inactive_since))
+                .filter(
+                    auth_throttles::locked_until
+                        .is_null()
+                        .or(auth_throttles::locked_until.lt(Utc::now())),
+                )
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning throttles"))?;
+
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::header, response::IntoResponse};
+
+    use super::*;
+
+    // 3 failures free , then delays , lock at 5 for 60s doubling to max 300s
+    const POLICY: LockoutPolicy = LockoutPolicy {
+        delay_after_failures: 3,
+        max_failures: 5,
+        base_lockout_seconds: 60,
+        max_lockout_seconds: 300,
+        reset_after_seconds: 3600,
+    };
+
+    fn seconds_after(now: DateTime<Utc>, result: &FailureResult) -> i64 {
+        match result {
+            FailureResult::Delayed(until) | FailureResult::LockedOut(until) => {
+                (*until - now).num_seconds()
+            }
+            FailureResult::Counted => 0,
+        }
+    }
+
+    #[test]
+    fn failures_are_counted_then_delayed_with_doubling_waits() {
+        let now = Utc::now();
+
+        assert_eq!(
+            POLICY.count_failure(0, 0, now, now),
+            (1, 0, FailureResult::Counted)
+        );
+        assert_eq!(
+            POLICY.count_failure(1, 0, now, now),
+            (2, 0, FailureResult::Counted)
+        );
+
+        // 3rd failure waits 1s , 4th waits 2s
+        let (attempts, lockouts, result) = POLICY.count_failure(2, 0, now, now);
+        assert_eq!((attempts, lockouts), (3, 0));
+        assert!(matches!(result, FailureResult::Delayed(_)));
+        assert_eq!(seconds_after(now, &result), 1);
+
+        let (_, _, result) = POLICY.count_failure(3, 0, now, now);
+        assert!(matches!(result, FailureResult::Delayed(_)));
+        assert_eq!(seconds_after(now, &result), 2);
+    }
+
+    #[test]
+    fn max_failures_lock_out_and_every_lockout_doubles_till_the_max() {
+        let now = Utc::now();
+
+        // lockout resets the failures and counts the lockout
+        let (attempts, lockouts, result) = POLICY.count_failure(4, 0, now, now);
+        assert_eq!((attempts, lockouts), (0, 1));
+        assert!(matches!(result, FailureResult::LockedOut(_)));
+        assert_eq!(seconds_after(now, &result), 60);
+
+        let expected = [60, 120, 240, 300, 300];
+        for (lockouts_before, seconds) in expected.into_iter().enumerate() {
+            let (_, lockouts, result) = POLICY.count_failure(4, lockouts_before as i32, now, now);
+            assert_eq!(lockouts, lockouts_before as i32 + 1);
+            assert_eq!(seconds_after(now, &result), seconds);
+        }
+
+        // very many lockouts do not overflow the shift
+        let (_, _, result) = POLICY.count_failure(4, i32::MAX - 1, now, now);
+        assert_eq!(seconds_after(now, &result), 300);
+    }
+
+    #[test]
+    fn quiet_period_forgets_failures_and_lockouts() {
+        let now = Utc::now();
+        let long_ago = now - Duration::seconds(POLICY.reset_after_seconds + 1);
+
+        assert_eq!(
+            POLICY.count_failure(4, 3, long_ago, now),
+            (1, 0, FailureResult::Counted)
+        );
+    }
+
+    #[test]
+    fn locked_out_error_is_429_with_retry_after() {
+        let locked_until = Utc::now() + Duration::seconds(90);
+
+        let seconds = retry_after_seconds(locked_until);
+        assert!((89..=90).contains(&seconds));
+
+        // lockout which just ended still asks for at least 1 second
+        assert_eq!(retry_after_seconds(Utc::now() - Duration::seconds(5)), 1);
+
+        let response = HttpError::too_many_requests("locked", seconds).into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            response.headers()[header::RETRY_AFTER],
+            seconds.to_string().as_str()
+        );
+    }
+}
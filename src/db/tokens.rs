@@ -0,0 +1,394 @@
+// we will add all the db functions related to refresh tokens and revoked(logged out) access tokens here
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::{NewRefreshToken, NewRevokedToken, RefreshTokens};
+use crate::schema::{refresh_tokens, revoked_tokens, user_sessions, users};
+
+// manager which have db connection and all the refresh token functions
+pub struct TokenRepository {
+    pub db_con: DbPool,
+}
+
+// when a refresh token is presented , one of these things can happen
+pub enum RefreshTokenRotation {
+    // token was valid , it is now used and new token is saved in the same family , we return the old token row
+    Rotated(RefreshTokens),
+    // token was already used before , someone is replaying it , so whole family is revoked now
+    ReuseDetected,
+    // token not found , revoked or expired
+    Invalid,
+}
+
+// what rotation should do with the presented token , the transaction writes it
+#[derive(Debug, PartialEq)]
+enum RotationStep {
+    Rotate,
+    RevokeFamily,
+    Refuse,
+}
+
+/**
+ * deciding the rotation of the presented token row (None => not found)
+ * revoked or expired token is only refused , used token which is still valid means reuse
+ */
+fn rotation_step(token: Option<&RefreshTokens>, now: DateTime<Utc>) -> RotationStep {
+    match token {
+        None => RotationStep::Refuse,
+        Some(token) if token.revoked || token.expires_at < now => RotationStep::Refuse,
+        Some(token) if token.used => RotationStep::RevokeFamily,
+        Some(_) => RotationStep::Rotate,
+    }
+}
+
+impl TokenRepository {
+    pub fn new(con: DbPool) -> Self {
+        TokenRepository { db_con: con }
+    }
+
+    /**
+     * saving a new refresh token (its hash) in the given family
+     * on login we will create a new family id , on refresh we will reuse the family of old token
+     */
+    pub async fn save_refresh_token(
+        &mut self,
+        user_id: Uuid,
+        family_id: Uuid,
+        token_hash: impl Into<String>,
+        expiry: DateTime<Utc>,
+    ) -> Result<bool, HttpError> {
+        let mut con = self.db_con.get().map_err(|_| {
+            HttpError::new(
+                "error is connection pool".to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+        let new_refresh_token = NewRefreshToken {
+            user_id,
+            family_id,
+            token_hash: token_hash.into(),
+            expires_at: expiry,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            diesel::insert_into(refresh_tokens::table)
+                .values(&new_refresh_token)
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while saving refresh token"))?;
+
+        Ok(true)
+    }
+
+    /**
+     * in this function we will rotate the refresh token
+     * @inputs => hash of the incoming refresh token , hash of the new refresh token and its expiry
+     * @result => in one transaction we lock the old token row , check it , mark it used and save new token in same family
+     * if old token was already used , we revoke the whole family (this is committed , not rolled back)
+     */
+    pub async fn rotate_refresh_token(
+        &mut self,
+        old_token_hash: impl Into<String>,
+        new_token_hash: impl Into<String>,
+        new_expiry: DateTime<Utc>,
+    ) -> Result<RefreshTokenRotation, HttpError> {
+        let old_hash = old_token_hash.into();
+        let new_hash = new_token_hash.into();
+
+        let mut con = self.db_con.get().map_err(|_| {
+            HttpError::new(
+                "error is connection pool".to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+        let result = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| {
+                // locking the row , so two parallel refresh with same token cannot both pass
+                let existing = refresh_tokens::table
+                    .filter(refresh_tokens::token_hash.eq(&old_hash))
+                    .for_update()
+                    .first::<RefreshTokens>(conn)
+                    .optional()?;
+
+                let token = match (rotation_step(existing.as_ref(), Utc::now()), existing) {
+                    (RotationStep::Rotate, Some(token)) => token,
+                    // already used token is presented again , revoke every token of this family and its session
+                    (RotationStep::RevokeFamily, Some(token)) => {
+                        diesel::update(refresh_tokens::table)
+                            .filter(refresh_tokens::family_id.eq(token.family_id))
+                            .set(refresh_tokens::revoked.eq(true))
+                            .execute(conn)?;
+
+                        diesel::update(user_sessions::table.find(token.family_id))
+                            .set(user_sessions::revoked_at.eq(Some(Utc::now())))
+                            .execute(conn)?;
+
+                        return Ok(RefreshTokenRotation::ReuseDetected);
+                    }
+                    _ => return Ok(RefreshTokenRotation::Invalid),
+                };
+
+                diesel::update(refresh_tokens::table.find(token.id))
+                    .set(refresh_tokens::used.eq(true))
+                    .execute(conn)?;
+
+                diesel::insert_into(refresh_tokens::table)
+                    .values(&NewRefreshToken {
+                        user_id: token.user_id,
+                        family_id: token.family_id,
+                        token_hash: new_hash,
+                        expires_at: new_expiry,
+                    })
+                    .execute(conn)?;
+
+                Ok(RefreshTokenRotation::Rotated(token))
+            })
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("db transaction failed"))?;
+
+        Ok(result)
+    }
+
+    /**
+     * adding access token jti to the denylist , till its expiry
+     * if it is already revoked , we do nothing
+     */
+    pub async fn revoke_access_token(
+        &mut self,
+        jti: Uuid,
+        user_id: Uuid,
+        expiry: DateTime<Utc>,
+    ) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let revoked_token = NewRevokedToken {
+            jti,
+            user_id,
+            expires_at: expiry,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            diesel::insert_into(revoked_tokens::table)
+                .values(&revoked_token)
+                .on_conflict(revoked_tokens::jti)
+                .do_nothing()
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while revoking the token"))?;
+
+        Ok(true)
+    }
+
+    /**
+     * checking if the access token (jti) is in the denylist
+     */
+    pub async fn is_access_token_revoked(&mut self, jti: Uuid) -> Result<bool, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let revoked = tokio::task::spawn_blocking(move || {
+            diesel::select(diesel::dsl::exists(revoked_tokens::table.find(jti)))
+                .get_result::<bool>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while checking revoked tokens"))?;
+
+        Ok(revoked)
+    }
+
+    /**
+     * revoking the family of the given refresh token , only if that token belongs to this user
+     * used on logout , so the refresh token of this device stops working also
+     */
+    pub async fn revoke_refresh_token_family(
+        &mut self,
+        user_id: Uuid,
+        token_hash: impl Into<String>,
+    ) -> Result<bool, HttpError> {
+        let hash = token_hash.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            let family_id = refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(&hash))
+                .filter(refresh_tokens::user_id.eq(user_id))
+                .select(refresh_tokens::family_id)
+                .first::<Uuid>(&mut con)
+                .optional()?;
+
+            // unknown refresh token , nothing to revoke
+            let Some(family_id) = family_id else {
+                return Ok(0);
+            };
+
+            diesel::update(refresh_tokens::table)
+                .filter(refresh_tokens::family_id.eq(family_id))
+                .set(refresh_tokens::revoked.eq(true))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while revoking refresh tokens"))?;
+
+        Ok(true)
+    }
+
+    /**
+     * revoking every token of the user (logout from all devices)
+     * token_version is bumped , so every access token already given out stops working at once
+     * and every session and refresh token is revoked , so no new access token can be made from them
+     * @result => new token_version of the user
+     */
+    pub async fn revoke_all_user_tokens(&mut self, user_id: Uuid) -> Result<i32, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let new_version = tokio::task::spawn_blocking(move || {
+            con.transaction::<_, diesel::result::Error, _>(|conn| revoke_user_tokens(conn, user_id))
+        })
+        .await
+        .map_err(|_| HttpError::server_error("thread panicked"))?
+        .map_err(|_| HttpError::server_error("error while revoking user tokens"))?;
+
+        Ok(new_version)
+    }
+
+    /**
+     * deleting denylist rows and refresh tokens which are already expired
+     * expired jwt is rejected by decode anyway , so keeping them is of no use
+     * @result => number of deleted rows
+     */
+    pub async fn prune_expired_tokens(&mut self) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            let now = Utc::now();
+
+            let revoked = diesel::delete(revoked_tokens::table)
+                .filter(revoked_tokens::expires_at.lt(now))
+                .execute(&mut con)?;
+
+            let refresh = diesel::delete(refresh_tokens::table)
+                .filter(refresh_tokens::expires_at.lt(now))
+                .execute(&mut con)?;
+
+            Ok::<usize, diesel::result::Error>(revoked + refresh)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning expired tokens"))?;
+
+        Ok(deleted)
+    }
+}
+
+/**
+ * bumping token_version and revoking every session and refresh token of the user
+ * plain diesel function , so it can run inside the transaction of the caller (password update , logout all)
+ * @result => new token_version of the user
+ */
+pub fn revoke_user_tokens(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<i32> {
+    let new_version = diesel::update(users::table.find(user_id))
+        .set(users::token_version.eq(users::token_version + 1))
+        .returning(users::token_version)
+        .get_result::<i32>(conn)?;
+
+    diesel::update(refresh_tokens::table)
+        .filter(refresh_tokens::user_id.eq(user_id))
+        .filter(refresh_tokens::revoked.eq(false))
+        .set(refresh_tokens::revoked.eq(true))
+        .execute(conn)?;
+
+    diesel::update(user_sessions::table)
+        .filter(user_sessions::user_id.eq(user_id))
+        .filter(user_sessions::revoked_at.is_null())
+        .set(user_sessions::revoked_at.eq(Some(Utc::now())))
+        .execute(conn)?;
+
+    Ok(new_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn token(used: bool, revoked: bool, expires_in_minutes: i64) -> RefreshTokens {
+        RefreshTokens {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            family_id: Uuid::new_v4(),
+            token_hash: "hash".to_string(),
+            used,
+            revoked,
+            expires_at: Utc::now() + Duration::minutes(expires_in_minutes),
+            created_at: Some(Utc::now()),
+        }
+    }
+
+    #[test]
+    fn fresh_token_is_rotated() {
+        assert_eq!(
+            rotation_step(Some(&token(false, false, 60)), Utc::now()),
+            RotationStep::Rotate
+        );
+    }
+
+    #[test]
+    fn used_token_presented_again_revokes_the_family() {
+        assert_eq!(
+            rotation_step(Some(&token(true, false, 60)), Utc::now()),
+            RotationStep::RevokeFamily
+        );
+    }
+
+    #[test]
+    fn unknown_revoked_or_expired_token_is_refused() {
+        let now = Utc::now();
+
+        assert_eq!(rotation_step(None, now), RotationStep::Refuse);
+        assert_eq!(
+            rotation_step(Some(&token(false, true, 60)), now),
+            RotationStep::Refuse
+        );
+        assert_eq!(
+            rotation_step(Some(&token(false, false, -1)), now),
+            RotationStep::Refuse
+        );
+
+        // family is already revoked (reuse was caught before) , nothing more to revoke
+        assert_eq!(
+            rotation_step(Some(&token(true, true, 60)), now),
+            RotationStep::Refuse
+        );
+    }
+}
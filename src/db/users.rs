@@ -1,28 +1,87 @@
 // we will add queries/struct/function to inteact with the user tables
 // which can be called via handlers , so it is same as .servide file
 
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::http::StatusCode;
 use chrono::*;
-use diesel::{
-    prelude::*,
-    r2d2::{ConnectionManager, Pool},
-};
+use diesel::{pg::Pg, prelude::*, result::Error};
 use uuid::Uuid;
 
 use crate::{
     DbPool,
     dtos::note_dto::NoteDTO,
     errors::HttpError,
-    models::{NewUser, NewUserNote, UserNotes, Users},
+    models::{NewUser, NewUserNote, UserNotes, UserRole, Users},
     schema::user_notes,
 };
 
-use crate::schema::{user_email_verifications, users};
+use crate::db::auth::{recent_password_hashes, set_user_password};
+use crate::db::tokens::revoke_user_tokens;
+use crate::schema::{one_time_codes, users};
+
+// users saved by one insert query while importing (postgres allows 65535 bind params per query)
+const IMPORT_BATCH_SIZE: usize = 1000;
 
 pub struct UserRepository {
     pub db_con: DbPool,
 }
 
+// filters of the admin users list , None means not filtered on that field
+#[derive(Debug, Clone, Default)]
+pub struct UserListFilter {
+    pub verified: Option<bool>,
+    pub role: Option<UserRole>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub search: Option<String>, // part of name or email , case insensitive
+}
+
+impl UserListFilter {
+    /**
+     * users query with the filters applied , used for both the page and the total count
+     */
+    fn query(&self) -> users::BoxedQuery<'static, Pg> {
+        let mut query = users::table.into_boxed();
+
+        if let Some(verified) = self.verified {
+            query = query.filter(users::verified.eq(verified));
+        }
+
+        if let Some(role) = self.role.clone() {
+            query = query.filter(users::role.eq(role));
+        }
+
+        if let Some(created_after) = self.created_after {
+            query = query.filter(users::created_at.ge(created_after));
+        }
+
+        if let Some(created_before) = self.created_before {
+            query = query.filter(users::created_at.lt(created_before));
+        }
+
+        if let Some(search) = self
+            .search
+            .as_deref()
+            .map(str::trim)
+            .filter(|search| !search.is_empty())
+        {
+            // % and _ typed by the admin are searched as they are , not as wildcards
+            let escaped = search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            let pattern = format!("%{}%", escaped);
+
+            query = query.filter(
+                users::name
+                    .ilike(pattern.clone())
+                    .or(users::email.ilike(pattern)),
+            );
+        }
+
+        query
+    }
+}
+
 // implementing user related functions
 impl UserRepository {
     // function to give db pool access to this repository/manager
@@ -32,17 +91,39 @@ impl UserRepository {
         }
     }
 
+    /**
+     * hashes of the current and last older passwords of the user (newest first) , for the password history check
+     */
+    pub async fn get_recent_password_hashes(
+        &mut self,
+        user: &Users,
+        limit: usize,
+    ) -> Result<Vec<String>, HttpError> {
+        let mut conn = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let user = user.clone();
+
+        tokio::task::spawn_blocking(move || recent_password_hashes(&mut conn, &user, limit))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .map_err(|_| HttpError::server_error("error while fetching password history"))
+    }
+
     /**
      * In this service we will update the password of the loggedn in user
-     *  @inputs => we will get id of the user and new password
-     * @we will update the password and return tru
+     *  @inputs => we will get id of the user , new password (hashed) and how many old passwords are kept
+     * @we will update the password (old one goes to history) , bump token_version , revoke all sessions and refresh tokens and return new token_version
      */
     pub async fn update_loggedIn_user_pass(
         &mut self,
         user_id: Uuid,
         new_pass: impl Into<String>,
-    ) -> Result<bool, HttpError> {
-        let mut conn = self.db_con.get().map_err(|e| {
+        history_size: usize,
+    ) -> Result<i32, HttpError> {
+        let mut conn = self.db_con.get().map_err(|_| {
             HttpError::new(
                 "unable to to db connection".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -51,22 +132,111 @@ impl UserRepository {
 
         let user_pass = new_pass.into();
 
-        let result = tokio::task::spawn_blocking(move || {
-            diesel::update(users::table)
-                .filter(users::id.eq(user_id.clone()))
-                .set(users::password.eq(user_pass))
-                .execute(&mut conn)
+        let new_version = tokio::task::spawn_blocking(move || {
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                // sessions and tokens of every device are revoked , caller gets a new session
+                set_user_password(conn, user_id, &user_pass, history_size)
+            })
         })
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?
-        .map_err(|e| {
+        .map_err(|_| {
             HttpError::new(
                 "error while updating the user password".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
             )
         })?;
 
-        Ok(true)
+        Ok(new_version)
+    }
+
+    /**
+     * changing the email of the logged in user , new email is not verified yet so login needs its otp first
+     * every session and refresh token is revoked and codes of the old email stop working
+     * @result => updated user , 409 if another account has the new email
+     */
+    pub async fn change_user_email(
+        &mut self,
+        user_id: Uuid,
+        new_email: impl Into<String>,
+    ) -> Result<Users, HttpError> {
+        let mut conn = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let new_email = new_email.into();
+
+        tokio::task::spawn_blocking(move || {
+            conn.transaction::<_, Error, _>(|conn| {
+                let old_email = users::table
+                    .find(user_id)
+                    .select(users::email)
+                    .first::<String>(conn)?;
+
+                let user = diesel::update(users::table.find(user_id))
+                    .set((
+                        users::email.eq(&new_email),
+                        users::verified.eq(false),
+                        users::updated_at.eq(Some(Utc::now())),
+                    ))
+                    .returning(Users::as_returning())
+                    .get_result(conn)?;
+
+                revoke_user_tokens(conn, user_id)?;
+
+                diesel::delete(
+                    one_time_codes::table.filter(one_time_codes::user_email.eq(old_email)),
+                )
+                .execute(conn)?;
+
+                Ok(user)
+            })
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|e| match e {
+            Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => {
+                HttpError::new(
+                    "email is already used by another account",
+                    StatusCode::CONFLICT,
+                )
+            }
+            Error::NotFound => HttpError::new("user not found", StatusCode::NOT_FOUND),
+            _ => HttpError::server_error("error while changing email"),
+        })
+    }
+
+    /**
+     * bulk saving users brought from the old system , with their old password hashes
+     * @inputs => users with already checked email , name and hash
+     * @result => how many users are saved , users whose email already exists are skipped (not updated)
+     */
+    pub async fn import_users(&mut self, new_users: Vec<NewUser>) -> Result<usize, HttpError> {
+        let mut conn = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        // one transaction , a failed import leaves no half imported users behind
+        tokio::task::spawn_blocking(move || {
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                let mut saved = 0;
+
+                for batch in new_users.chunks(IMPORT_BATCH_SIZE) {
+                    saved += diesel::insert_into(users::table)
+                        .values(batch)
+                        .on_conflict(users::email)
+                        .do_nothing()
+                        .execute(conn)?;
+                }
+
+                Ok(saved)
+            })
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|e| HttpError::server_error(format!("error while importing users {}", e)))
     }
 
     /**
@@ -87,9 +257,9 @@ impl UserRepository {
             .map_err(|e| HttpError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
 
         let new_note = NewUserNote {
-            user_id: user_id.clone(),
-            title: title,
-            content: content,
+            user_id,
+            title,
+            content,
         };
 
         // saving user notes
@@ -101,7 +271,7 @@ impl UserRepository {
         })
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?
-        .map_err(|e| {
+        .map_err(|_| {
             HttpError::new(
                 "getting error in saving user notes".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -132,7 +302,7 @@ impl UserRepository {
             .map_err(|e| HttpError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
 
         // updating user note user notes
-        let result = tokio::task::spawn_blocking(move || {
+        tokio::task::spawn_blocking(move || {
             diesel::update(user_notes::table)
                 .filter(user_notes::id.eq(note_id))
                 .set((user_notes::title.eq(title), user_notes::content.eq(content)))
@@ -140,7 +310,7 @@ impl UserRepository {
         })
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?
-        .map_err(|e| {
+        .map_err(|_| {
             HttpError::new(
                 "getting error in updating user notes".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -163,12 +333,12 @@ impl UserRepository {
             .map_err(|e| HttpError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
 
         // deleting user note
-        let result = tokio::task::spawn_blocking(move || {
+        tokio::task::spawn_blocking(move || {
             diesel::delete(user_notes::table.find(note_id)).execute(&mut con)
         })
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?
-        .map_err(|e| {
+        .map_err(|_| {
             HttpError::new(
                 "getting error in deleting the user note".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -183,11 +353,7 @@ impl UserRepository {
      * @input => we will get page number , user id . we will have offset(which 5 set , 1st 5 or 2nd five or 3rd five , etc)
      * @return => we will return nuser notes in 5 sets of that specific offset or page
      */
-    pub async fn get_user_notes(
-        &mut self,
-        user_id: Uuid,
-    ) -> Result<Vec<UserNotes>, HttpError> {
-
+    pub async fn get_user_notes(&mut self, user_id: Uuid) -> Result<Vec<UserNotes>, HttpError> {
         let mut con = self
             .db_con
             .get()
@@ -205,4 +371,175 @@ impl UserRepository {
 
         Ok(user_notes)
     }
+
+    /**
+     * one page of users for the admin users list , newest first
+     * @result => users of the page and how many users match the filters in total
+     */
+    pub async fn list_users(
+        &mut self,
+        filter: UserListFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Users>, i64), HttpError> {
+        let mut conn = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            let total = filter.query().count().get_result::<i64>(&mut conn)?;
+
+            let page = filter
+                .query()
+                .order_by((users::created_at.desc(), users::id))
+                .limit(limit)
+                .offset(offset)
+                .select(Users::as_select())
+                .load(&mut conn)?;
+
+            Ok::<_, Error>((page, total))
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching users"))
+    }
+
+    /**
+     * marking the email of the user as verified without the otp (admin)
+     */
+    pub async fn force_verify_user(&mut self, user_id: Uuid) -> Result<Users, HttpError> {
+        self.update_user(user_id, "error while verifying user", move |conn| {
+            diesel::update(users::table.find(user_id))
+                .set((
+                    users::verified.eq(true),
+                    users::updated_at.eq(Some(Utc::now())),
+                ))
+                .execute(conn)
+        })
+        .await
+    }
+
+    /**
+     * changing user/admin role , tokens carry the role claim so every session of the user is revoked
+     */
+    pub async fn change_user_role(
+        &mut self,
+        user_id: Uuid,
+        role: UserRole,
+    ) -> Result<Users, HttpError> {
+        self.update_user(user_id, "error while changing user role", move |conn| {
+            let updated = diesel::update(users::table.find(user_id))
+                .set((users::role.eq(role), users::updated_at.eq(Some(Utc::now()))))
+                .execute(conn)?;
+
+            if updated > 0 {
+                revoke_user_tokens(conn, user_id)?;
+            }
+
+            Ok(updated)
+        })
+        .await
+    }
+
+    /**
+     * suspending (every session is revoked , login is refused) or unsuspending the user
+     * suspending an already suspended user keeps the first suspend time
+     */
+    pub async fn set_user_suspended(
+        &mut self,
+        user_id: Uuid,
+        suspended: bool,
+    ) -> Result<Users, HttpError> {
+        self.update_user(user_id, "error while suspending user", move |conn| {
+            if !suspended {
+                return diesel::update(users::table.find(user_id))
+                    .set(users::suspended_at.eq(None::<DateTime<Utc>>))
+                    .execute(conn);
+            }
+
+            let found = users::table.find(user_id).count().get_result::<i64>(conn)?;
+
+            diesel::update(users::table.find(user_id))
+                .filter(users::suspended_at.is_null())
+                .set(users::suspended_at.eq(Some(Utc::now())))
+                .execute(conn)?;
+
+            if found > 0 {
+                revoke_user_tokens(conn, user_id)?;
+            }
+
+            Ok(found as usize)
+        })
+        .await
+    }
+
+    /**
+     * deleting the user for good , his notes , sessions , tokens , mfa , passkeys and roles go with him (cascade)
+     * codes of his email are not linked to the user row , so they are deleted here
+     * @result => false if there is no such user
+     */
+    pub async fn delete_user(&mut self, user_id: Uuid) -> Result<bool, HttpError> {
+        let mut conn = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            conn.transaction::<_, Error, _>(|conn| {
+                let Some(email) = diesel::delete(users::table.find(user_id))
+                    .returning(users::email)
+                    .get_result::<String>(conn)
+                    .optional()?
+                else {
+                    return Ok(false);
+                };
+
+                diesel::delete(one_time_codes::table.filter(one_time_codes::user_email.eq(email)))
+                    .execute(conn)?;
+
+                Ok(true)
+            })
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while deleting user"))
+    }
+
+    /**
+     * running an update of one user in a transaction and giving back the updated user
+     * @inputs => update which returns how many users it changed (0 => user not found) , error message for db errors
+     */
+    async fn update_user<F>(
+        &mut self,
+        user_id: Uuid,
+        error_message: &'static str,
+        update: F,
+    ) -> Result<Users, HttpError>
+    where
+        F: FnOnce(&mut PgConnection) -> QueryResult<usize> + Send + 'static,
+    {
+        let mut conn = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            conn.transaction::<_, Error, _>(|conn| {
+                if update(conn)? == 0 {
+                    return Ok(None);
+                }
+
+                users::table
+                    .find(user_id)
+                    .select(Users::as_select())
+                    .get_result(conn)
+                    .map(Some)
+            })
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error(error_message))?
+        .ok_or_else(|| HttpError::new("user not found", StatusCode::NOT_FOUND))
+    }
 }
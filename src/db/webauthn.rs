@@ -0,0 +1,199 @@
+// we will add all the db functions related to passkeys (webauthn credentials and ceremonies) here
+
+use axum::http::StatusCode;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use uuid::Uuid;
+
+use crate::DbPool;
+use crate::errors::HttpError;
+use crate::models::{
+    NewWebauthnCeremony, NewWebauthnCredential, WebauthnCeremonies, WebauthnCeremonyKind,
+    WebauthnCredentials,
+};
+use crate::schema::{webauthn_ceremonies, webauthn_credentials};
+
+// manager which have db connection and all the passkey functions
+pub struct WebauthnRepository {
+    pub db_con: DbPool,
+}
+
+impl WebauthnRepository {
+    pub fn new(con: DbPool) -> Self {
+        WebauthnRepository { db_con: con }
+    }
+
+    /**
+     * saving state of a started registration/login
+     * @result => id of the ceremony , client sends it back with finish req
+     */
+    pub async fn save_ceremony(
+        &mut self,
+        new_ceremony: NewWebauthnCeremony,
+    ) -> Result<Uuid, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let ceremony_id = tokio::task::spawn_blocking(move || {
+            diesel::insert_into(webauthn_ceremonies::table)
+                .values(&new_ceremony)
+                .returning(webauthn_ceremonies::id)
+                .get_result::<Uuid>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while saving webauthn ceremony"))?;
+
+        Ok(ceremony_id)
+    }
+
+    /**
+     * taking (deleting and returning) a not expired ceremony of given type
+     * deleting it means same challenge cannot be finished twice
+     * @inputs => ceremony id , its type and the user id if we know the user (registration)
+     */
+    pub async fn take_ceremony(
+        &mut self,
+        ceremony_id: Uuid,
+        ceremony_type: WebauthnCeremonyKind,
+        user_id: Option<Uuid>,
+    ) -> Result<Option<WebauthnCeremonies>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let ceremony = tokio::task::spawn_blocking(move || {
+            let mut query = diesel::delete(webauthn_ceremonies::table)
+                .filter(webauthn_ceremonies::id.eq(ceremony_id))
+                .filter(webauthn_ceremonies::ceremony_type.eq(ceremony_type))
+                .filter(webauthn_ceremonies::expires_at.gt(Utc::now()))
+                .into_boxed();
+
+            if let Some(user_id) = user_id {
+                query = query.filter(webauthn_ceremonies::user_id.eq(user_id));
+            }
+
+            query
+                .returning(WebauthnCeremonies::as_returning())
+                .get_result(&mut con)
+                .optional()
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching webauthn ceremony"))?;
+
+        Ok(ceremony)
+    }
+
+    /**
+     * all passkeys of the user
+     */
+    pub async fn get_user_credentials(
+        &mut self,
+        user_id: Uuid,
+    ) -> Result<Vec<WebauthnCredentials>, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let credentials = tokio::task::spawn_blocking(move || {
+            webauthn_credentials::table
+                .filter(webauthn_credentials::user_id.eq(user_id))
+                .order_by(webauthn_credentials::created_at.asc())
+                .load::<WebauthnCredentials>(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while fetching passkeys"))?;
+
+        Ok(credentials)
+    }
+
+    /**
+     * saving a newly registered passkey
+     * same authenticator credential cannot be saved twice
+     */
+    pub async fn save_credential(
+        &mut self,
+        new_credential: NewWebauthnCredential,
+    ) -> Result<WebauthnCredentials, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let credential = tokio::task::spawn_blocking(move || {
+            diesel::insert_into(webauthn_credentials::table)
+                .values(&new_credential)
+                .returning(WebauthnCredentials::as_returning())
+                .get_result(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|e| match e {
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                HttpError::new("passkey is already registered", StatusCode::CONFLICT)
+            }
+            _ => HttpError::server_error("error while saving passkey"),
+        })?;
+
+        Ok(credential)
+    }
+
+    /**
+     * saving updated passkey (sign counter , backup state) after a login with it
+     */
+    pub async fn update_credential_after_login(
+        &mut self,
+        credential_id: Uuid,
+        passkey: impl Into<String>,
+    ) -> Result<bool, HttpError> {
+        let passkey = passkey.into();
+
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        tokio::task::spawn_blocking(move || {
+            diesel::update(webauthn_credentials::table.find(credential_id))
+                .set((
+                    webauthn_credentials::passkey.eq(passkey),
+                    webauthn_credentials::last_used_at.eq(Some(Utc::now())),
+                ))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while updating passkey"))?;
+
+        Ok(true)
+    }
+
+    /**
+     * deleting ceremonies which were started but never finished
+     * @result => number of deleted rows
+     */
+    pub async fn prune_expired_ceremonies(&mut self) -> Result<usize, HttpError> {
+        let mut con = self
+            .db_con
+            .get()
+            .map_err(|_| HttpError::server_error("error in getting db ppol"))?;
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            diesel::delete(webauthn_ceremonies::table)
+                .filter(webauthn_ceremonies::expires_at.lt(Utc::now()))
+                .execute(&mut con)
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .map_err(|_| HttpError::server_error("error while pruning webauthn ceremonies"))?;
+
+        Ok(deleted)
+    }
+}
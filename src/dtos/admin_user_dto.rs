@@ -0,0 +1,93 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{UserRole, Users};
+
+// query of the admin users list , eg ?page=2&per_page=50&verified=true&role=admin&search=john
+#[derive(Validate, Deserialize, Debug, Clone)]
+pub struct AdminUsersQueryDTO {
+    #[validate(range(min = 1, message = "page starts from 1"))]
+    pub page: Option<i64>,
+
+    #[validate(range(min = 1, max = 100, message = "per_page should be 1 to 100"))]
+    pub per_page: Option<i64>,
+
+    pub verified: Option<bool>,
+
+    pub role: Option<String>, // user or admin
+
+    pub created_after: Option<DateTime<Utc>>, // rfc3339 , eg 2026-01-01T00:00:00Z
+
+    pub created_before: Option<DateTime<Utc>>,
+
+    #[validate(length(max = 100, message = "search should be at most 100 chars"))]
+    pub search: Option<String>,
+}
+
+#[derive(Validate, Deserialize, Debug, Clone)]
+pub struct ChangeRoleDTO {
+    #[validate(length(min = 1, message = "role is required"))]
+    pub role: String, // user or admin
+}
+
+// user as admins see him , password hash and token version are never sent
+#[derive(Serialize, Debug)]
+pub struct AdminUserDTO {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub verified: bool,
+    pub role: UserRole,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+}
+
+impl From<Users> for AdminUserDTO {
+    fn from(user: Users) -> Self {
+        AdminUserDTO {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            verified: user.verified,
+            role: user.role,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            suspended_at: user.suspended_at,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct AdminUsersResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub users: Vec<AdminUserDTO>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64, // users matching the filters , on all pages
+}
+
+impl IntoResponse for AdminUsersResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct AdminUserResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub user: AdminUserDTO,
+}
+
+impl IntoResponse for AdminUserResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
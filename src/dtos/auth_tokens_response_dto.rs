@@ -0,0 +1,21 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+
+// response which we send whenever user gets new tokens (verify email , login , refresh , reset password)
+#[derive(Serialize, Debug, Clone)]
+pub struct AuthTokensResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub user_id: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64, // access token life in seconds
+}
+
+impl IntoResponse for AuthTokensResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct ChangeEmailDTO {
+    // otp to verify it is sent on this email
+    #[validate(email)]
+    pub new_email: String,
+}
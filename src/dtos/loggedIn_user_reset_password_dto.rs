@@ -3,9 +3,10 @@ use validator::Validate;
 
 #[derive(Validate, Serialize, Deserialize, Clone)]
 pub struct LoggedInUserResetPasswordDTO {
-    #[validate(length(min = 6, message = "old password min length should be greater that 6"))]
+    // older (or imported) passwords may not follow the current rules , so only presence is checked
+    #[validate(length(min = 1, message = "old password is required"))]
     pub old_password: String,
 
-    #[validate(length(min = 6, message = "new password min length should be greater that 6"))]
+    // rules are checked by the password policy (config)
     pub new_password: String,
 }
@@ -8,4 +8,8 @@ pub struct loggedInUser {
 
     #[validate(length(min = 6, message = "passowrd length should be min 6 chars"))]
     pub password: String,
+
+    // name of the device , shown in the sessions list (e.g. "Pixel 8")
+    #[validate(length(max = 100, message = "device name length should be max 100 chars"))]
+    pub device_name: Option<String>,
 }
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct LogoutDTO {
+    // if given , refresh tokens of this login are also revoked
+    #[validate(length(min = 1, message = "refresh token cannot be empty"))]
+    pub refresh_token: Option<String>,
+}
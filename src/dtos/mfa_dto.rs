@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+// code from the authenticator app , used to confirm totp enrollment
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct TotpCodeDTO {
+    #[validate(length(equal = 6, message = "totp code should be 6 digits"))]
+    pub code: String,
+}
+
+// turning totp off , one of totp code or recovery code
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct MfaCodeDTO {
+    #[validate(length(equal = 6, message = "totp code should be 6 digits"))]
+    pub code: Option<String>,
+
+    #[validate(length(min = 1, max = 20, message = "recovery code is not valid"))]
+    pub recovery_code: Option<String>,
+}
+
+// second step of login , challenge token from login and one of totp code or recovery code
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct MfaLoginDTO {
+    #[validate(length(min = 1, message = "mfa token is required"))]
+    pub mfa_token: String,
+
+    #[validate(length(equal = 6, message = "totp code should be 6 digits"))]
+    pub code: Option<String>,
+
+    #[validate(length(min = 1, max = 20, message = "recovery code is not valid"))]
+    pub recovery_code: Option<String>,
+}
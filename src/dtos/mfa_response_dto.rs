@@ -0,0 +1,50 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+
+// totp secret and otpauth uri (for qr code) , totp is enabled only after confirm
+#[derive(Serialize, Debug)]
+pub struct TotpEnrollResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+impl IntoResponse for TotpEnrollResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
+
+// recovery codes are shown only once , we only keep their hashes
+#[derive(Serialize, Debug)]
+pub struct RecoveryCodesResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub recovery_codes: Vec<String>,
+}
+
+impl IntoResponse for RecoveryCodesResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
+
+// login response when password was correct but second factor is still needed
+#[derive(Serialize, Debug)]
+pub struct MfaChallengeResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64, // challenge life in seconds
+}
+
+impl IntoResponse for MfaChallengeResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
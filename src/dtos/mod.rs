@@ -1,10 +1,23 @@
-pub mod register_dto;
-pub mod verify_email_dto;
+pub mod admin_user_dto;
+pub mod auth_tokens_response_dto;
+pub mod change_email_dto;
+pub mod loggedIn_user_reset_password_dto;
 pub mod login_dto;
-pub mod send_otp;
+pub mod logout_dto;
+pub mod mfa_dto;
+pub mod mfa_response_dto;
 pub mod non_logged_in_user_reset_password_dto;
-pub mod loggedIn_user_reset_password_dto;
 pub mod note_dto;
+pub mod passwordless_login_dto;
+pub mod reauthenticate_dto;
+pub mod refresh_token_dto;
+pub mod register_dto;
+pub mod role_dto;
+pub mod send_otp;
+pub mod user_dto;
 pub mod user_notes_vec_response_dto;
 pub mod user_ok_response_dto;
-pub mod user_dto;
\ No newline at end of file
+pub mod user_sessions_response_dto;
+pub mod verify_email_dto;
+pub mod webauthn_dto;
+pub mod webauthn_response_dto;
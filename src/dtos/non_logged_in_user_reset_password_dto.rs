@@ -1,16 +1,14 @@
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-#[derive(Validate , Serialize  , Deserialize , Clone)]
-pub struct NonLoggedInUserResetPasswordDTO{
-
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct NonLoggedInUserResetPasswordDTO {
     #[validate(email)]
-    pub user_email : String,
-
-
-    pub reset_token : String,
+    pub user_email: String,
 
-    #[validate(length(min = 6 , message = "password length should be greater than 6"))]
-    pub new_password : String
+    #[validate(length(min = 1, message = "reset token is required"))]
+    pub reset_token: String,
 
-}
\ No newline at end of file
+    // rules are checked by the password policy (config) , same as register
+    pub new_password: String,
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+// login with the code sent on email
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct EmailCodeLoginDTO {
+    #[validate(email)]
+    pub user_email: String,
+
+    #[validate(length(equal = 6, message = "otp should be 6 digits"))]
+    pub otp: String,
+
+    #[validate(length(max = 100, message = "device name length should be max 100 chars"))]
+    pub device_name: Option<String>,
+}
+
+// login with the token from the magic link
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct MagicLinkLoginDTO {
+    #[validate(length(min = 1, message = "token is required"))]
+    pub token: String,
+
+    #[validate(length(max = 100, message = "device name length should be max 100 chars"))]
+    pub device_name: Option<String>,
+}
@@ -0,0 +1,31 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+// logged in user proving again who he is , one of password or totp code
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct ReauthenticateDTO {
+    #[validate(length(min = 1, message = "password is required"))]
+    pub password: Option<String>,
+
+    #[validate(length(equal = 6, message = "totp code should be 6 digits"))]
+    pub code: Option<String>,
+}
+
+// new access token of the same session with fresh auth_time , refresh token does not change
+#[derive(Serialize, Debug)]
+pub struct ReauthenticateResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64, // access token life in seconds
+    pub auth_time: i64,
+}
+
+impl IntoResponse for ReauthenticateResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
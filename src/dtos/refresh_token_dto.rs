@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct RefreshTokenDTO {
+    #[validate(length(min = 1, message = "refresh token is required"))]
+    pub refresh_token: String,
+}
@@ -0,0 +1,69 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{Permissions, Roles};
+
+// new custom role , permissions are given by their names (eg notes:read:any)
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct CreateRoleDTO {
+    #[validate(length(min = 1, max = 50, message = "role name should be 1 to 50 chars"))]
+    pub name: String,
+
+    #[validate(length(max = 255, message = "description should be at most 255 chars"))]
+    pub description: Option<String>,
+
+    #[validate(length(min = 1, message = "role needs at least one permission"))]
+    pub permissions: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RoleDTO {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RoleDTO {
+    pub fn from_role(role: Roles, permissions: Vec<String>) -> Self {
+        RoleDTO {
+            id: role.id,
+            name: role.name,
+            description: role.description,
+            permissions,
+            created_at: role.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RolesResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub roles: Vec<RoleDTO>,
+}
+
+impl IntoResponse for RolesResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PermissionsResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub permissions: Vec<Permissions>,
+}
+
+impl IntoResponse for PermissionsResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
@@ -0,0 +1,29 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// one login session (device) of the user
+#[derive(Serialize, Debug, Clone)]
+pub struct UserSessionDTO {
+    pub id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub current: bool, // true for the session which made this req
+}
+
+#[derive(Serialize, Debug)]
+pub struct UserSessionsResponseDTO {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub sessions: Vec<UserSessionDTO>,
+}
+
+impl IntoResponse for UserSessionsResponseDTO {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
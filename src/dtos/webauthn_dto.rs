@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential};
+
+// answer of the authenticator for passkey registration
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct WebauthnRegisterFinishDTO {
+    pub ceremony_id: Uuid,
+    pub credential: RegisterPublicKeyCredential,
+
+    // name of the passkey shown to the user (e.g. "work laptop")
+    #[validate(length(max = 100, message = "passkey name length should be max 100 chars"))]
+    pub name: Option<String>,
+}
+
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct WebauthnLoginOptionsDTO {
+    #[validate(email)]
+    pub email: String,
+}
+
+// answer of the authenticator for passkey login
+#[derive(Validate, Serialize, Deserialize, Clone)]
+pub struct WebauthnLoginFinishDTO {
+    pub ceremony_id: Uuid,
+    pub credential: PublicKeyCredential,
+
+    #[validate(length(max = 100, message = "device name length should be max 100 chars"))]
+    pub device_name: Option<String>,
+}
@@ -0,0 +1,18 @@
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+
+// options for navigator.credentials.create()/get() , client sends ceremony id back with the finish req
+#[derive(Serialize, Debug)]
+pub struct WebauthnOptionsResponseDTO<T: Serialize> {
+    #[serde(with = "http_serde::status_code")]
+    pub status: StatusCode,
+    pub message: String,
+    pub ceremony_id: String,
+    pub options: T,
+}
+
+impl<T: Serialize> IntoResponse for WebauthnOptionsResponseDTO<T> {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
@@ -1,11 +1,13 @@
 use axum::{
     Json,
-    http::StatusCode,
-    response::{self, IntoResponse, Response},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
 };
 use core::fmt;
 use serde::{Deserialize, Serialize};
 
+use crate::utils::password_policy::PasswordViolation;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     error: String,
@@ -32,6 +34,7 @@ pub enum ErrorMessage {
     UserNotAuthenticated,
     PaawordNotValidated,
     InvalidHashFormat,
+    UnknownPepper,
 }
 
 // error messages in strings
@@ -55,6 +58,9 @@ impl ToString for ErrorMessage {
             ErrorMessage::InvalidHashFormat => {
                 "incoming hashed password is not of correct format".to_string()
             }
+            ErrorMessage::UnknownPepper => {
+                "password hash needs a pepper which is not configured".to_string()
+            }
         }
     }
 }
@@ -65,6 +71,10 @@ pub struct HttpError {
     pub message: String,
     #[serde(with = "http_serde::status_code")] //add serialie trait to the statuscode struct
     pub status: StatusCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>, // seconds , sent as Retry-After header with 429
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub violations: Option<Vec<PasswordViolation>>, // broken password policy rules , sent in the body
 }
 
 // http error function
@@ -73,6 +83,8 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status,
+            retry_after: None,
+            violations: None,
         }
     }
 
@@ -80,6 +92,8 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::NOT_FOUND,
+            retry_after: None,
+            violations: None,
         }
     }
 
@@ -87,6 +101,8 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::UNAUTHORIZED,
+            retry_after: None,
+            violations: None,
         }
     }
 
@@ -94,6 +110,17 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::INTERNAL_SERVER_ERROR,
+            retry_after: None,
+            violations: None,
+        }
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::FORBIDDEN,
+            retry_after: None,
+            violations: None,
         }
     }
 
@@ -101,6 +128,28 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::BAD_REQUEST,
+            retry_after: None,
+            violations: None,
+        }
+    }
+
+    // too many attempts , client has to wait retry_after seconds before trying again
+    pub fn too_many_requests(message: impl Into<String>, retry_after: u64) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::TOO_MANY_REQUESTS,
+            retry_after: Some(retry_after),
+            violations: None,
+        }
+    }
+
+    // new password breaks the password policy , body has every broken rule
+    pub fn password_policy(violations: Vec<PasswordViolation>) -> Self {
+        HttpError {
+            message: "password does not follow the password policy".to_string(),
+            status: StatusCode::BAD_REQUEST,
+            retry_after: None,
+            violations: Some(violations),
         }
     }
 
@@ -115,10 +164,22 @@ impl HttpError {
 }
 
 impl IntoResponse for HttpError {
-
     fn into_response(self) -> Response {
         // here we are constructing tuple and hence calling into response of tuple , so tuple of (status and json message) to reponse of httperror
-        (self.status, Json(self.message)).into_response()
+        // with policy violations the whole error is sent (message , status , violations) , else only the message
+        let mut response = if self.violations.is_some() {
+            (self.status, Json(&self)).into_response()
+        } else {
+            (self.status, Json(self.message)).into_response()
+        };
+
+        if let Some(seconds) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+
+        response
     }
 }
 
@@ -0,0 +1,487 @@
+// we will write admin only handler/controller code here
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+};
+use chrono::Duration;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    AppState,
+    db::{
+        auth::AuthRepository,
+        otp::OtpService,
+        roles::RoleRepository,
+        users::{UserListFilter, UserRepository},
+    },
+    dtos::{
+        admin_user_dto::{
+            AdminUserDTO, AdminUserResponseDTO, AdminUsersQueryDTO, AdminUsersResponseDTO,
+            ChangeRoleDTO,
+        },
+        role_dto::{CreateRoleDTO, PermissionsResponseDTO, RoleDTO, RolesResponseDTO},
+        user_ok_response_dto::UserOkResponsesDTO,
+    },
+    errors::HttpError,
+    handler::auth::clear_login_throttle,
+    mail::mail::{EmailType, construct_mail},
+    middleware::{Admin, RequireRecentAuth, RequireRole},
+    models::{NewRole, OtpPurposeKind, UserRole},
+    utils::token::RECENT_AUTH_MAXAGE_MINUTES,
+};
+
+// users list page size when per_page is not given
+const DEFAULT_USERS_PER_PAGE: i64 = 20;
+
+// router is guarded by RequireRole<Admin> in create_router , handlers also take it so they stay admin only wherever they are routed
+// every api which changes an account (delete , lock out , unlock , verify , give/take rights) also needs a recent authentication of the admin
+pub fn admin_handler() -> Router {
+    let recent_auth = RequireRecentAuth(Duration::minutes(RECENT_AUTH_MAXAGE_MINUTES));
+
+    Router::new()
+        .route("/users", get(list_users))
+        .route(
+            "/users/{user_id}",
+            get(get_user).merge(delete(delete_user).layer(recent_auth)),
+        )
+        .route(
+            "/users/{user_id}/verify",
+            post(force_verify_user).layer(recent_auth),
+        )
+        .route(
+            "/users/{user_id}/role",
+            put(change_user_role).layer(recent_auth),
+        )
+        .route(
+            "/users/{user_id}/password-reset",
+            post(send_password_reset).layer(recent_auth),
+        )
+        .route(
+            "/users/{user_id}/suspend",
+            post(suspend_user).layer(recent_auth),
+        )
+        .route(
+            "/users/{user_id}/unsuspend",
+            post(unsuspend_user).layer(recent_auth),
+        )
+        .route(
+            "/users/{user_id}/unlock",
+            post(unlock_user).layer(recent_auth),
+        )
+        .route(
+            "/roles",
+            get(get_roles).merge(post(create_role).layer(recent_auth)),
+        )
+        .route("/permissions", get(get_permissions))
+        .route(
+            "/users/{user_id}/roles/{role_id}",
+            put(assign_user_role)
+                .delete(remove_user_role)
+                .layer(recent_auth),
+        )
+}
+
+/**
+ * unlocking the account which is locked after too many wrong passwords
+ * @input => admin from auth middleware , user id in path
+ * @result => failed login count and lockout of the account are removed , user can login again
+ */
+pub async fn unlock_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = Uuid::parse_str(&user_id)
+        .map_err(|_| HttpError::bad_request("userId is not a valid Id"))?;
+
+    let mut auth_repo = AuthRepository::new(app_state.db.clone());
+
+    let user = auth_repo.get_user(user_uuid).await?;
+
+    clear_login_throttle(&app_state, &user.email).await?;
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "user account unlocked".to_string(),
+        data: None,
+    })
+}
+
+fn parse_id(id: &str, name: &str) -> Result<Uuid, HttpError> {
+    Uuid::parse_str(id).map_err(|_| HttpError::bad_request(format!("{} is not a valid Id", name)))
+}
+
+/**
+ * @result => every custom role with its permissions
+ */
+pub async fn get_roles(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+) -> Result<impl IntoResponse, HttpError> {
+    let roles = RoleRepository::new(app_state.db.clone())
+        .get_roles()
+        .await?;
+
+    Ok(RolesResponseDTO {
+        status: StatusCode::OK,
+        message: "success".to_string(),
+        roles: roles
+            .into_iter()
+            .map(|(role, permissions)| RoleDTO::from_role(role, permissions))
+            .collect(),
+    })
+}
+
+/**
+ * creating a custom role
+ * @input => role name , optional description and names of its permissions (see /permissions)
+ * @result => saved role , users get its permissions once it is assigned to them
+ */
+pub async fn create_role(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Json(body): Json<CreateRoleDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let new_role = NewRole {
+        name: body.name.trim().to_string(),
+        description: body.description,
+    };
+
+    let role = RoleRepository::new(app_state.db.clone())
+        .create_role(new_role, body.permissions.clone())
+        .await?;
+
+    let mut permissions = body.permissions;
+    permissions.sort();
+    permissions.dedup();
+
+    Ok(RolesResponseDTO {
+        status: StatusCode::CREATED,
+        message: "role created".to_string(),
+        roles: vec![RoleDTO::from_role(role, permissions)],
+    })
+}
+
+/**
+ * @result => every permission which can be given to a role
+ */
+pub async fn get_permissions(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+) -> Result<impl IntoResponse, HttpError> {
+    let permissions = RoleRepository::new(app_state.db.clone())
+        .get_permissions()
+        .await?;
+
+    Ok(PermissionsResponseDTO {
+        status: StatusCode::OK,
+        message: "success".to_string(),
+        permissions,
+    })
+}
+
+/**
+ * giving a custom role to the user , a user can have many roles
+ * @input => user id and role id in path
+ */
+pub async fn assign_user_role(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path((user_id, role_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+    let role_uuid = parse_id(&role_id, "roleId")?;
+
+    let assigned = RoleRepository::new(app_state.db.clone())
+        .assign_role(user_uuid, role_uuid)
+        .await?;
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: if assigned {
+            "role assigned".to_string()
+        } else {
+            "user already has this role".to_string()
+        },
+        data: None,
+    })
+}
+
+/**
+ * taking a custom role back from the user
+ * @input => user id and role id in path
+ */
+pub async fn remove_user_role(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path((user_id, role_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+    let role_uuid = parse_id(&role_id, "roleId")?;
+
+    let removed = RoleRepository::new(app_state.db.clone())
+        .remove_role(user_uuid, role_uuid)
+        .await?;
+
+    if !removed {
+        return Err(HttpError::new(
+            "user does not have this role",
+            StatusCode::NOT_FOUND,
+        ));
+    }
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "role removed".to_string(),
+        data: None,
+    })
+}
+
+fn parse_user_role(role: &str) -> Result<UserRole, HttpError> {
+    match role.trim().to_lowercase().as_str() {
+        "user" => Ok(UserRole::User),
+        "admin" => Ok(UserRole::Admin),
+        _ => Err(HttpError::bad_request("role should be user or admin")),
+    }
+}
+
+/**
+ * admin can not change role of , suspend or delete his own account , so the last admin can not lock everyone out
+ */
+fn ensure_not_self(admin: &RequireRole<Admin>, user_id: Uuid) -> Result<(), HttpError> {
+    if admin.user_data.user.id == user_id {
+        return Err(HttpError::bad_request(
+            "admins can not do this to their own account",
+        ));
+    }
+
+    Ok(())
+}
+
+/**
+ * users list for support staff
+ * @input => page , per_page , filters (verified , role , created_after , created_before) and search on name/email in query
+ * @result => one page of users (newest first) and total count of matching users
+ */
+pub async fn list_users(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Query(query): Query<AdminUsersQueryDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    query
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_USERS_PER_PAGE);
+
+    // huge page numbers would overflow the offset
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .ok_or_else(|| HttpError::bad_request("page is too large"))?;
+
+    let filter = UserListFilter {
+        verified: query.verified,
+        role: query.role.as_deref().map(parse_user_role).transpose()?,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        search: query.search,
+    };
+
+    let (users, total) = UserRepository::new(app_state.db.clone())
+        .list_users(filter, per_page, offset)
+        .await?;
+
+    Ok(AdminUsersResponseDTO {
+        status: StatusCode::OK,
+        message: "success".to_string(),
+        users: users.into_iter().map(AdminUserDTO::from).collect(),
+        page,
+        per_page,
+        total,
+    })
+}
+
+/**
+ * @input => user id in path
+ * @result => details of the user (without password hash)
+ */
+pub async fn get_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+
+    let user = AuthRepository::new(app_state.db.clone())
+        .get_user(user_uuid)
+        .await?;
+
+    Ok(AdminUserResponseDTO {
+        status: StatusCode::OK,
+        message: "success".to_string(),
+        user: user.into(),
+    })
+}
+
+/**
+ * marking the email of the user verified , when he can not get the otp mail
+ */
+pub async fn force_verify_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+
+    let user = UserRepository::new(app_state.db.clone())
+        .force_verify_user(user_uuid)
+        .await?;
+
+    Ok(AdminUserResponseDTO {
+        status: StatusCode::OK,
+        message: "user verified".to_string(),
+        user: user.into(),
+    })
+}
+
+/**
+ * changing user/admin role , the user has to login again (role is in his tokens)
+ * @input => user id in path , new role in body
+ */
+pub async fn change_user_role(
+    Extension(app_state): Extension<Arc<AppState>>,
+    admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+    Json(body): Json<ChangeRoleDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_uuid = parse_id(&user_id, "userId")?;
+    ensure_not_self(&admin, user_uuid)?;
+
+    let role = parse_user_role(&body.role)?;
+
+    let user = UserRepository::new(app_state.db.clone())
+        .change_user_role(user_uuid, role)
+        .await?;
+
+    Ok(AdminUserResponseDTO {
+        status: StatusCode::OK,
+        message: "user role changed".to_string(),
+        user: user.into(),
+    })
+}
+
+/**
+ * sending the reset password otp to the user , same mail as forgot password , user continues from verify-otp
+ */
+pub async fn send_password_reset(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+
+    let user = AuthRepository::new(app_state.db.clone())
+        .get_user(user_uuid)
+        .await?;
+
+    let otp = OtpService::new(app_state.db.clone())
+        .issue_code(OtpPurposeKind::PasswordReset, &user.email)
+        .await?;
+
+    construct_mail(
+        &user.email,
+        &[otp.code],
+        EmailType::ResetPasswordEmailVerification,
+    )
+    .await
+    .map_err(|e| HttpError::server_error(e.message))?;
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "password reset mail sent".to_string(),
+        data: None,
+    })
+}
+
+/**
+ * suspending the user , every session of him is revoked and he can not login until unsuspended
+ */
+pub async fn suspend_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+    ensure_not_self(&admin, user_uuid)?;
+
+    let user = UserRepository::new(app_state.db.clone())
+        .set_user_suspended(user_uuid, true)
+        .await?;
+
+    Ok(AdminUserResponseDTO {
+        status: StatusCode::OK,
+        message: "user suspended".to_string(),
+        user: user.into(),
+    })
+}
+
+/**
+ * letting a suspended user login again
+ */
+pub async fn unsuspend_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    _admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+
+    let user = UserRepository::new(app_state.db.clone())
+        .set_user_suspended(user_uuid, false)
+        .await?;
+
+    Ok(AdminUserResponseDTO {
+        status: StatusCode::OK,
+        message: "user unsuspended".to_string(),
+        user: user.into(),
+    })
+}
+
+/**
+ * deleting the user and all his data for good , it can not be undone
+ */
+pub async fn delete_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    admin: RequireRole<Admin>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user_uuid = parse_id(&user_id, "userId")?;
+    ensure_not_self(&admin, user_uuid)?;
+
+    let deleted = UserRepository::new(app_state.db.clone())
+        .delete_user(user_uuid)
+        .await?;
+
+    if !deleted {
+        return Err(HttpError::new("user not found", StatusCode::NOT_FOUND));
+    }
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "user deleted".to_string(),
+        data: None,
+    })
+}
@@ -2,59 +2,114 @@
 
 use std::sync::Arc;
 
-use axum::{Extension, Json, Router, http::StatusCode, response::IntoResponse, routing::post};
-use chrono::{DateTime, Duration, Utc};
+use axum::{
+    Extension, Json, Router,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use chrono::{Duration, Utc};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     AppState,
     db::{
-        auth::{AuthRepository, SavedUserType},
+        auth::{AuthRepository, LoginVerification, SavedUserType, reset_user_password},
+        mfa::MfaRepository,
+        otp::{OtpService, OtpVerification},
+        sessions::SessionRepository,
+        throttle::{FailureResult, LockoutPolicy, ThrottleRepository, retry_after_seconds},
+        tokens::{RefreshTokenRotation, TokenRepository},
         users,
     },
     dtos::{
+        auth_tokens_response_dto::AuthTokensResponseDTO,
         login_dto::loggedInUser,
+        mfa_dto::MfaLoginDTO,
+        mfa_response_dto::MfaChallengeResponseDTO,
         non_logged_in_user_reset_password_dto::NonLoggedInUserResetPasswordDTO,
-        register_dto::{self, RegisterUser},
+        passwordless_login_dto::{EmailCodeLoginDTO, MagicLinkLoginDTO},
+        refresh_token_dto::RefreshTokenDTO,
+        register_dto::RegisterUser,
         send_otp::SendOtpDTO,
         user_ok_response_dto::UserOkResponsesDTO,
-        verify_email_dto::{self, VerifyEmailDTO},
+        verify_email_dto::VerifyEmailDTO,
     },
-    errors::HttpError,
-    mail::{
-        mail::{
-            EmailType::{NewUserEmailVerification, ResetPasswordEmailVerification},
-            construct_mail,
+    errors::{ErrorMessage, HttpError},
+    handler::webauthn::webauthn_login_handler,
+    mail::mail::{
+        EmailType::{
+            self, AccountLocked, ExistingAccountSignup, LoginCode, MfaLocked,
+            NewUserEmailVerification, ResetPasswordEmailVerification,
         },
-        sendMail::{self, send_mail},
+        construct_mail,
     },
-    models::Users,
+    models::{NewMfaChallenge, NewUserSession, OneTimeCodes, OtpPurposeKind, UserMfa, Users},
     utils::{
-        self,
-        password::{self, generate_otp, hash_pass, validate_pas},
-        token::create_token,
+        client_info::ClientInfo,
+        password::validate_pas,
+        token::{REFRESH_TOKEN_MAXAGE_DAYS, generate_refresh_token, hash_refresh_token},
+        totp::verify_totp_code,
     },
 };
 
-pub enum userType {
-    NewUser,
-    ExistingNonVerifiedSavedUser,
-}
+// wrong passwords are counted per account (email) and per ip
+pub const LOGIN_ACCOUNT_SCOPE: &str = "login:account";
+pub const LOGIN_IP_SCOPE: &str = "login:ip";
+
+// after 3 wrong passwords every try waits 1s , 2s , 4s ... , 10 wrong passwords lock the account for 15 minutes (doubles every time , max 1 day)
+const LOGIN_ACCOUNT_POLICY: LockoutPolicy = LockoutPolicy {
+    delay_after_failures: 3,
+    max_failures: 10,
+    base_lockout_seconds: 15 * 60,
+    max_lockout_seconds: 24 * 60 * 60,
+    reset_after_seconds: 24 * 60 * 60,
+};
+
+// one ip trying many accounts (credential stuffing) , more failures are allowed as many users can share one ip
+const LOGIN_IP_POLICY: LockoutPolicy = LockoutPolicy {
+    delay_after_failures: 20,
+    max_failures: 50,
+    base_lockout_seconds: 15 * 60,
+    max_lockout_seconds: 24 * 60 * 60,
+    reset_after_seconds: 24 * 60 * 60,
+};
+
+// wrong totp/recovery codes are counted per user , every login gives a new challenge so the challenge limit alone is not enough
+pub const MFA_ACCOUNT_SCOPE: &str = "mfa:account";
+
+// 5 wrong codes (across challenges) lock totp login for 15 minutes (doubles every time , max 1 day)
+const MFA_ACCOUNT_POLICY: LockoutPolicy = LockoutPolicy {
+    delay_after_failures: 5,
+    max_failures: 5,
+    base_lockout_seconds: 15 * 60,
+    max_lockout_seconds: 24 * 60 * 60,
+    reset_after_seconds: 24 * 60 * 60,
+};
+
+// time user gets to enter the totp/recovery code after correct password
+const MFA_CHALLENGE_MAXAGE_MINUTES: i64 = 5;
 
 pub fn auth_handler() -> Router {
     Router::new()
         .route("/register", post(register_user))
         .route("/verify-email", post(verify_user))
         .route("/login", post(login_user))
+        .route("/login/mfa", post(login_with_mfa))
+        .route("/login/email-code/send", post(send_login_code))
+        .route("/login/email-code", post(login_with_email_code))
+        .route("/login/magic-link", post(login_with_magic_link))
+        .route("/refresh", post(refresh_tokens))
         .nest("/reset-password", reset_pass_handler())
+        .nest("/webauthn", webauthn_login_handler())
 }
 
 // api routes for reset-pass for non logged-in user
 pub fn reset_pass_handler() -> Router {
     Router::new()
-        .route("/send-otp", post(send_otp)) //to send otp and register user_reset_password_email_verifications
-        .route("/verify-otp", post(verify_forget_pass_emails_otp)) //to verify otp and save reset_token in user_reset_pass_validations
+        .route("/send-otp", post(send_otp)) //to send otp and save its hash in one_time_codes
+        .route("/verify-otp", post(verify_forget_pass_emails_otp)) //to verify otp and give reset_token (saved in one_time_codes)
         .route("/save-new-password", post(save_new_pass)) // to verify reset-token and save new pass and send new jwt tokens to user
 }
 
@@ -64,7 +119,7 @@ pub fn reset_pass_handler() -> Router {
  * user details in register user dto format details
  * shared app state (containg db connection) ,
  * data will be coming in json format , we need data in struct format ,
- * we will save new user in users table and otp details in user_email_verification table , to verify user email
+ * we will save new user in users table and otp (its hash) in one_time_codes table , to verify user email
  */
 pub async fn register_user(
     Extension(app_state): Extension<Arc<AppState>>,
@@ -76,16 +131,29 @@ pub async fn register_user(
     body.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    // otp verification
-    let otp = generate_otp(); // otp/verification token , will send on email
-    let exp_duration = Utc::now() + Duration::minutes(5); // 5 minutes exp time to validate the otp 
+    // every broken password rule is returned together
+    let mut violations = app_state
+        .config
+        .password_policy
+        .check(&body.password, &[&body.email, &body.name]);
 
-    let hashed_pass =
-        password::hash_pass(&body.password).map_err(|e| HttpError::bad_request(e.to_string()))?;
+    if let Some(checker) = &app_state.breached_passwords {
+        violations.extend(checker.check(&body.password));
+    }
+
+    if !violations.is_empty() {
+        return Err(HttpError::password_policy(violations));
+    }
+
+    let hashed_pass = app_state
+        .password_hasher
+        .hash(&body.password)
+        .await
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     println!("time now beofre db pool clone {:?}", Utc::now());
     // we will create a clone of referec
-    let mut db_conn = app_state.db.clone();
+    let db_conn = app_state.db.clone();
 
     println!("time now after db pool clone {:?}", Utc::now());
 
@@ -96,22 +164,25 @@ pub async fn register_user(
 
     let saved_user = user_repo
         .save_new_user(&body.name, &body.email, hashed_pass)
-        .await
-        .map_err(|e| e)?;
+        .await?;
 
     println!("time now after saving user {:?}", Utc::now());
 
+    // in enumeration safe mode every signup gets the same response , user id is not returned (verify with user_email)
+    let enumeration_safe_response = UserOkResponsesDTO {
+        status: StatusCode::CREATED,
+        message: "please check your email to complete the registration".to_string(),
+        data: None,
+    };
+
     match saved_user {
-        SavedUserType::NewUserSaved(user) => {
-            add_or_update_new_user_to_user_verification_table_and_send_email(
-                user.email.clone(),
-                &mut user_repo,
-                otp,
-                exp_duration,
-                userType::NewUser
-            )
-            .await
-            .map_err(|e| e)?;
+        // non verified user signing up again gets a new otp , old one stops working
+        SavedUserType::NewUserSaved(user) | SavedUserType::ExistingNonVerifiedSavedUser(user) => {
+            send_email_verification_code(&app_state, &user.email).await?;
+
+            if app_state.config.enumeration_safe_auth {
+                return Ok(enumeration_safe_response);
+            }
 
             // and return response , we may return user id in the frontend , so that when req comes back , we have user_id to find user and verify the verification token
             // for tuple intoresponse is already implemeted
@@ -124,215 +195,206 @@ pub async fn register_user(
                 data: Some(vec![user.id.to_string()]),
             })
         }
-        SavedUserType::ExistingNonVerifiedSavedUser(user) => {
-            add_or_update_new_user_to_user_verification_table_and_send_email(
-                user.email.clone(),
-                &mut user_repo,
-                otp,
-                exp_duration,
-                userType::ExistingNonVerifiedSavedUser
+        SavedUserType::VerifiedUser(user) => {
+            if !app_state.config.enumeration_safe_auth {
+                return Err(HttpError::new(
+                    "existing user , please login instead",
+                    StatusCode::CONFLICT,
+                ));
+            }
+
+            // real account holder is told that someone tried to signup with his email
+            send_auth_mail(
+                &app_state,
+                user.email,
+                vec![user.name],
+                ExistingAccountSignup,
             )
-            .await
-            .map_err(|e| e)?;
+            .await?;
 
-            // and return response , we may return user id in the frontend , so that when req comes back , we have user_id to find user and verify the verification token
-            // for tuple intoresponse is already implemeted
-            // so basically a response struct is created and sent it to the frontent
-            // response (status_code , content_type , body : userId(converted into json))
-            // why we wrote json wrapper , to tell intoresponse that we need to convet this uuid into json format
-            Ok(UserOkResponsesDTO {
-                status: StatusCode::CREATED,
-                message: "user registerd".to_string(),
-                data: Some(vec![user.id.to_string()]),
-            })
-        }
-        SavedUserType::VerifiedUser => {
-            return Err(HttpError::new(
-                "existing user , please login instead",
-                StatusCode::CONFLICT,
-            ));
-            // if error comes in saving the user , then it might be a db , user_already exist types of error , we will return that error only
+            Ok(enumeration_safe_response)
         }
     }
 }
 
 /**
- * privaet function to save/update user to user_verification table and send email
- * why update ? => if non verified registerd user tries to sign up again we will update OTP a
+ * creating email verification otp (in one_time_codes) and sending email , used by register and email change
  */
-async fn add_or_update_new_user_to_user_verification_table_and_send_email(
-    user_email : String,
-    user_repo: &mut AuthRepository,
-    otp: String,
-    exp_duration: DateTime<Utc>,
-    user_type: userType,
+pub async fn send_email_verification_code(
+    app_state: &Arc<AppState>,
+    user_email: &str,
 ) -> Result<bool, HttpError> {
-    match user_type {
-        userType::NewUser => {
-            // save user otp details in user_emai_verification table
-            user_repo
-                .add_new_user_to_user_verification_table(
-                    otp.to_string(),
-                    user_email.to_string(),
-                    exp_duration,
-                )
-                .await
-                .map_err(|e| e)?;
+    let mut otp_service = OtpService::new(app_state.db.clone());
 
-            println!("time now after saving otp details {:?}", Utc::now());
-        }
-        userType::ExistingNonVerifiedSavedUser => {
-            user_repo
-                .update_user_verification_status(
-                    otp.to_string(),
-                    user_email.to_string(),
-                    exp_duration,
-                )
-                .await
-                .map_err(|e| e)?;
-        }
-    }
+    let otp = otp_service
+        .issue_code(OtpPurposeKind::EmailVerification, user_email)
+        .await?;
 
     // sending otp verification req to the user
     // if we could not able to send email , error will show up
-    construct_mail(
-        user_email.clone(),
-        &[otp.to_string(), user_email.to_string()], //[otp , name_of_the_user]
-        NewUserEmailVerification.clone(),
+    send_auth_mail(
+        app_state,
+        user_email.to_string(),
+        vec![otp.code, user_email.to_string()], //[otp , name_of_the_user]
+        NewUserEmailVerification,
     )
-    .await
-    .map_err(|e| HttpError::new(e.message.to_string(), e.status))?;
-
-    println!("time now after sending email req {:?}", Utc::now());
+    .await?;
 
     Ok(true)
 }
 
+/**
+ * sending mails of the auth flows which must not tell if an email is registered
+ * in enumeration safe mode mail is sent in background , so response time is same whether a mail was sent or not
+ * (mail error is only logged then)
+ */
+async fn send_auth_mail(
+    app_state: &Arc<AppState>,
+    to: String,
+    variables: Vec<String>,
+    email_type: EmailType,
+) -> Result<(), HttpError> {
+    if app_state.config.enumeration_safe_auth {
+        tokio::spawn(async move {
+            if let Err(e) = construct_mail(&to, &variables, email_type).await {
+                tracing::error!("error while sending auth mail {}", e);
+            }
+        });
+
+        return Ok(());
+    }
+
+    construct_mail(to, &variables, email_type)
+        .await
+        .map_err(|e| HttpError::new(e.message.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(())
+}
+
+/**
+ * otp of email verification and reset password flows which did not verify is turned into error here
+ * @result => used up code row , if the otp was correct
+ */
+fn verified_otp(verification: OtpVerification) -> Result<OneTimeCodes, HttpError> {
+    match verification {
+        OtpVerification::Verified(otp) => Ok(otp),
+        OtpVerification::Invalid => Err(HttpError::bad_request("otp not equal")),
+        OtpVerification::Expired => Err(HttpError::bad_request("otp has expired")),
+        OtpVerification::TooManyAttempts => Err(HttpError::bad_request(
+            "too many wrong attempts , please ask for a new otp",
+        )),
+        OtpVerification::NotFound => Err(HttpError::bad_request("otp not found or already used")),
+        OtpVerification::Locked(locked_until) => Err(HttpError::too_many_requests(
+            "too many wrong otps , please try again later",
+            retry_after_seconds(locked_until),
+        )),
+    }
+}
+
 /**
  * input => we will take userid and otp entered and app state(we will get db pool from this)
  * return type => intoresponse => (status , ( tokens))
  */
 pub async fn verify_user(
     Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
     Json(body): Json<VerifyEmailDTO>,
-) -> Result<impl IntoResponse, HttpError> {
+) -> Result<Response, HttpError> {
     body.validate()
         .map_err(|e| HttpError::new(e.to_string(), StatusCode::BAD_REQUEST))?;
 
-    let mut db_con = app_state.db.clone();
+    let db_con = app_state.db.clone();
     let mut auth_repo = AuthRepository::new(db_con);
-    let user_id = body.user_id.ok_or_else(|| {
-        HttpError::new(
-            "cannot extract user id ".to_string(),
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
 
-    // string uuidd to uuid conversion
-    let mut userId = Uuid::parse_str(&user_id)
-        .map_err(|e| HttpError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
+    // user is found by user id (from register response) or by email (enumeration safe register does not return id)
+    let user = match (&body.user_id, &body.user_email) {
+        (Some(user_id), _) => {
+            // string uuidd to uuid conversion
+            let user_uuid = Uuid::parse_str(user_id)
+                .map_err(|e| HttpError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
 
-    // finding user from the db for rhe user id came in with req body
-    let user = auth_repo.get_user(userId).await.map_err(|e| e)?;
+            // finding user from the db for rhe user id came in with req body
+            Some(auth_repo.get_user(user_uuid).await?)
+        }
+        (None, Some(user_email)) => match auth_repo.get_user_from_email(user_email).await {
+            Ok(user) => Some(user),
+            Err(e) if e.status == StatusCode::NOT_FOUND => None,
+            Err(e) => return Err(e),
+        },
+        (None, None) => return Err(HttpError::bad_request("user_id or user_email is required")),
+    };
 
     // if verified user tris to verify again , we will return this okay status
-    if user.verified {
+    // (not in enumeration safe mode , there he gets the same error as an unknown email)
+    if user.as_ref().is_some_and(|user| user.verified) && !app_state.config.enumeration_safe_auth {
         return Ok((
             StatusCode::ACCEPTED,
             Json("user already verified".to_string()),
-        ));
-    }
-
-    // getting new_users user_email_verification status
-    let user_verification_data = auth_repo
-        .get_user_verification_status(user.email.clone())
-        .await
-        .map_err(|e| e)?;
-
-    // if otp already used , then user is already verified , take to login state
-    if user_verification_data.used {
-        return Err(HttpError::bad_request("user already verified".to_string()));
-    }
-
-    // extracting option<data time > to datatime
-    let exp_time = &user_verification_data.expires_at.ok_or_else(|| {
-        HttpError::new(
-            "error while extracting expiration time",
-            StatusCode::INTERNAL_SERVER_ERROR,
         )
-    })?;
-
-    // if exp< current ( time khtmm ) = > then show error expire
-    //  check for if otp not equal , show error
-    // if exp > current (if time is there) => check for otp equal or not
-    // first we will return error , then do for corret
-
-    // if otp has expired
-    if exp_time < &Utc::now() {
-        return Err(HttpError::new(
-            "otp has expred".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
-    }
-
-    // getting token from the user object as string
-    let saved_otp = &user_verification_data.otp;
-
-    // if otp not euqal , show error
-    if saved_otp != &body.otp {
-        return Err(HttpError::new(
-            "otp not equal".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
+            .into_response());
     }
 
-    // till here , exp is okay and otp are equal
-    // we will create auth_token/jwt token for the user verification
-    let auth_tokens = create_token(&user_id).map_err(|e| {
-        HttpError::new(
-            "error while generating auth tokens",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
-
-    // token exp time will be 24 hours
-    let exp_time = Utc::now() + Duration::hours(24);
-
-    // updating otp used status to true
-    auth_repo
-        .update_new_user_otp_status(&user.email)
-        .await
-        .map_err(|e| e)?;
-
-    auth_repo
-        .update_jwt_token_to_user(&user.email, &auth_tokens, exp_time)
-        .await
-        .map_err(|e| e)?;
-
-    // // we need to update the user verification status to used and save auth jwt token in db also
+    let email = user
+        .as_ref()
+        .map(|user| user.email.clone())
+        .or(body.user_email.clone())
+        .unwrap_or_default();
+
+    // checking otp and using it up (wrong otp uses one attempt)
+    // unknown and verified emails have no otp , so they get the same error (and failure count) as a wrong otp
+    let mut otp_service = OtpService::new(app_state.db.clone());
+
+    let verification = otp_service
+        .verify_code(OtpPurposeKind::EmailVerification, &email, &body.otp)
+        .await?;
+
+    verified_otp(verification)?;
+
+    let mut user = user
+        .filter(|user| !user.verified)
+        .ok_or_else(|| HttpError::bad_request("otp not found or already used"))?;
+
+    // till here , otp is correct and used up
+    auth_repo.mark_user_verified(&user.email).await?;
+
+    // token should carry the verified status
+    user.verified = true;
+
+    // we will create a session and auth tokens for the verified user
+    let auth_tokens = issue_auth_tokens(
+        &app_state,
+        &user,
+        &client,
+        None,
+        false,
+        StatusCode::CREATED,
+        "user verified",
+    )
+    .await?;
 
-    Ok((StatusCode::CREATED, Json(auth_tokens)))
+    Ok(auth_tokens.into_response())
 }
 
 /**
  * @inputs => we will get app state and login data as input
  * @result => we will login user and return auth token and basic  user details to the user
+ * if user has totp enabled , we return a mfa challenge token instead of auth tokens
  */
 pub async fn login_user(
     Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
     Json(login_info): Json<loggedInUser>,
-) -> Result<impl IntoResponse, HttpError> {
+) -> Result<Response, HttpError> {
     // we will get user name and password
     // we will send to the db to check if it is okay or not ?
     // if no , we will send unauthorized request
-    // if yes , we will call utils/token function with user id to create token
-    // we will then cal update token db function to save tokens and exp
+    // if yes , we will create a new session (device) and tokens for it
     // and then response okay and send tokens
 
     // validate incoming data
-    login_info.validate().map_err(|e| {
-        HttpError::bad_request(e.to_string())
-    })?;
+    login_info
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     // trasnferring ownershit to our vars
     let user_email = login_info.email.to_string();
@@ -344,231 +406,879 @@ pub async fn login_user(
     // created auth repo instance , so that we call its db function
     let mut auth_repo = AuthRepository::new(db_pool);
 
+    // account or ip with too many wrong passwords has to wait , password is not even checked
+    check_login_throttle(&app_state, &user_email, &client).await?;
+
     // check for user authenticity and verify else will show unauthorized error
-    let logged_in_user = auth_repo
-        .verify_login_user(&user_email, &user_pass)
+    let logged_in_user = match auth_repo
+        .verify_login_user(&user_email, &user_pass, &app_state.password_hasher)
+        .await?
+    {
+        LoginVerification::LoggedIn(user) => user,
+        LoginVerification::NotVerified => {
+            return Err(HttpError::unauthorized(
+                "users email is not verfied , please sign and verify email",
+            ));
+        }
+        // unknown email and wrong password are both counted , so locking does not tell which emails exist
+        failure => {
+            record_failed_login(&app_state, &user_email, &client).await?;
+
+            // in enumeration safe mode both get the same error
+            if app_state.config.enumeration_safe_auth {
+                return Err(HttpError::unauthorized(
+                    ErrorMessage::WrongCredentials.to_string(),
+                ));
+            }
+
+            return match failure {
+                LoginVerification::UserNotFound => {
+                    Err(HttpError::new("user not found", StatusCode::NOT_FOUND))
+                }
+                _ => Err(HttpError::unauthorized("wrong password")),
+            };
+        }
+    };
+
+    clear_login_throttle(&app_state, &user_email).await?;
+
+    complete_login(
+        &app_state,
+        &logged_in_user,
+        &client,
+        login_info.device_name.clone(),
+    )
+    .await
+}
+
+fn login_throttle_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/**
+ * refusing the login if the account or the ip is waiting (delay) or locked
+ * @result => 429 with Retry-After of the longest wait
+ */
+pub async fn check_login_throttle(
+    app_state: &Arc<AppState>,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<(), HttpError> {
+    let mut throttle_repo = ThrottleRepository::new(app_state.db.clone());
+
+    let mut locked_until = throttle_repo
+        .locked_until(LOGIN_ACCOUNT_SCOPE, login_throttle_key(email))
+        .await?;
+
+    if let Some(ip) = &client.ip_address {
+        let ip_locked_until = throttle_repo.locked_until(LOGIN_IP_SCOPE, ip).await?;
+
+        locked_until = locked_until.max(ip_locked_until);
+    }
+
+    match locked_until {
+        Some(locked_until) => Err(HttpError::too_many_requests(
+            "too many failed logins , please try again later",
+            retry_after_seconds(locked_until),
+        )),
+        None => Ok(()),
+    }
+}
+
+/**
+ * counting a wrong password for the account and the ip
+ * when the account gets locked , its owner gets an email (if the account exists)
+ */
+pub async fn record_failed_login(
+    app_state: &Arc<AppState>,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<(), HttpError> {
+    let mut throttle_repo = ThrottleRepository::new(app_state.db.clone());
+
+    if let Some(ip) = &client.ip_address {
+        throttle_repo
+            .record_failure(LOGIN_IP_SCOPE, ip, &LOGIN_IP_POLICY)
+            .await?;
+    }
+
+    let result = throttle_repo
+        .record_failure(
+            LOGIN_ACCOUNT_SCOPE,
+            login_throttle_key(email),
+            &LOGIN_ACCOUNT_POLICY,
+        )
+        .await?;
+
+    let FailureResult::LockedOut(locked_until) = result else {
+        return Ok(());
+    };
+
+    let mut auth_repo = AuthRepository::new(app_state.db.clone());
+
+    if let Ok(user) = auth_repo.get_user_from_email(email).await {
+        let minutes = ((locked_until - Utc::now()).num_seconds() + 59) / 60;
+
+        // login response should not fail because mail could not be sent
+        if let Err(e) = construct_mail(
+            &user.email,
+            &[user.name, minutes.to_string()],
+            AccountLocked,
+        )
         .await
-        .map_err(|e| e)?;
+        {
+            tracing::error!("error while sending account locked mail {}", e);
+        }
+    }
 
-    // creating auth tokens for the user
-    let auth_token = create_token(&logged_in_user).map_err(|e| {
-        HttpError::new(
-            "error while creating used auth tokens",
-            StatusCode::INTERNAL_SERVER_ERROR,
+    Ok(())
+}
+
+/**
+ * forgetting wrong passwords of the account after a correct login (ip failures are kept)
+ */
+pub async fn clear_login_throttle(
+    app_state: &Arc<AppState>,
+    email: &str,
+) -> Result<bool, HttpError> {
+    let mut throttle_repo = ThrottleRepository::new(app_state.db.clone());
+
+    throttle_repo
+        .clear(LOGIN_ACCOUNT_SCOPE, login_throttle_key(email))
+        .await
+}
+
+/**
+ * refusing the totp/recovery code check if the user is locked after too many wrong codes
+ * @result => 429 with Retry-After
+ */
+pub async fn check_mfa_throttle(app_state: &Arc<AppState>, user_id: Uuid) -> Result<(), HttpError> {
+    let locked_until = ThrottleRepository::new(app_state.db.clone())
+        .locked_until(MFA_ACCOUNT_SCOPE, user_id.to_string())
+        .await?;
+
+    match locked_until {
+        Some(locked_until) => Err(HttpError::too_many_requests(
+            "too many wrong mfa codes , please try again later",
+            retry_after_seconds(locked_until),
+        )),
+        None => Ok(()),
+    }
+}
+
+/**
+ * counting a wrong totp/recovery code of the user
+ * when totp login gets locked , user gets an email , as the password was already correct
+ */
+pub async fn record_failed_mfa(app_state: &Arc<AppState>, user: &Users) -> Result<(), HttpError> {
+    let result = ThrottleRepository::new(app_state.db.clone())
+        .record_failure(MFA_ACCOUNT_SCOPE, user.id.to_string(), &MFA_ACCOUNT_POLICY)
+        .await?;
+
+    if let FailureResult::LockedOut(locked_until) = result {
+        let minutes = ((locked_until - Utc::now()).num_seconds() + 59) / 60;
+
+        // response should not fail because mail could not be sent
+        if let Err(e) = construct_mail(
+            &user.email,
+            &[user.name.clone(), minutes.to_string()],
+            MfaLocked,
         )
-    })?;
+        .await
+        {
+            tracing::error!("error while sending mfa locked mail {}", e);
+        }
+    }
 
-    let token_exp = Utc::now() + Duration::hours(24);
+    Ok(())
+}
 
-    // storing the auth tokens in the users table
-    auth_repo
-        .update_jwt_token_to_user(&user_email, &auth_token, token_exp)
+/**
+ * forgetting wrong mfa codes of the user after a correct one
+ */
+pub async fn clear_mfa_throttle(
+    app_state: &Arc<AppState>,
+    user_id: Uuid,
+) -> Result<bool, HttpError> {
+    ThrottleRepository::new(app_state.db.clone())
+        .clear(MFA_ACCOUNT_SCOPE, user_id.to_string())
         .await
-        .map_err(|e| e)?;
+}
 
-    Ok((UserOkResponsesDTO{
-        status : StatusCode::ACCEPTED,
-        message : "user loggedIn successfully".to_string(),
-        data : Some(vec![auth_token , logged_in_user])
+/**
+ * suspended user gets no tokens and no mfa challenge , whichever way he logs in
+ */
+fn ensure_not_suspended(user: &Users) -> Result<(), HttpError> {
+    if user.suspended_at.is_some() {
+        return Err(HttpError::forbidden("account is suspended"));
+    }
 
+    Ok(())
+}
 
-    }))
+/**
+ * last step of every first factor login (password , email code , magic link)
+ * @inputs => user who passed the first factor , client and optional device name
+ * @result => mfa challenge token if user has totp enabled , else new session and auth tokens
+ */
+pub async fn complete_login(
+    app_state: &Arc<AppState>,
+    user: &Users,
+    client: &ClientInfo,
+    device_name: Option<String>,
+) -> Result<Response, HttpError> {
+    ensure_not_suspended(user)?;
+
+    // user with totp enabled gets a challenge token , tokens are given only after the second factor
+    let mut mfa_repo = MfaRepository::new(app_state.db.clone());
+
+    let mfa = mfa_repo.get_user_mfa(user.id).await?;
+
+    if mfa.is_some_and(|mfa| mfa.enabled_at.is_some()) {
+        let mfa_token = generate_refresh_token();
+
+        mfa_repo
+            .create_challenge(NewMfaChallenge {
+                user_id: user.id,
+                token_hash: hash_refresh_token(&mfa_token),
+                device_name,
+                expires_at: Utc::now() + Duration::minutes(MFA_CHALLENGE_MAXAGE_MINUTES),
+            })
+            .await?;
+
+        return Ok(MfaChallengeResponseDTO {
+            status: StatusCode::OK,
+            message: "mfa required".to_string(),
+            mfa_required: true,
+            mfa_token,
+            expires_in: MFA_CHALLENGE_MAXAGE_MINUTES * 60,
+        }
+        .into_response());
+    }
+
+    // every login is a new session and refresh token family
+    let auth_tokens = issue_auth_tokens(
+        app_state,
+        user,
+        client,
+        device_name,
+        false,
+        StatusCode::ACCEPTED,
+        "user loggedIn successfully",
+    )
+    .await?;
+
+    Ok(auth_tokens.into_response())
 }
 
 /**
- * In this function we will send otp to the incoming user email
- * @input => we will get user email as input
- * @ result => we will create , save otp in the user_verification table and sent it to the user email and return bool after sending
+ * second step of login for users with totp enabled
+ * @inputs => mfa token from login and one of totp code or recovery code
+ * @result => same session and tokens as normal login , every challenge allows only few wrong codes
+ * wrong codes are also counted per user (across challenges) and lock totp login after MFA_ACCOUNT_POLICY.max_failures
  */
-pub async fn send_otp(
+pub async fn login_with_mfa(
     Extension(app_state): Extension<Arc<AppState>>,
-    Json(user_email): Json<SendOtpDTO>,
+    client: ClientInfo,
+    Json(body): Json<MfaLoginDTO>,
 ) -> Result<impl IntoResponse, HttpError> {
-    // we will get user email
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let email = user_email.user_email;
+    if body.code.is_some() == body.recovery_code.is_some() {
+        return Err(HttpError::bad_request(
+            "give either totp code or recovery code",
+        ));
+    }
 
-    // 6 digit random otp
-    let otp = generate_otp();
-    let otp_exp = Utc::now() + Duration::minutes(5); //5 min of exp time for otp
+    let mut mfa_repo = MfaRepository::new(app_state.db.clone());
 
-    // clone of db connection pointer
-    let db_con = app_state.db.clone();
+    let challenge = mfa_repo
+        .start_challenge_attempt(hash_refresh_token(&body.mfa_token))
+        .await?
+        .ok_or_else(|| {
+            HttpError::unauthorized("mfa challenge is invalid or expired , please login again")
+        })?;
 
-    let mut auth_repo = AuthRepository::new(db_con);
+    let mfa = mfa_repo
+        .get_user_mfa(challenge.user_id)
+        .await?
+        .filter(|mfa| mfa.enabled_at.is_some())
+        .ok_or_else(|| HttpError::unauthorized("mfa is not enabled for this user"))?;
 
-    // add user email and otp to table to verify on the next step
-    auth_repo
-        .add_otp_details_to_user_reset_password_verification_table(
-            otp.to_string(),
-            email.to_string(),
-            otp_exp,
-        )
-        .await
-        .map_err(|e| e)?;
+    let user = AuthRepository::new(app_state.db.clone())
+        .get_user(challenge.user_id)
+        .await?;
 
-    // sending otp email to the user
-    construct_mail(
-        email.to_string(),
-        &[otp.to_string()],
-        ResetPasswordEmailVerification.clone(),
+    check_mfa_throttle(&app_state, user.id).await?;
+
+    let verified = verify_mfa_code(
+        &app_state,
+        &mut mfa_repo,
+        &mfa,
+        body.code.as_deref(),
+        body.recovery_code.as_deref(),
+    )
+    .await?;
+
+    if !verified {
+        record_failed_mfa(&app_state, &user).await?;
+
+        return Err(HttpError::unauthorized("invalid mfa code"));
+    }
+
+    clear_mfa_throttle(&app_state, user.id).await?;
+
+    if !mfa_repo.complete_challenge(challenge.id).await? {
+        return Err(HttpError::unauthorized(
+            "mfa challenge is invalid or expired , please login again",
+        ));
+    }
+
+    issue_auth_tokens(
+        &app_state,
+        &user,
+        &client,
+        challenge.device_name,
+        true,
+        StatusCode::ACCEPTED,
+        "user loggedIn successfully",
     )
     .await
-    .map_err(|e| HttpError::new(e.message.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
+}
 
-    Ok(UserOkResponsesDTO{
-        status : StatusCode::OK,
-        message :"otp is been sent for the email verification".to_string(),
-        data : None
-    })
+/**
+ * checking a totp code or a recovery code of the user with totp enabled
+ * @inputs => totp row of the user , one of totp code or recovery code
+ * @result => true if the code is correct , it is used up (totp time step saved / recovery code marked used) so it works only once
+ */
+pub async fn verify_mfa_code(
+    app_state: &Arc<AppState>,
+    mfa_repo: &mut MfaRepository,
+    mfa: &UserMfa,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> Result<bool, HttpError> {
+    if let Some(code) = code {
+        // totp code , its time step is saved so the same code cannot log in twice
+        return match verify_totp_code(&mfa.totp_secret, code, mfa.last_used_step) {
+            Some(step) => mfa_repo.use_totp_step(mfa.user_id, step).await,
+            None => Ok(false),
+        };
+    }
+
+    // recovery code , checking with every unused code hash of the user
+    let recovery_code = recovery_code.unwrap_or_default().trim().to_lowercase();
+
+    let codes = mfa_repo.get_unused_recovery_codes(mfa.user_id).await?;
+
+    // every code hash is an argon2 verify , so the whole search runs on the hashing pool
+    let matched = app_state
+        .password_hasher
+        .run(move || {
+            codes
+                .into_iter()
+                .find(|code| validate_pas(&recovery_code, &code.code_hash).unwrap_or(false))
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match matched {
+        Some(code) => mfa_repo.use_recovery_code(code.id).await,
+        None => Ok(false),
+    }
 }
 
 /**
- * this function is to verify the otp of the user/email(not loggedIn user/no_auth_token user) ,(stading on the login page) who has forget his pass and now wants to reset his password
- * we will verify his email via sent otp , here
- * @inputs => we will get otp and email
- * @result => we will verify his otp and return true or false
+ * sending a passwordless login code and magic link to the email
+ * @inputs => email of the user
+ * @result => same response whether the email is registered or not , so nobody can check which emails have accounts
  */
-pub async fn verify_forget_pass_emails_otp(
+pub async fn send_login_code(
     Extension(app_state): Extension<Arc<AppState>>,
-    Json(verify_data): Json<VerifyEmailDTO>,
+    Json(body): Json<SendOtpDTO>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let email = verify_data.user_email.ok_or_else(|| {
-        HttpError::new(
-            "cannot extract email from the inputs".to_string(),
-            StatusCode::INTERNAL_SERVER_ERROR,
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let email = body.user_email;
+
+    let mut auth_repo = AuthRepository::new(app_state.db.clone());
+
+    let user = match auth_repo.get_user_from_email(&email).await {
+        Ok(user) => Some(user),
+        Err(e) if e.status == StatusCode::NOT_FOUND => None,
+        Err(e) => return Err(e),
+    };
+
+    // only verified users can login without password
+    if let Some(user) = user.filter(|user| user.verified) {
+        let mut otp_service = OtpService::new(app_state.db.clone());
+
+        let otp = otp_service
+            .issue_code(OtpPurposeKind::LoginCode, &user.email)
+            .await?;
+
+        let magic_link_token = app_state
+            .token_service
+            .create_magic_link_token(&user.email, otp.id, otp.expires_at)
+            .map_err(|_| HttpError::server_error("error while creating login link"))?;
+
+        let magic_link = format!(
+            "{}?token={}",
+            app_state.config.magic_link_url, magic_link_token
+        );
+
+        send_auth_mail(
+            &app_state,
+            user.email,
+            vec![otp.code, magic_link],
+            LoginCode,
         )
-    })?;
+        .await?;
+    }
 
-    let to_be_verified_otp = verify_data.otp;
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "if this email is registered , a login code has been sent".to_string(),
+        data: None,
+    })
+}
 
-    let db_con = app_state.db.clone();
+/**
+ * passwordless login with the code sent on email
+ * @inputs => email , 6 digit code and optional device name
+ * @result => same as password login (mfa challenge if totp is enabled) , every code allows only few wrong attempts
+ */
+pub async fn login_with_email_code(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<EmailCodeLoginDTO>,
+) -> Result<Response, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let mut auth_repo = AuthRepository::new(db_con);
+    let mut otp_service = OtpService::new(app_state.db.clone());
+
+    // same error for every failure , so the response does not tell if the email has a code
+    let login_code = match otp_service
+        .verify_code(OtpPurposeKind::LoginCode, &body.user_email, &body.otp)
+        .await?
+    {
+        OtpVerification::Verified(login_code) => login_code,
+        OtpVerification::Locked(locked_until) => {
+            return Err(HttpError::too_many_requests(
+                "too many wrong login codes , please try again later",
+                retry_after_seconds(locked_until),
+            ));
+        }
+        _ => return Err(HttpError::unauthorized("login code is invalid or expired")),
+    };
 
-    let user_otp_details = auth_repo
-        .get_user_reset_password_email_verification_status(&email)
-        .await
-        .map_err(|e| e)?;
+    let mut auth_repo = AuthRepository::new(app_state.db.clone());
+
+    let user = auth_repo
+        .get_user_from_email(&login_code.user_email)
+        .await?;
+
+    complete_login(&app_state, &user, &client, body.device_name).await
+}
 
-    if user_otp_details.used {
-        return Err(HttpError::bad_request("otp already used".to_string()));
+/**
+ * passwordless login with the magic link token
+ * it is a post (frontend page reads the token from the link) , so email link scanners opening the link do not use it up
+ * @inputs => signed token from the link and optional device name
+ * @result => same as password login , link works only once and uses up its email code also
+ */
+pub async fn login_with_magic_link(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<MagicLinkLoginDTO>,
+) -> Result<Response, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let claims = app_state
+        .token_service
+        .decode_magic_link_token(&body.token)
+        .map_err(|_| HttpError::unauthorized("login link is invalid or expired"))?;
+
+    let code_id = Uuid::parse_str(&claims.jti)
+        .map_err(|_| HttpError::unauthorized("login link is invalid or expired"))?;
+
+    let mut otp_service = OtpService::new(app_state.db.clone());
+
+    if !otp_service
+        .consume_code(OtpPurposeKind::LoginCode, code_id, &claims.sub)
+        .await?
+    {
+        return Err(HttpError::unauthorized("login link is invalid or expired"));
     }
 
-    let otp_exp = user_otp_details.expires_at.ok_or_else(|| {
-        HttpError::new(
-            "cannot extract expiry details from the details ",
-            StatusCode::INTERNAL_SERVER_ERROR,
+    let mut auth_repo = AuthRepository::new(app_state.db.clone());
+
+    let user = auth_repo.get_user_from_email(&claims.sub).await?;
+
+    complete_login(&app_state, &user, &client, body.device_name).await
+}
+
+/**
+ * @inputs => we will get refresh token in the body
+ * @result => we will rotate the refresh token (old one is marked used) and return new access + refresh token
+ * if an already used refresh token comes again , it is stolen/replayed , so we revoke the whole family and user has to login again
+ */
+pub async fn refresh_tokens(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<RefreshTokenDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let new_refresh_token = generate_refresh_token();
+    let refresh_exp = Utc::now() + Duration::days(REFRESH_TOKEN_MAXAGE_DAYS);
+
+    let mut token_repo = TokenRepository::new(app_state.db.clone());
+
+    let rotation = token_repo
+        .rotate_refresh_token(
+            hash_refresh_token(&body.refresh_token),
+            hash_refresh_token(&new_refresh_token),
+            refresh_exp,
         )
-    })?;
+        .await?;
 
-    if &otp_exp < &Utc::now() {
-        return Err(HttpError::bad_request("otp expired".to_string()));
-    }
+    let old_token = match rotation {
+        RefreshTokenRotation::Rotated(old_token) => old_token,
+        RefreshTokenRotation::ReuseDetected => {
+            return Err(HttpError::unauthorized(
+                "refresh token already used , all sessions of this login are revoked , please login again",
+            ));
+        }
+        RefreshTokenRotation::Invalid => {
+            return Err(HttpError::unauthorized(
+                "refresh token is invalid or expired",
+            ));
+        }
+    };
+
+    let user_id = old_token.user_id;
+
+    // token_version can change after the refresh token was created , so we read the latest one
+    let user = AuthRepository::new(app_state.db.clone())
+        .get_user(user_id)
+        .await?;
+
+    ensure_not_suspended(&user)?;
+
+    // family id of the refresh token is the session id
+    // refresh is not an authentication , so the new token keeps auth time of the session
+    let (auth_time, mfa_authenticated_at) = SessionRepository::new(app_state.db.clone())
+        .get_auth_times(old_token.family_id)
+        .await?;
+
+    let access_token = app_state
+        .token_service
+        .create_token(
+            &user,
+            old_token.family_id,
+            auth_time,
+            mfa_authenticated_at.is_some(),
+        )
+        .map_err(|_| {
+            HttpError::new(
+                "error while generating auth tokens",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
 
-    let saved_otp = user_otp_details.otp;
+    Ok(AuthTokensResponseDTO {
+        status: StatusCode::OK,
+        message: "tokens refreshed".to_string(),
+        user_id: user_id.to_string(),
+        access_token,
+        refresh_token: new_refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.token_service.access_token_maxage_seconds(),
+    })
+}
 
-    if to_be_verified_otp != saved_otp {
-        return Err(HttpError::bad_request("otp not eqaul".to_string()));
-    }
+/**
+ * function to start a new login session for the user and give tokens for it
+ * @inputs => user (with his current token_version) , client(ip , user agent) and optional device name , status and message of response
+ * mfa is true only when this login passed a second factor (totp/recovery code) , it becomes the mfa claim of the session
+ * @result => session is saved , access token (with sid) and refresh token (family = session id) are returned
+ * raw refresh token is only given to the user , we never store it
+ */
+pub async fn issue_auth_tokens(
+    app_state: &Arc<AppState>,
+    user: &Users,
+    client: &ClientInfo,
+    device_name: Option<String>,
+    mfa: bool,
+    status: StatusCode,
+    message: &str,
+) -> Result<AuthTokensResponseDTO, HttpError> {
+    ensure_not_suspended(user)?;
+
+    let user_id = user.id;
+
+    let mut session_repo = SessionRepository::new(app_state.db.clone());
+
+    let session = session_repo
+        .create_session(NewUserSession {
+            user_id,
+            device_name,
+            user_agent: client.user_agent.clone(),
+            ip_address: client.ip_address.clone(),
+            mfa_authenticated_at: mfa.then(Utc::now),
+        })
+        .await?;
+
+    let access_token = app_state
+        .token_service
+        .create_token(
+            user,
+            session.id,
+            session.authenticated_at,
+            session.mfa_authenticated_at.is_some(),
+        )
+        .map_err(|_| {
+            HttpError::new(
+                "error while generating auth tokens",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let refresh_token = generate_refresh_token();
+    let refresh_exp = Utc::now() + Duration::days(REFRESH_TOKEN_MAXAGE_DAYS);
 
-    // if we reached here , otp is eqaul , now we will create reset token and save it and return it to the user
-    let reset_token = uuid::Uuid::new_v4();
-    let exp_at = Utc::now() + Duration::minutes(5);
+    let mut token_repo = TokenRepository::new(app_state.db.clone());
 
-    let hashed_reset_token = hash_pass(reset_token.clone()).map_err(|e| {
-        HttpError::new(
-            "getting error in hshing pass",
-            StatusCode::INTERNAL_SERVER_ERROR,
+    token_repo
+        .save_refresh_token(
+            user_id,
+            session.id,
+            hash_refresh_token(&refresh_token),
+            refresh_exp,
         )
-    })?;
+        .await?;
+
+    Ok(AuthTokensResponseDTO {
+        status,
+        message: message.to_string(),
+        user_id: user_id.to_string(),
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.token_service.access_token_maxage_seconds(),
+    })
+}
 
-    // saving reset token details in the user_reset_pass_validations table
-    auth_repo
-        .save_reset_token_details(email.clone(), hashed_reset_token.clone(), exp_at)
-        .await
-        .map_err(|e| e)?;
+/**
+ * In this function we will send otp to the incoming user email
+ * @input => we will get user email as input
+ * @ result => we will create , save otp (its hash) in the one_time_codes table and sent it to the user email and return bool after sending
+ * unknown email gets 404 , or the same ok response (and no mail) in enumeration safe mode
+ */
+pub async fn send_otp(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(user_email): Json<SendOtpDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    user_email
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    // we will get user email
+
+    let email = user_email.user_email;
+
+    let enumeration_safe = app_state.config.enumeration_safe_auth;
+
+    let mut auth_repo = AuthRepository::new(app_state.db.clone());
+
+    let user = match auth_repo.get_user_from_email(&email).await {
+        Ok(user) => Some(user),
+        Err(e) if e.status == StatusCode::NOT_FOUND && enumeration_safe => None,
+        Err(e) => return Err(e),
+    };
+
+    if let Some(user) = user {
+        let mut otp_service = OtpService::new(app_state.db.clone());
+
+        // 6 digit random otp , saved (its hash) to verify on the next step
+        let otp = otp_service
+            .issue_code(OtpPurposeKind::PasswordReset, &user.email)
+            .await?;
+
+        // sending otp email to the user
+        send_auth_mail(
+            &app_state,
+            user.email,
+            vec![otp.code],
+            ResetPasswordEmailVerification,
+        )
+        .await?;
+    }
+
+    let message = if enumeration_safe {
+        "if this email is registered , an otp has been sent to reset the password"
+    } else {
+        "otp is been sent for the email verification"
+    };
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: message.to_string(),
+        data: None,
+    })
+}
+
+/**
+ * this function is to verify the otp of the user/email(not loggedIn user/no_auth_token user) ,(stading on the login page) who has forget his pass and now wants to reset his password
+ * we will verify his email via sent otp , here
+ * @inputs => we will get otp and email
+ * @result => otp is used up and a reset token (tied to this otp row) is returned , it is needed to save the new password
+ */
+pub async fn verify_forget_pass_emails_otp(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(verify_data): Json<VerifyEmailDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    verify_data
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let email = verify_data
+        .user_email
+        .ok_or_else(|| HttpError::bad_request("user_email is required"))?;
+
+    let mut otp_service = OtpService::new(app_state.db.clone());
+
+    let verification = otp_service
+        .verify_code(OtpPurposeKind::PasswordReset, &email, &verify_data.otp)
+        .await?;
+
+    let otp = verified_otp(verification)?;
+
+    // if we reached here , otp is correct and used up , now we will create reset token and save it (its hash) and return it to the user
+    // token points to the otp row , so only this otp can give this token
+    let reset_token = otp_service
+        .issue_code_after(&otp, OtpPurposeKind::PasswordResetToken)
+        .await?;
 
     // returning reset tokens to the user for the next step validation
-    Ok(
-        UserOkResponsesDTO{
-            status : StatusCode::ACCEPTED,
-            message : "email Verified".to_string(),
-            data : Some(vec![reset_token.to_string()])
-        }
-    )
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::ACCEPTED,
+        message: "email Verified".to_string(),
+        data: Some(vec![reset_token.code]),
+    })
 }
 
 /**
  * this function is to update the pass of the user/email(non loggedIn user/no_auth_token user) , who has verified his email via otp
  * we will update the pass of the email
  * @input => email  , reset_pass_token and new_pass
- * @result => we will return new auth tokens to the user
+ * @result => we will return new auth tokens to the user , or mfa challenge token if user has totp enabled (same as login)
+ * new password is hashed like register , token is used up in the same transaction which saves the password
+ * so a token works only once and a failed save does not use it up
  */
 pub async fn save_new_pass(
     Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
     Json(new_pass_data): Json<NonLoggedInUserResetPasswordDTO>,
 ) -> Result<impl IntoResponse, HttpError> {
+    new_pass_data
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
     let email = new_pass_data.user_email;
 
-    let unhashed_reset_token = new_pass_data.reset_token;
     let new_password = new_pass_data.new_password;
 
-    let db_con = app_state.db.clone();
+    let mut otp_service = OtpService::new(app_state.db.clone());
 
-    let mut auth_repo = AuthRepository::new(db_con);
+    // reset token is checked first (without using it up) , so reqs with bogus tokens never reach the argon2 work below
+    verified_otp(
+        otp_service
+            .check_code(
+                OtpPurposeKind::PasswordResetToken,
+                &email,
+                &new_pass_data.reset_token,
+            )
+            .await?,
+    )?;
 
-    let reset_pass_data = auth_repo
-        .get_user_reset_password_validations_details(&email)
-        .await
-        .map_err(|e| e)?;
+    let user = AuthRepository::new(app_state.db.clone())
+        .get_user_from_email(&email)
+        .await?;
 
-    if reset_pass_data.used {
-        return Err(HttpError::bad_request(
-            "password already updated".to_string(),
-        ));
+    // new password should follow the policy and should not be a recently used password
+    // if it breaks a rule , reset token is still usable for a better password
+    let policy = &app_state.config.password_policy;
+
+    let mut violations = policy.check(&new_password, &[&user.email, &user.name]);
+
+    if let Some(checker) = &app_state.breached_passwords {
+        violations.extend(checker.check(&new_password));
     }
 
-    let otp_exp = reset_pass_data.expires_at.ok_or_else(|| {
-        HttpError::new(
-            "cannot extract expiry details from the details ",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
+    if policy.history_size > 0 {
+        let hashes = users::UserRepository::new(app_state.db.clone())
+            .get_recent_password_hashes(&user, policy.history_size)
+            .await?;
+
+        // one argon2 verify per old hash
+        let history_policy = policy.clone();
+        let password = new_password.clone();
+        let reused = app_state
+            .password_hasher
+            .run(move || history_policy.check_history(&password, &hashes))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    if &otp_exp < &Utc::now() {
-        return Err(HttpError::bad_request("otp expired".to_string()));
+        violations.extend(reused);
     }
 
-    let verify_reset_token =
-        validate_pas(&unhashed_reset_token, &reset_pass_data.hashed_reset_token).map_err(|e| {
-            HttpError::new(
-                "not able to validate reset token".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )
-        })?;
+    if !violations.is_empty() {
+        return Err(HttpError::password_policy(violations));
+    }
 
-    let user_details = auth_repo
-        .get_user_from_email(&reset_pass_data.user_email)
+    let hashed_password = app_state
+        .password_hasher
+        .hash(&new_password)
         .await
-        .map_err(|e| e)?;
-
-    let jwt_token = create_token(user_details.id.to_string()).map_err(|e| {
-        HttpError::new(
-            "error while generating auth tokens",
-            StatusCode::INTERNAL_SERVER_ERROR,
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let history_size = policy.history_size;
+
+    // token is used up and password is saved in one transaction
+    // password update will bump the token_version and revoke every session
+    // every older token (other devices , attacker) stops working
+    let (verification, updated_user) = otp_service
+        .verify_code_and_apply(
+            OtpPurposeKind::PasswordResetToken,
+            &email,
+            &new_pass_data.reset_token,
+            move |conn, reset_token| {
+                reset_user_password(
+                    conn,
+                    &reset_token.user_email,
+                    &hashed_password,
+                    history_size,
+                )
+                .map(Ok::<_, ()>)
+            },
         )
-    })?;
-    let expiry = Utc::now() + Duration::hours(24);
+        .await?;
 
-    let ans = auth_repo
-        .update_user_password_and_jwt_token_and_exp(&email, new_password, jwt_token.clone(), expiry)
-        .await
-        .map_err(|e| e)?;
+    verified_otp(verification)?;
 
-    Ok(UserOkResponsesDTO{
-        status : StatusCode::ACCEPTED,
-        message : "user password is updated".to_string(),
-        data : Some(vec![jwt_token.to_string() , user_details.id.to_string()])
-    })
+    let user_details = updated_user
+        .and_then(Result::ok)
+        .ok_or_else(|| HttpError::server_error("error while saving new password"))?;
+
+    // user who proved the email can login again , even if wrong passwords had locked the account
+    clear_login_throttle(&app_state, &user_details.email).await?;
+
+    // reset proves only the email , so a user with totp still has to give the second factor before getting tokens
+    complete_login(&app_state, &user_details, &client, None).await
 }
@@ -0,0 +1,212 @@
+// we will write two factor (totp) handlers for the logged in user here
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, post},
+};
+use chrono::Duration;
+use validator::Validate;
+
+use crate::{
+    AppState,
+    db::mfa::MfaRepository,
+    dtos::{
+        mfa_dto::{MfaCodeDTO, TotpCodeDTO},
+        mfa_response_dto::{RecoveryCodesResponseDTO, TotpEnrollResponseDTO},
+        user_ok_response_dto::UserOkResponsesDTO,
+    },
+    errors::HttpError,
+    handler::auth::{check_mfa_throttle, clear_mfa_throttle, record_failed_mfa, verify_mfa_code},
+    middleware::{JwtAuthMiddleware, RequireRecentAuth},
+    utils::{
+        password::hash_pass,
+        token::RECENT_AUTH_MAXAGE_MINUTES,
+        totp::{generate_recovery_codes, generate_totp_secret, totp_uri, verify_totp_code},
+    },
+};
+
+// these routes are nested in users handler , so auth middleware protects them
+pub fn mfa_handler() -> Router {
+    Router::new()
+        .route(
+            "/totp/enroll",
+            post(enroll_totp).layer(RequireRecentAuth(Duration::minutes(
+                RECENT_AUTH_MAXAGE_MINUTES,
+            ))),
+        )
+        .route("/totp/confirm", post(confirm_totp))
+        .route(
+            "/totp",
+            delete(disable_totp).layer(RequireRecentAuth(Duration::minutes(
+                RECENT_AUTH_MAXAGE_MINUTES,
+            ))),
+        )
+}
+
+/**
+ * starting totp setup for the logged in user
+ * @input => user from auth middleware
+ * @result => new secret is saved as pending and returned with otpauth uri , login does not ask for totp till user confirms a code
+ */
+pub async fn enroll_totp(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user = user_data.user;
+
+    let mut mfa_repo = MfaRepository::new(app_state.db.clone());
+
+    let existing = mfa_repo.get_user_mfa(user.id).await?;
+
+    if existing.is_some_and(|mfa| mfa.enabled_at.is_some()) {
+        return Err(HttpError::new(
+            "totp is already enabled",
+            StatusCode::CONFLICT,
+        ));
+    }
+
+    let secret = generate_totp_secret();
+
+    let otpauth_uri = totp_uri(&secret, &user.email)
+        .map_err(|_| HttpError::server_error("error while creating totp uri"))?;
+
+    mfa_repo.save_pending_totp_secret(user.id, &secret).await?;
+
+    Ok(TotpEnrollResponseDTO {
+        status: StatusCode::OK,
+        message: "scan the uri in your authenticator app and confirm a code".to_string(),
+        secret,
+        otpauth_uri,
+    })
+}
+
+/**
+ * confirming totp setup with the first code from the authenticator app
+ * @input => user from auth middleware , 6 digit code
+ * @result => totp is enabled and recovery codes are returned (only this time , we save their argon2 hashes)
+ */
+pub async fn confirm_totp(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Json(body): Json<TotpCodeDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = user_data.user;
+
+    let mut mfa_repo = MfaRepository::new(app_state.db.clone());
+
+    let mfa = mfa_repo
+        .get_user_mfa(user.id)
+        .await?
+        .ok_or_else(|| HttpError::bad_request("start totp enrollment first"))?;
+
+    if mfa.enabled_at.is_some() {
+        return Err(HttpError::new(
+            "totp is already enabled",
+            StatusCode::CONFLICT,
+        ));
+    }
+
+    let step = verify_totp_code(&mfa.totp_secret, &body.code, mfa.last_used_step)
+        .ok_or_else(|| HttpError::bad_request("invalid totp code"))?;
+
+    let recovery_codes = generate_recovery_codes();
+
+    let codes_to_hash = recovery_codes.clone();
+    let recovery_code_hashes = app_state
+        .password_hasher
+        .run(move || {
+            codes_to_hash
+                .iter()
+                .map(hash_pass)
+                .collect::<Result<Vec<String>, _>>()
+        })
+        .await
+        .and_then(|hashes| hashes)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let enabled = mfa_repo
+        .enable_totp(user.id, step, recovery_code_hashes)
+        .await?;
+
+    // parallel confirm already enabled it
+    if !enabled {
+        return Err(HttpError::new(
+            "totp is already enabled",
+            StatusCode::CONFLICT,
+        ));
+    }
+
+    Ok(RecoveryCodesResponseDTO {
+        status: StatusCode::OK,
+        message: "totp enabled , save these recovery codes".to_string(),
+        recovery_codes,
+    })
+}
+
+/**
+ * turning totp off for the logged in user
+ * @input => user from auth middleware , one of totp code or recovery code
+ * token must be recently authenticated (login or reauthenticate in last RECENT_AUTH_MAXAGE_MINUTES)
+ * @result => totp , recovery codes and open login challenges are deleted , admins lose admin apis till they enable it again
+ */
+pub async fn disable_totp(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Json(body): Json<MfaCodeDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if body.code.is_some() == body.recovery_code.is_some() {
+        return Err(HttpError::bad_request(
+            "give either totp code or recovery code",
+        ));
+    }
+
+    let user = user_data.user;
+
+    let mut mfa_repo = MfaRepository::new(app_state.db.clone());
+
+    let mfa = mfa_repo
+        .get_user_mfa(user.id)
+        .await?
+        .filter(|mfa| mfa.enabled_at.is_some())
+        .ok_or_else(|| HttpError::bad_request("totp is not enabled"))?;
+
+    check_mfa_throttle(&app_state, user.id).await?;
+
+    let verified = verify_mfa_code(
+        &app_state,
+        &mut mfa_repo,
+        &mfa,
+        body.code.as_deref(),
+        body.recovery_code.as_deref(),
+    )
+    .await?;
+
+    if !verified {
+        record_failed_mfa(&app_state, &user).await?;
+
+        return Err(HttpError::unauthorized("invalid mfa code"));
+    }
+
+    clear_mfa_throttle(&app_state, user.id).await?;
+
+    // parallel disable already removed it
+    if !mfa_repo.disable_totp(user.id).await? {
+        return Err(HttpError::bad_request("totp is not enabled"));
+    }
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "totp disabled".to_string(),
+        data: None,
+    })
+}
@@ -1,2 +1,6 @@
+pub mod admin;
 pub mod auth;
-pub mod users;
\ No newline at end of file
+pub mod mfa;
+pub mod users;
+pub mod webauthn;
+pub mod well_known;
@@ -1,30 +1,85 @@
 use std::sync::Arc;
 
 use axum::{
-    Extension, Json, Router, extract::Path, http::StatusCode, response::IntoResponse, routing::{delete, get, post, put},
+    Extension, Json, Router,
+    extract::Path,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
 };
-use diesel::result;
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     AppState,
-    db::users::UserRepository,
+    db::{
+        mfa::MfaRepository, sessions::SessionRepository, tokens::TokenRepository,
+        users::UserRepository,
+    },
     dtos::{
-        loggedIn_user_reset_password_dto::LoggedInUserResetPasswordDTO, note_dto::NoteDTO, user_dto::UserDTO, user_notes_vec_response_dto::UserNotesVecResponseDTO, user_ok_response_dto::UserOkResponsesDTO
+        change_email_dto::ChangeEmailDTO,
+        loggedIn_user_reset_password_dto::LoggedInUserResetPasswordDTO,
+        logout_dto::LogoutDTO,
+        note_dto::NoteDTO,
+        reauthenticate_dto::{ReauthenticateDTO, ReauthenticateResponseDTO},
+        user_dto::UserDTO,
+        user_notes_vec_response_dto::UserNotesVecResponseDTO,
+        user_ok_response_dto::UserOkResponsesDTO,
+        user_sessions_response_dto::{UserSessionDTO, UserSessionsResponseDTO},
     },
     errors::HttpError,
-    middleware::JwtAuthMiddleware,
-    utils::password::validate_pas,
+    handler::{
+        auth::{
+            check_login_throttle, check_mfa_throttle, clear_login_throttle, clear_mfa_throttle,
+            issue_auth_tokens, record_failed_login, record_failed_mfa,
+            send_email_verification_code, verify_mfa_code,
+        },
+        mfa::mfa_handler,
+        webauthn::webauthn_register_handler,
+    },
+    mail::mail::{EmailType, construct_mail},
+    middleware::{JwtAuthMiddleware, RequireRecentAuth},
+    utils::{
+        client_info::ClientInfo,
+        password_policy::PasswordViolation,
+        token::{RECENT_AUTH_MAXAGE_MINUTES, hash_refresh_token},
+    },
 };
 
 pub fn users_handler() -> Router {
     Router::new()
-    .route("/user_details", get(get_user_data))
-    .route("/get-user-notes" , get(get_users_notes))
-    .route("/create-user-note" , post(create_user_note))
-    .route("/edit-user-note/{note_id}" , put(update_user_note))
-    .route("/delete-user-note/{note_id}" , delete(delete_user_note))
+        .route("/user_details", get(get_user_data))
+        .route("/get-user-notes", get(get_users_notes))
+        .route("/users/{user_id}/notes", get(get_any_user_notes))
+        .route("/create-user-note", post(create_user_note))
+        .route("/edit-user-note/{note_id}", put(update_user_note))
+        .route("/delete-user-note/{note_id}", delete(delete_user_note))
+        .route(
+            "/password",
+            put(update_loggedIn_user_password).layer(RequireRecentAuth(Duration::minutes(
+                RECENT_AUTH_MAXAGE_MINUTES,
+            ))),
+        )
+        .route(
+            "/email",
+            put(change_user_email).layer(RequireRecentAuth(Duration::minutes(
+                RECENT_AUTH_MAXAGE_MINUTES,
+            ))),
+        )
+        .route(
+            "/",
+            delete(delete_user_account).layer(RequireRecentAuth(Duration::minutes(
+                RECENT_AUTH_MAXAGE_MINUTES,
+            ))),
+        )
+        .route("/reauthenticate", post(reauthenticate))
+        .route("/logout", post(logout_user))
+        .route("/logout-all", post(logout_all_devices))
+        .route("/sessions", get(get_user_sessions))
+        .route("/sessions/{session_id}", delete(revoke_user_session))
+        .nest("/mfa", mfa_handler())
+        .nest("/webauthn", webauthn_register_handler())
 }
 
 /**
@@ -37,22 +92,253 @@ pub async fn get_user_data(
 ) -> Result<impl IntoResponse, HttpError> {
     println!("middleware worked {:?}", user.user);
 
-    let user_data = UserDTO{
-        name : user.user.name,
-        email : user.user.email,
+    let user_data = UserDTO {
+        name: user.user.name,
+        email: user.user.email,
     };
 
     Ok((StatusCode::ACCEPTED, user_data))
 }
 
 /**
- * in this service we will update the the logged in user password
+ * logout of the current device
+ * @input => user and token claims from auth middleware , optional refresh token of this login in body
+ * @result => we will add the current access token jti to the denylist , revoke the current session and the refresh token family if given
+ */
+pub async fn logout_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    body: Option<Json<LogoutDTO>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let mut token_repo = TokenRepository::new(app_state.db.clone());
+
+    revoke_current_access_token(&mut token_repo, &user_data).await?;
+
+    let session_id = Uuid::parse_str(&user_data.claims.sid)
+        .map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+
+    SessionRepository::new(app_state.db.clone())
+        .revoke_session(user_data.user.id, session_id)
+        .await?;
+
+    if let Some(Json(logout_data)) = body {
+        logout_data
+            .validate()
+            .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+        if let Some(refresh_token) = logout_data.refresh_token {
+            token_repo
+                .revoke_refresh_token_family(user_data.user.id, hash_refresh_token(&refresh_token))
+                .await?;
+        }
+    }
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "user logged out".to_string(),
+        data: None,
+    })
+}
+
+/**
+ * logout from all the devices
+ * @input => user and token claims from auth middleware
+ * @result => token_version of the user is bumped (every access token dies) and every refresh token of the user is revoked
+ */
+pub async fn logout_all_devices(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let mut token_repo = TokenRepository::new(app_state.db.clone());
+
+    token_repo.revoke_all_user_tokens(user_data.user.id).await?;
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "user logged out from all devices".to_string(),
+        data: None,
+    })
+}
+
+/**
+ * all the devices where user is logged in
+ * @input => user and token claims from auth middleware
+ * @result => list of active sessions , the session of this req is marked current
+ */
+pub async fn get_user_sessions(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let mut session_repo = SessionRepository::new(app_state.db.clone());
+
+    let sessions = session_repo.get_user_sessions(user_data.user.id).await?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| UserSessionDTO {
+            current: session.id.to_string() == user_data.claims.sid,
+            id: session.id.to_string(),
+            device_name: session.device_name,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        })
+        .collect();
+
+    Ok(UserSessionsResponseDTO {
+        status: StatusCode::OK,
+        message: "success".to_string(),
+        sessions,
+    })
+}
+
+/**
+ * logout a single device of the user
+ * @input => user from auth middleware , session id in path
+ * @result => session and its refresh tokens are revoked , its access tokens are rejected by auth middleware
+ */
+pub async fn revoke_user_session(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Path(session_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|_| HttpError::bad_request("sessionId is not a valid Id"))?;
+
+    let mut session_repo = SessionRepository::new(app_state.db.clone());
+
+    let revoked = session_repo
+        .revoke_session(user_data.user.id, session_uuid)
+        .await?;
+
+    if !revoked {
+        return Err(HttpError::not_found("session not found"));
+    }
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "session revoked".to_string(),
+        data: None,
+    })
+}
+
+/**
+ * private function to put the access token of this req in the denylist till its expiry
+ */
+async fn revoke_current_access_token(
+    token_repo: &mut TokenRepository,
+    user_data: &JwtAuthMiddleware,
+) -> Result<bool, HttpError> {
+    let jti = Uuid::parse_str(&user_data.claims.jti)
+        .map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+
+    let expiry = DateTime::<Utc>::from_timestamp(user_data.claims.exp as i64, 0)
+        .ok_or_else(|| HttpError::server_error("cannot extract token expiry"))?;
+
+    token_repo
+        .revoke_access_token(jti, user_data.user.id, expiry)
+        .await
+}
+
+/**
+ * step up before a sensitive api , logged in user gives his password or totp code again
+ * @input => user and token claims from auth middleware , one of password or totp code
+ * @result => session is marked as authenticated now , new access token of the same session (fresh auth_time) is returned
+ * totp code also marks the session as mfa authenticated (mfa claim) , so a passkey session can step up for admin apis
+ * wrong password is counted like a failed login and wrong totp code like a failed mfa login , so guessing gets throttled and locked
+ */
+pub async fn reauthenticate(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Json(body): Json<ReauthenticateDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if body.password.is_some() == body.code.is_some() {
+        return Err(HttpError::bad_request("give either password or totp code"));
+    }
+
+    let user = user_data.user;
+
+    if let Some(password) = &body.password {
+        // wrong passwords count under the login throttle , same as password login
+        check_login_throttle(&app_state, &user.email, &client).await?;
+
+        let verified = app_state
+            .password_hasher
+            .verify(password, &user.password)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        if !verified {
+            record_failed_login(&app_state, &user.email, &client).await?;
+
+            return Err(HttpError::unauthorized("wrong password"));
+        }
+
+        clear_login_throttle(&app_state, &user.email).await?;
+    } else {
+        // wrong totp codes count under the mfa throttle , same as mfa login , password lockout is not touched
+        let mut mfa_repo = MfaRepository::new(app_state.db.clone());
+
+        let mfa = mfa_repo
+            .get_user_mfa(user.id)
+            .await?
+            .filter(|mfa| mfa.enabled_at.is_some())
+            .ok_or_else(|| HttpError::bad_request("mfa is not enabled for this user"))?;
+
+        check_mfa_throttle(&app_state, user.id).await?;
+
+        // same code can not be used twice (login and reauthenticate) , its time step is saved
+        let verified =
+            verify_mfa_code(&app_state, &mut mfa_repo, &mfa, body.code.as_deref(), None).await?;
+
+        if !verified {
+            record_failed_mfa(&app_state, &user).await?;
+
+            return Err(HttpError::unauthorized("wrong totp code"));
+        }
+
+        clear_mfa_throttle(&app_state, user.id).await?;
+    }
+
+    let session_id = Uuid::parse_str(&user_data.claims.sid)
+        .map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+
+    // totp reauthenticate is a second factor , so it also makes the session mfa authenticated
+    let (auth_time, mfa_authenticated_at) = SessionRepository::new(app_state.db.clone())
+        .mark_authenticated(user.id, session_id, body.code.is_some())
+        .await?
+        .ok_or_else(|| HttpError::unauthorized("session is revoked , please login again"))?;
+
+    let access_token = app_state
+        .token_service
+        .create_token(&user, session_id, auth_time, mfa_authenticated_at.is_some())
+        .map_err(|_| HttpError::server_error("error while generating auth tokens"))?;
+
+    Ok(ReauthenticateResponseDTO {
+        status: StatusCode::OK,
+        message: "reauthenticated".to_string(),
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.token_service.access_token_maxage_seconds(),
+        auth_time: auth_time.timestamp(),
+    })
+}
+
+/**
+ * in this service we will update the the logged in user password (PUT /api/user/password)
  * @input => we will get user from auth midleware(from jwt tokens sent by the frontend) , old pass , new pass
- * @result => we will verify user and its old password , if they are eqaul , we will update the new password
+ * token must be recently authenticated (login or reauthenticate in last RECENT_AUTH_MAXAGE_MINUTES)
+ * @result => we will verify user and its old password , if they are eqaul , we will check the new password with the policy ,
+ * hash and save it , every session is revoked , caller gets new tokens and the user gets a mail about the change
  */
 pub async fn update_loggedIn_user_password(
     Extension(app_state): Extension<Arc<AppState>>,
-
+    client: ClientInfo,
     Extension(user_data): Extension<JwtAuthMiddleware>,
     Json(passwords_data): Json<LoggedInUserResetPasswordDTO>,
 ) -> Result<impl IntoResponse, HttpError> {
@@ -64,9 +350,12 @@ pub async fn update_loggedIn_user_password(
     let old_incoming_pass = passwords_data.old_password;
     let new_incoming_pass = passwords_data.new_password;
 
-    let user = user_data.user;
+    let mut user = user_data.user;
 
-    let pass_compared_result = validate_pas(&old_incoming_pass, &user.password)
+    let pass_compared_result = app_state
+        .password_hasher
+        .verify(&old_incoming_pass, &user.password)
+        .await
         .map_err(|e| HttpError::new(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR))?;
 
     // if old passowrd is not equal , then we will return error
@@ -76,17 +365,158 @@ pub async fn update_loggedIn_user_password(
         ));
     }
 
+    // old password matched the current hash , so same new password means the current one is reused
+    if new_incoming_pass == old_incoming_pass {
+        return Err(HttpError::password_policy(vec![PasswordViolation {
+            rule: "current_password",
+            message: "new password should be different from the current password".to_string(),
+        }]));
+    }
+
     let db_con = app_state.db.clone();
     let mut user_repo = UserRepository::new(db_con);
 
-    let res = user_repo
-        .update_loggedIn_user_pass(user.id.clone(), new_incoming_pass.to_string())
+    // new password should follow the policy and should not be a recently used password
+    let policy = &app_state.config.password_policy;
+
+    let mut violations = policy.check(&new_incoming_pass, &[&user.email, &user.name]);
+
+    if let Some(checker) = &app_state.breached_passwords {
+        violations.extend(checker.check(&new_incoming_pass));
+    }
+
+    if policy.history_size > 0 {
+        let hashes = user_repo
+            .get_recent_password_hashes(&user, policy.history_size)
+            .await?;
+
+        // one argon2 verify per old hash
+        let history_policy = policy.clone();
+        let password = new_incoming_pass.clone();
+        let reused = app_state
+            .password_hasher
+            .run(move || history_policy.check_history(&password, &hashes))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        violations.extend(reused);
+    }
+
+    if !violations.is_empty() {
+        return Err(HttpError::password_policy(violations));
+    }
+
+    let hashed_password = app_state
+        .password_hasher
+        .hash(&new_incoming_pass)
         .await
-        .map_err(|e| e)?;
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let new_token_version = user_repo
+        .update_loggedIn_user_pass(user.id, hashed_password, policy.history_size)
+        .await?;
+
+    // every session is signed out now , so this client gets a fresh session and tokens
+    user.token_version = new_token_version;
+
+    // password is already changed , so the mail is sent in background and its error is only logged
+    let mail_vars = vec![
+        user.name.clone(),
+        Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+        client
+            .ip_address
+            .clone()
+            .unwrap_or_else(|| "an unknown device".to_string()),
+    ];
+    let to = user.email.clone();
+
+    tokio::spawn(async move {
+        if let Err(e) = construct_mail(&to, &mail_vars, EmailType::PasswordChanged).await {
+            tracing::error!("error while sending password changed mail {}", e);
+        }
+    });
+
+    issue_auth_tokens(
+        &app_state,
+        &user,
+        &client,
+        None,
+        user_data.claims.mfa, // new session keeps the second factor of the session which changed the password
+        StatusCode::OK,
+        "password updated",
+    )
+    .await
+}
+
+/**
+ * changing the email of the logged in user (PUT /api/user/email) , needs a recent authentication
+ * @input => user from auth middleware , new email
+ * @result => email is changed and not verified , every device is logged out , otp is sent to the new email
+ * user verifies the new email like a new signup (verify otp) , old email is told about the change
+ */
+pub async fn change_user_email(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Json(body): Json<ChangeEmailDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = user_data.user;
+
+    if body.new_email == user.email {
+        return Err(HttpError::bad_request(
+            "new email is same as the current email",
+        ));
+    }
+
+    let updated_user = UserRepository::new(app_state.db.clone())
+        .change_user_email(user.id, &body.new_email)
+        .await?;
+
+    send_email_verification_code(&app_state, &updated_user.email).await?;
+
+    // email is already changed , so the mail to the old email is sent in background and its error is only logged
+    let mail_vars = vec![
+        user.name.clone(),
+        updated_user.email.clone(),
+        Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+    ];
+    let to = user.email.clone();
+
+    tokio::spawn(async move {
+        if let Err(e) = construct_mail(&to, &mail_vars, EmailType::EmailChanged).await {
+            tracing::error!("error while sending email changed mail {}", e);
+        }
+    });
 
     Ok(UserOkResponsesDTO {
         status: StatusCode::OK,
-        message: "password updated".to_string(),
+        message: "email changed , please verify the new email with the otp sent to it".to_string(),
+        data: None,
+    })
+}
+
+/**
+ * deleting the account of the logged in user (DELETE /api/user) , needs a recent authentication
+ * @input => user from auth middleware
+ * @result => user is deleted with his notes , sessions , tokens , mfa and passkeys
+ */
+pub async fn delete_user_account(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = UserRepository::new(app_state.db.clone())
+        .delete_user(user_data.user.id)
+        .await?;
+
+    if !deleted {
+        return Err(HttpError::new("user not found", StatusCode::NOT_FOUND));
+    }
+
+    Ok(UserOkResponsesDTO {
+        status: StatusCode::OK,
+        message: "account deleted".to_string(),
         data: None,
     })
 }
@@ -112,13 +542,10 @@ pub async fn create_user_note(
     let mut user_repo = UserRepository::new(db_con_pool);
 
     // saving the usernote to db
-    let noteId = user_repo
-        .create_user_note(userId.clone(), note_data)
-        .await
-        .map_err(|e| e)?;
+    let noteId = user_repo.create_user_note(userId, note_data).await?;
 
-        // let mut data = Vec::new();
-        // data.
+    // let mut data = Vec::new();
+    // data.
     Ok(UserOkResponsesDTO {
         status: StatusCode::CREATED,
         message: "new user note created".to_string(),
@@ -133,7 +560,7 @@ pub async fn create_user_note(
  */
 pub async fn update_user_note(
     Extension(app_state): Extension<Arc<AppState>>,
-    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Extension(_user_data): Extension<JwtAuthMiddleware>,
     Path(noteId): Path<String>,
     Json(updated_user_note): Json<NoteDTO>,
 ) -> Result<impl IntoResponse, HttpError> {
@@ -141,19 +568,17 @@ pub async fn update_user_note(
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let userId = user_data.user.id;
     let db_con_pool = app_state.db.clone();
 
     let noteId_uuid =
-        Uuid::parse_str(&noteId).map_err(|e| HttpError::bad_request("noteId is not a valid Id"))?;
+        Uuid::parse_str(&noteId).map_err(|_| HttpError::bad_request("noteId is not a valid Id"))?;
 
     let mut user_repo = UserRepository::new(db_con_pool);
 
     // updating the usernote to db
-    let noteId = user_repo
+    user_repo
         .update_user_note(noteId_uuid, updated_user_note)
-        .await
-        .map_err(|e| e)?;
+        .await?;
 
     Ok(UserOkResponsesDTO {
         status: StatusCode::OK,
@@ -169,22 +594,18 @@ pub async fn update_user_note(
  */
 pub async fn delete_user_note(
     Extension(app_state): Extension<Arc<AppState>>,
-    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Extension(_user_data): Extension<JwtAuthMiddleware>,
     Path(noteId): Path<String>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let userId = user_data.user.id;
     let db_con_pool = app_state.db.clone();
 
     let noteId_uuid =
-        Uuid::parse_str(&noteId).map_err(|e| HttpError::bad_request("noteId is not a valid Id"))?;
+        Uuid::parse_str(&noteId).map_err(|_| HttpError::bad_request("noteId is not a valid Id"))?;
 
     let mut user_repo = UserRepository::new(db_con_pool);
 
     // deleting the usernote to db
-    let noteId = user_repo
-        .delete_user_note(noteId_uuid)
-        .await
-        .map_err(|e| e)?;
+    user_repo.delete_user_note(noteId_uuid).await?;
 
     Ok(UserOkResponsesDTO {
         status: StatusCode::OK,
@@ -207,10 +628,35 @@ pub async fn get_users_notes(
 
     let mut user_repo = UserRepository::new(db_con);
 
-    let vec_of_users_notes = user_repo
-        .get_user_notes(user_id)
-        .await
-        .map_err(|e| e)?;
+    let vec_of_users_notes = user_repo.get_user_notes(user_id).await?;
+
+    Ok(UserNotesVecResponseDTO {
+        status: StatusCode::OK,
+        message: "success".to_string(),
+        notesVec: vec_of_users_notes,
+    })
+}
+
+/**
+ * notes of some other user , for support staff
+ * @inputs => user from auth middleware (needs notes:read:any permission from his roles) , whose notes in path
+ * @result => notes of that user
+ */
+pub async fn get_any_user_notes(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_obj): Extension<JwtAuthMiddleware>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    user_obj
+        .require_permission(&app_state.db, "notes:read:any")
+        .await?;
+
+    let user_id = Uuid::parse_str(&user_id)
+        .map_err(|_| HttpError::bad_request("userId is not a valid Id"))?;
+
+    let mut user_repo = UserRepository::new(app_state.db.clone());
+
+    let vec_of_users_notes = user_repo.get_user_notes(user_id).await?;
 
     Ok(UserNotesVecResponseDTO {
         status: StatusCode::OK,
@@ -0,0 +1,634 @@
+// we will write passkey (webauthn) handlers here
+// registration is for logged in users , login is passwordless (email + passkey)
+
+use std::sync::Arc;
+
+use axum::{Extension, Json, Router, http::StatusCode, response::IntoResponse, routing::post};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PasskeyAuthentication, PasskeyRegistration,
+    PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse, Webauthn,
+};
+
+use crate::{
+    AppState,
+    db::{auth::AuthRepository, webauthn::WebauthnRepository},
+    dtos::{
+        user_ok_response_dto::UserOkResponsesDTO,
+        webauthn_dto::{
+            WebauthnLoginFinishDTO, WebauthnLoginOptionsDTO, WebauthnRegisterFinishDTO,
+        },
+        webauthn_response_dto::WebauthnOptionsResponseDTO,
+    },
+    errors::HttpError,
+    handler::auth::issue_auth_tokens,
+    middleware::{JwtAuthMiddleware, RequireRecentAuth},
+    models::{
+        NewWebauthnCeremony, NewWebauthnCredential, WebauthnCeremonyKind, WebauthnCredentials,
+    },
+    utils::{client_info::ClientInfo, token::RECENT_AUTH_MAXAGE_MINUTES},
+};
+
+// time user gets to complete the browser/authenticator prompt
+const WEBAUTHN_CEREMONY_MAXAGE_MINUTES: i64 = 5;
+
+// nested in users handler , so auth middleware protects them
+pub fn webauthn_register_handler() -> Router {
+    Router::new()
+        .route(
+            "/register/options",
+            post(start_passkey_registration).layer(RequireRecentAuth(Duration::minutes(
+                RECENT_AUTH_MAXAGE_MINUTES,
+            ))),
+        )
+        .route("/register/finish", post(finish_passkey_registration))
+}
+
+// nested in auth handler , no token needed
+pub fn webauthn_login_handler() -> Router {
+    Router::new()
+        .route("/login/options", post(start_passkey_login))
+        .route("/login/finish", post(finish_passkey_login))
+}
+
+// passkeys are saved as json , we parse them back for the ceremonies
+fn parse_passkeys(credentials: &[WebauthnCredentials]) -> Result<Vec<Passkey>, HttpError> {
+    credentials
+        .iter()
+        .map(|credential| {
+            serde_json::from_str::<Passkey>(&credential.passkey)
+                .map_err(|_| HttpError::server_error("error while reading saved passkey"))
+        })
+        .collect()
+}
+
+/**
+ * registration ceremony step one , without db
+ * @inputs => user and his saved passkeys
+ * @result => creation options for the browser and the state json to save with the ceremony
+ */
+fn registration_options(
+    webauthn: &Webauthn,
+    user_id: Uuid,
+    email: &str,
+    name: &str,
+    credentials: &[WebauthnCredentials],
+) -> Result<(CreationChallengeResponse, String), HttpError> {
+    let exclude_credentials = parse_passkeys(credentials)?
+        .iter()
+        .map(|passkey| passkey.cred_id().clone())
+        .collect();
+
+    let (options, registration_state) = webauthn
+        .start_passkey_registration(user_id, email, name, Some(exclude_credentials))
+        .map_err(|_| HttpError::server_error("error while starting passkey registration"))?;
+
+    let state = serde_json::to_string(&registration_state)
+        .map_err(|_| HttpError::server_error("error while saving passkey registration"))?;
+
+    Ok((options, state))
+}
+
+/**
+ * registration ceremony step two , without db
+ * @inputs => saved state json of the ceremony and the credential created by the authenticator
+ * @result => credential id (url safe base64) and the passkey json to save
+ */
+fn registered_passkey(
+    webauthn: &Webauthn,
+    state: &str,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<(String, String), HttpError> {
+    let registration_state = serde_json::from_str::<PasskeyRegistration>(state)
+        .map_err(|_| HttpError::server_error("error while reading passkey registration"))?;
+
+    let passkey = webauthn
+        .finish_passkey_registration(credential, &registration_state)
+        .map_err(|_| HttpError::bad_request("passkey verification failed"))?;
+
+    let passkey_json = serde_json::to_string(&passkey)
+        .map_err(|_| HttpError::server_error("error while saving passkey"))?;
+
+    Ok((URL_SAFE_NO_PAD.encode(passkey.cred_id()), passkey_json))
+}
+
+/**
+ * login ceremony step one , without db
+ * @inputs => saved passkeys of the user , hide_credentials for enumeration safe mode
+ * @result => request options for the browser and the state json to save with the ceremony
+ * saved state still allows only these passkeys when they are hidden from the options , so finish checks the owner
+ */
+fn login_options(
+    webauthn: &Webauthn,
+    credentials: &[WebauthnCredentials],
+    hide_credentials: bool,
+) -> Result<(RequestChallengeResponse, String), HttpError> {
+    let passkeys = parse_passkeys(credentials)?;
+
+    let (mut options, authentication_state) = webauthn
+        .start_passkey_authentication(&passkeys)
+        .map_err(|_| HttpError::server_error("error while starting passkey login"))?;
+
+    if hide_credentials {
+        options.public_key.allow_credentials.clear();
+    }
+
+    let state = serde_json::to_string(&authentication_state)
+        .map_err(|_| HttpError::server_error("error while saving passkey login"))?;
+
+    Ok((options, state))
+}
+
+/**
+ * login ceremony step two , without db
+ * @inputs => saved state json of the ceremony , assertion from the authenticator and passkeys of the ceremony owner
+ * @result => id of the used credential row and its passkey json with updated sign counter
+ */
+fn verified_passkey_login(
+    webauthn: &Webauthn,
+    state: &str,
+    credential: &PublicKeyCredential,
+    credentials: &[WebauthnCredentials],
+) -> Result<(Uuid, String), HttpError> {
+    let authentication_state = serde_json::from_str::<PasskeyAuthentication>(state)
+        .map_err(|_| HttpError::server_error("error while reading passkey login"))?;
+
+    let auth_result = webauthn
+        .finish_passkey_authentication(credential, &authentication_state)
+        .map_err(|_| HttpError::unauthorized("passkey verification failed"))?;
+
+    // used passkey must be one of the owners passkeys
+    let credential_id = URL_SAFE_NO_PAD.encode(auth_result.cred_id());
+
+    let credential = credentials
+        .iter()
+        .find(|credential| credential.credential_id == credential_id)
+        .ok_or_else(|| HttpError::unauthorized("passkey not found"))?;
+
+    let mut passkey = serde_json::from_str::<Passkey>(&credential.passkey)
+        .map_err(|_| HttpError::server_error("error while reading saved passkey"))?;
+
+    passkey.update_credential(&auth_result);
+
+    let passkey_json = serde_json::to_string(&passkey)
+        .map_err(|_| HttpError::server_error("error while saving passkey"))?;
+
+    Ok((credential.id, passkey_json))
+}
+
+/**
+ * starting passkey registration for the logged in user
+ * @input => user from auth middleware
+ * @result => creation options for the browser , his already registered passkeys are excluded
+ */
+pub async fn start_passkey_registration(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let user = user_data.user;
+
+    let mut webauthn_repo = WebauthnRepository::new(app_state.db.clone());
+
+    let credentials = webauthn_repo.get_user_credentials(user.id).await?;
+
+    let (options, state) = registration_options(
+        &app_state.webauthn,
+        user.id,
+        &user.email,
+        &user.name,
+        &credentials,
+    )?;
+
+    let ceremony_id = webauthn_repo
+        .save_ceremony(NewWebauthnCeremony {
+            user_id: user.id,
+            ceremony_type: WebauthnCeremonyKind::Registration,
+            state,
+            expires_at: Utc::now() + Duration::minutes(WEBAUTHN_CEREMONY_MAXAGE_MINUTES),
+        })
+        .await?;
+
+    Ok(WebauthnOptionsResponseDTO {
+        status: StatusCode::OK,
+        message: "passkey registration started".to_string(),
+        ceremony_id: ceremony_id.to_string(),
+        options,
+    })
+}
+
+/**
+ * finishing passkey registration
+ * @input => user from auth middleware , ceremony id and the credential created by the authenticator
+ * @result => passkey is saved for the user
+ */
+pub async fn finish_passkey_registration(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user_data): Extension<JwtAuthMiddleware>,
+    Json(body): Json<WebauthnRegisterFinishDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = user_data.user;
+
+    let mut webauthn_repo = WebauthnRepository::new(app_state.db.clone());
+
+    // ceremony must be started by this same user
+    let ceremony = webauthn_repo
+        .take_ceremony(
+            body.ceremony_id,
+            WebauthnCeremonyKind::Registration,
+            Some(user.id),
+        )
+        .await?
+        .ok_or_else(|| HttpError::bad_request("passkey registration is invalid or expired"))?;
+
+    let (credential_id, passkey) =
+        registered_passkey(&app_state.webauthn, &ceremony.state, &body.credential)?;
+
+    webauthn_repo
+        .save_credential(NewWebauthnCredential {
+            user_id: user.id,
+            credential_id,
+            passkey,
+            name: body.name,
+        })
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        UserOkResponsesDTO {
+            status: StatusCode::CREATED,
+            message: "passkey registered".to_string(),
+            data: None,
+        },
+    ))
+}
+
+/**
+ * starting passwordless login with a passkey
+ * @input => email of the user
+ * @result => request options for the browser with passkeys of this user
+ * in enumeration safe mode every email gets the same looking options with empty allowCredentials (browser offers its own passkeys) ,
+ * for unknown , unverified or passkey less users the ceremony is not saved , so it fails at finish like a wrong passkey
+ */
+pub async fn start_passkey_login(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<WebauthnLoginOptionsDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let enumeration_safe = app_state.config.enumeration_safe_auth;
+
+    let user = match AuthRepository::new(app_state.db.clone())
+        .get_user_from_email(&body.email)
+        .await
+    {
+        Ok(user) => Some(user),
+        Err(e) if e.status == StatusCode::NOT_FOUND && enumeration_safe => None,
+        Err(e) => return Err(e),
+    };
+
+    let mut webauthn_repo = WebauthnRepository::new(app_state.db.clone());
+
+    let credentials = match &user {
+        Some(user) if user.verified => webauthn_repo.get_user_credentials(user.id).await?,
+        Some(_) if !enumeration_safe => {
+            return Err(HttpError::unauthorized(
+                "users email is not verfied , please sign and verify email",
+            ));
+        }
+        _ => Vec::new(),
+    };
+
+    let user = match user {
+        Some(user) if !credentials.is_empty() => user,
+        _ if enumeration_safe => return unsaved_passkey_login(&app_state.webauthn),
+        _ => {
+            return Err(HttpError::bad_request(
+                "no passkey registered for this user",
+            ));
+        }
+    };
+
+    let (options, state) = login_options(&app_state.webauthn, &credentials, enumeration_safe)?;
+
+    let ceremony_id = webauthn_repo
+        .save_ceremony(NewWebauthnCeremony {
+            user_id: user.id,
+            ceremony_type: WebauthnCeremonyKind::Authentication,
+            state,
+            expires_at: Utc::now() + Duration::minutes(WEBAUTHN_CEREMONY_MAXAGE_MINUTES),
+        })
+        .await?;
+
+    Ok(WebauthnOptionsResponseDTO {
+        status: StatusCode::OK,
+        message: "passkey login started".to_string(),
+        ceremony_id: ceremony_id.to_string(),
+        options,
+    })
+}
+
+/**
+ * passkey login options which can never be finished , for emails which can not login with a passkey (enumeration safe mode)
+ * @result => same shape as real options (fresh challenge , no allowCredentials) with a ceremony id which is not saved
+ */
+fn unsaved_passkey_login(
+    webauthn: &Webauthn,
+) -> Result<WebauthnOptionsResponseDTO<RequestChallengeResponse>, HttpError> {
+    // no passkeys , so allowCredentials is empty like in the real options of this mode
+    let (options, _) = webauthn
+        .start_passkey_authentication(&[])
+        .map_err(|_| HttpError::server_error("error while starting passkey login"))?;
+
+    Ok(WebauthnOptionsResponseDTO {
+        status: StatusCode::OK,
+        message: "passkey login started".to_string(),
+        ceremony_id: Uuid::new_v4().to_string(),
+        options,
+    })
+}
+
+/**
+ * finishing passkey login
+ * @input => ceremony id , assertion from the authenticator and optional device name
+ * @result => same session and tokens as password login
+ * passkey needs user verification (pin/biometric) on the device , so it is already two factors and totp is not asked
+ */
+pub async fn finish_passkey_login(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<WebauthnLoginFinishDTO>,
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let mut webauthn_repo = WebauthnRepository::new(app_state.db.clone());
+
+    let ceremony = webauthn_repo
+        .take_ceremony(body.ceremony_id, WebauthnCeremonyKind::Authentication, None)
+        .await?
+        .ok_or_else(|| HttpError::unauthorized("passkey login is invalid or expired"))?;
+
+    let credentials = webauthn_repo.get_user_credentials(ceremony.user_id).await?;
+
+    // updating sign counter of the used passkey
+    let (credential_id, passkey) = verified_passkey_login(
+        &app_state.webauthn,
+        &ceremony.state,
+        &body.credential,
+        &credentials,
+    )?;
+
+    webauthn_repo
+        .update_credential_after_login(credential_id, passkey)
+        .await?;
+
+    let user = AuthRepository::new(app_state.db.clone())
+        .get_user(ceremony.user_id)
+        .await?;
+
+    issue_auth_tokens(
+        &app_state,
+        &user,
+        &client,
+        body.device_name,
+        false,
+        StatusCode::ACCEPTED,
+        "user loggedIn successfully",
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use webauthn_authenticator_rs::{WebauthnAuthenticator, softpasskey::SoftPasskey};
+    use webauthn_rs::prelude::{Url, WebauthnBuilder};
+
+    use super::*;
+
+    // same rp as the default config (WEBAUTHN_RP_ID , WEBAUTHN_RP_ORIGIN)
+    fn webauthn() -> (Webauthn, Url) {
+        let origin = Url::parse("http://localhost:8080").unwrap();
+        let webauthn = WebauthnBuilder::new("localhost", &origin)
+            .and_then(|builder| builder.rp_name("rust_auth_full").build())
+            .unwrap();
+
+        (webauthn, origin)
+    }
+
+    // software authenticator , it reports user verification like a device with pin/biometric
+    fn authenticator() -> WebauthnAuthenticator<SoftPasskey> {
+        WebauthnAuthenticator::new(SoftPasskey::new(true))
+    }
+
+    /**
+     * registration through the same steps as the register handlers , finish body is parsed like the frontend posts it
+     * @result => credential row like the one saved in the db
+     */
+    fn register(
+        webauthn: &Webauthn,
+        origin: &Url,
+        authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+        user_id: Uuid,
+    ) -> WebauthnCredentials {
+        let (options, state) =
+            registration_options(webauthn, user_id, "staff@example.com", "staff", &[]).unwrap();
+
+        let credential = authenticator
+            .do_registration(origin.clone(), options)
+            .unwrap();
+
+        let body: WebauthnRegisterFinishDTO = serde_json::from_value(json!({
+            "ceremony_id": Uuid::new_v4(),
+            "credential": credential,
+            "name": "work laptop",
+        }))
+        .unwrap();
+        body.validate().unwrap();
+
+        let (credential_id, passkey) =
+            registered_passkey(webauthn, &state, &body.credential).unwrap();
+
+        WebauthnCredentials {
+            id: Uuid::new_v4(),
+            user_id,
+            credential_id,
+            passkey,
+            name: body.name,
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    /**
+     * assertion of the authenticator for these options , parsed like the frontend posts it to /login/finish
+     * @result => None if the authenticator has no key for the options
+     */
+    fn assertion(
+        origin: &Url,
+        authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+        options: RequestChallengeResponse,
+    ) -> Option<PublicKeyCredential> {
+        let credential = authenticator
+            .do_authentication(origin.clone(), options)
+            .ok()?;
+
+        let body: WebauthnLoginFinishDTO = serde_json::from_value(json!({
+            "ceremony_id": Uuid::new_v4(),
+            "credential": credential,
+            "device_name": "work laptop",
+        }))
+        .unwrap();
+        body.validate().unwrap();
+
+        Some(body.credential)
+    }
+
+    #[test]
+    fn passkey_register_then_login_round_trip() {
+        let (webauthn, origin) = webauthn();
+        let mut authenticator = authenticator();
+
+        let mut saved = register(&webauthn, &origin, &mut authenticator, Uuid::new_v4());
+
+        for _ in 0..2 {
+            let (options, state) =
+                login_options(&webauthn, std::slice::from_ref(&saved), false).unwrap();
+            let credential = assertion(&origin, &mut authenticator, options).unwrap();
+
+            let (credential_id, passkey) = verified_passkey_login(
+                &webauthn,
+                &state,
+                &credential,
+                std::slice::from_ref(&saved),
+            )
+            .unwrap();
+            assert_eq!(credential_id, saved.id);
+
+            // updated passkey (sign counter) is saved and used for the next login
+            saved.passkey = passkey;
+        }
+    }
+
+    #[test]
+    fn registration_excludes_already_saved_passkeys() {
+        let (webauthn, origin) = webauthn();
+        let user_id = Uuid::new_v4();
+
+        let saved = register(&webauthn, &origin, &mut authenticator(), user_id);
+
+        let (options, _) = registration_options(
+            &webauthn,
+            user_id,
+            "staff@example.com",
+            "staff",
+            &[saved.clone()],
+        )
+        .unwrap();
+
+        let excluded: Vec<String> = options
+            .public_key
+            .exclude_credentials
+            .unwrap_or_default()
+            .iter()
+            .map(|credential| URL_SAFE_NO_PAD.encode(&credential.id))
+            .collect();
+        assert_eq!(excluded, [saved.credential_id]);
+    }
+
+    #[test]
+    fn passkey_of_other_authenticator_can_not_login() {
+        let (webauthn, origin) = webauthn();
+        let owner = register(&webauthn, &origin, &mut authenticator(), Uuid::new_v4());
+
+        // authenticator which never registered has no key for the allowed credential
+        let (options, _) = login_options(&webauthn, &[owner.clone()], false).unwrap();
+        assert!(assertion(&origin, &mut authenticator(), options).is_none());
+
+        // other users passkey , while the ceremony was started for the owner
+        let mut other_authenticator = authenticator();
+        let other = register(&webauthn, &origin, &mut other_authenticator, Uuid::new_v4());
+
+        let (_, owner_state) = login_options(&webauthn, &[owner.clone()], true).unwrap();
+        let (other_options, _) = login_options(&webauthn, &[other], false).unwrap();
+        let credential = assertion(&origin, &mut other_authenticator, other_options).unwrap();
+
+        let error =
+            verified_passkey_login(&webauthn, &owner_state, &credential, &[owner]).unwrap_err();
+        assert_eq!(error.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn enumeration_safe_options_hide_passkeys_but_finish_checks_the_owner() {
+        let (webauthn, origin) = webauthn();
+        let mut authenticator = authenticator();
+        let saved = register(&webauthn, &origin, &mut authenticator, Uuid::new_v4());
+
+        let (shown, _) = login_options(&webauthn, &[saved.clone()], false).unwrap();
+        let (mut hidden, state) = login_options(&webauthn, &[saved.clone()], true).unwrap();
+        assert!(hidden.public_key.allow_credentials.is_empty());
+
+        // browser offers its own passkeys for empty allowCredentials , soft authenticator needs the list back
+        hidden.public_key.allow_credentials = shown.public_key.allow_credentials;
+        let credential = assertion(&origin, &mut authenticator, hidden).unwrap();
+
+        // passkey is verified but it is not in the owners passkeys (deleted meanwhile)
+        let error = verified_passkey_login(&webauthn, &state, &credential, &[]).unwrap_err();
+        assert_eq!(error.status, StatusCode::UNAUTHORIZED);
+
+        let (credential_id, _) =
+            verified_passkey_login(&webauthn, &state, &credential, &[saved.clone()]).unwrap();
+        assert_eq!(credential_id, saved.id);
+    }
+
+    #[test]
+    fn unsaved_login_looks_like_a_real_hidden_one() {
+        let (webauthn, origin) = webauthn();
+        let saved = register(&webauthn, &origin, &mut authenticator(), Uuid::new_v4());
+
+        let (real, _) = login_options(&webauthn, &[saved], true).unwrap();
+        let unsaved = unsaved_passkey_login(&webauthn).unwrap();
+        let again = unsaved_passkey_login(&webauthn).unwrap();
+
+        assert_eq!(unsaved.status, StatusCode::OK);
+        assert_eq!(unsaved.message, "passkey login started");
+        assert!(Uuid::parse_str(&unsaved.ceremony_id).is_ok());
+        assert_ne!(unsaved.ceremony_id, again.ceremony_id);
+        assert_ne!(
+            unsaved.options.public_key.challenge,
+            again.options.public_key.challenge
+        );
+
+        // same json keys and same allowCredentials , only challenge differs
+        let mut real = serde_json::to_value(&real).unwrap();
+        let mut unsaved = serde_json::to_value(&unsaved.options).unwrap();
+        real["publicKey"]["challenge"] = json!(null);
+        unsaved["publicKey"]["challenge"] = json!(null);
+        assert_eq!(real, unsaved);
+    }
+
+    #[test]
+    fn state_of_other_ceremony_type_is_refused() {
+        let (webauthn, origin) = webauthn();
+        let mut authenticator = authenticator();
+        let saved = register(&webauthn, &origin, &mut authenticator, Uuid::new_v4());
+
+        let (_, registration_state) =
+            registration_options(&webauthn, saved.user_id, "staff@example.com", "staff", &[])
+                .unwrap();
+
+        let (options, _) = login_options(&webauthn, &[saved.clone()], false).unwrap();
+        let credential = assertion(&origin, &mut authenticator, options).unwrap();
+
+        let error = verified_passkey_login(&webauthn, &registration_state, &credential, &[saved])
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
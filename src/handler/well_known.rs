@@ -0,0 +1,29 @@
+// public endpoints other services use to verify our tokens , no auth needed
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+
+use crate::AppState;
+
+pub fn well_known_handler() -> Router {
+    Router::new().route("/jwks.json", get(get_jwks))
+}
+
+/**
+ * returning public keys (current signing key and older keys still accepted) in jwks format
+ * with HS256 there is no public key , so keys list is empty
+ * verifiers can cache it for some time , new keys are added here before they start signing
+ */
+pub async fn get_jwks(Extension(app_state): Extension<Arc<AppState>>) -> Response {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(app_state.token_service.jwks().clone()),
+    )
+        .into_response()
+}
@@ -0,0 +1,161 @@
+// importing users of the old system , run as => backend import-users <file.csv | file.jsonl>
+// every row has email , name , password_hash and verified , csv needs a header row with these names
+// old hashes (bcrypt , scrypt , pbkdf2-sha256 or argon2) are saved as they are , users login with their old password
+// and the hash is replaced with our argon2id hash on the first successful login
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use serde::Deserialize;
+use validator::ValidateEmail;
+
+use crate::{
+    DbPool, db::users::UserRepository, models::NewUser, utils::password::is_supported_hash,
+};
+
+// same limits as the users table columns
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_EMAIL_LENGTH: usize = 255;
+const MAX_HASH_LENGTH: usize = 255;
+
+// (line number , parsed row or why it can not be parsed)
+type ImportRow = (usize, Result<ImportedUser, String>);
+
+#[derive(Deserialize)]
+struct ImportedUser {
+    email: String,
+    name: String,
+    password_hash: String,
+    #[serde(default)]
+    verified: bool,
+}
+
+/**
+ * reading , checking and saving the users of the file
+ * rows which can not be imported are printed with their line and skipped , rest of the file is still imported
+ * @result => error if the file can not be read or saving fails
+ */
+pub async fn import_users(pool: DbPool, path: &str) -> Result<(), String> {
+    let rows = read_rows(Path::new(path))?;
+    let total = rows.len();
+
+    let mut new_users = Vec::with_capacity(total);
+    let mut invalid = 0;
+
+    for (line, row) in rows {
+        match row.and_then(check_row) {
+            Ok(new_user) => new_users.push(new_user),
+            Err(e) => {
+                invalid += 1;
+                tracing::warn!("line {} is skipped , {}", line, e);
+            }
+        }
+    }
+
+    let checked = new_users.len();
+
+    let mut user_repo = UserRepository::new(pool);
+
+    let saved = user_repo
+        .import_users(new_users)
+        .await
+        .map_err(|e| e.message)?;
+
+    tracing::info!(
+        "{} rows , {} users imported , {} already existing skipped , {} invalid skipped",
+        total,
+        saved,
+        checked - saved,
+        invalid
+    );
+
+    Ok(())
+}
+
+/**
+ * file type comes from the extension , .csv or .jsonl (.ndjson)
+ * @result => rows of the file
+ */
+fn read_rows(path: &Path) -> Result<Vec<ImportRow>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {} {}", path.display(), e))?;
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("csv") => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(file);
+
+            Ok(reader
+                .deserialize::<ImportedUser>()
+                .enumerate()
+                .map(|(index, row)| {
+                    // line of the csv error is more exact (quoted fields can have new lines)
+                    let line = row
+                        .as_ref()
+                        .err()
+                        .and_then(|e| e.position())
+                        .map(|position| position.line() as usize)
+                        .unwrap_or(index + 2);
+
+                    (line, row.map_err(|e| e.to_string()))
+                })
+                .collect())
+        }
+        Some("jsonl") | Some("ndjson") => {
+            let mut rows = Vec::new();
+
+            for (index, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.map_err(|e| format!("cannot read {} {}", path.display(), e))?;
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                rows.push((
+                    index + 1,
+                    serde_json::from_str::<ImportedUser>(&line).map_err(|e| e.to_string()),
+                ));
+            }
+
+            Ok(rows)
+        }
+        _ => Err("import file should be .csv or .jsonl".to_string()),
+    }
+}
+
+/**
+ * same checks as register (email , name) , and the hash should be one which we can verify
+ */
+fn check_row(row: ImportedUser) -> Result<NewUser, String> {
+    let email = row.email.trim().to_string();
+    let name = row.name.trim().to_string();
+    let password_hash = row.password_hash.trim().to_string();
+
+    if email.len() > MAX_EMAIL_LENGTH || !email.validate_email() {
+        return Err(format!("{} is not a valid email", email));
+    }
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "name of {} should be 1 to {} characters",
+            email, MAX_NAME_LENGTH
+        ));
+    }
+
+    if password_hash.len() > MAX_HASH_LENGTH || !is_supported_hash(&password_hash) {
+        return Err(format!(
+            "password hash of {} is not a supported bcrypt , scrypt , pbkdf2-sha256 or argon2 hash",
+            email
+        ));
+    }
+
+    Ok(NewUser {
+        name,
+        email,
+        verified: row.verified,
+        password: password_hash,
+    })
+}
@@ -14,6 +14,12 @@ pub struct EmailData {
 pub enum EmailType {
     NewUserEmailVerification,
     ResetPasswordEmailVerification,
+    LoginCode,
+    AccountLocked,
+    MfaLocked,
+    ExistingAccountSignup,
+    PasswordChanged,
+    EmailChanged,
 }
 
 /**
@@ -29,7 +35,7 @@ pub async fn construct_mail(
     let reciever = to.into();
 
     // getting the email data(subject and content)
-    let email_data = render_email(email_type.clone(), variables).map_err(|e| e)?;
+    let email_data = render_email(email_type.clone(), variables)?;
 
     // this will send email to the user(reciever) and return true or errror
     let result = send_mail(
@@ -37,8 +43,7 @@ pub async fn construct_mail(
         email_data.content.to_string(),
         email_data.subject.to_string(),
     )
-    .await
-    .map_err(|e| e)?;
+    .await?;
 
     Ok(result)
 }
@@ -50,7 +55,7 @@ fn render_email(email_type: EmailType, vars: &[String]) -> Result<EmailData, Htt
     match email_type {
         EmailType::NewUserEmailVerification => {
             let data = EmailData {
-                content: format! {"Hi {},\n\nWelcome to RustAuth! We are glad to have you. \n\nYour OTP for email verification is {}" , vars.get(1).ok_or_else(|| HttpError::bad_request("user name missing".to_string()))? , vars.get(0).ok_or_else(|| HttpError::bad_request("otp is missing"))?},
+                content: format! {"Hi {},\n\nWelcome to RustAuth! We are glad to have you. \n\nYour OTP for email verification is {}" , vars.get(1).ok_or_else(|| HttpError::bad_request("user name missing".to_string()))? , vars.first().ok_or_else(|| HttpError::bad_request("otp is missing"))?},
                 subject: "Welcome to RustAuth 🎉".to_string(),
             };
 
@@ -60,12 +65,98 @@ fn render_email(email_type: EmailType, vars: &[String]) -> Result<EmailData, Htt
             let data = EmailData {
                 content: format!(
                     "Your password reset token is {}",
-                    vars.get(0)
+                    vars.first()
                         .ok_or_else(|| HttpError::bad_request("otp missing"))?
                 ),
                 subject: "Reset your password".to_string(),
             };
 
+            Ok(data)
+        }
+        EmailType::LoginCode => {
+            let data = EmailData {
+                content: format!(
+                    "Your login code is {}\n\nor login with this link {}\n\nIf you did not try to login , you can ignore this email",
+                    vars.first()
+                        .ok_or_else(|| HttpError::bad_request("otp missing"))?,
+                    vars.get(1)
+                        .ok_or_else(|| HttpError::bad_request("login link missing"))?
+                ),
+                subject: "Your RustAuth login code".to_string(),
+            };
+
+            Ok(data)
+        }
+        EmailType::AccountLocked => {
+            let data = EmailData {
+                content: format!(
+                    "Hi {},\n\nWe locked your account for {} minutes after too many wrong passwords.\n\nIf this was not you , please reset your password once the lock ends , or contact support to unlock it",
+                    vars.first()
+                        .ok_or_else(|| HttpError::bad_request("user name missing"))?,
+                    vars.get(1)
+                        .ok_or_else(|| HttpError::bad_request("lock time missing"))?
+                ),
+                subject: "Your RustAuth account is locked".to_string(),
+            };
+
+            Ok(data)
+        }
+        EmailType::MfaLocked => {
+            let data = EmailData {
+                content: format!(
+                    "Hi {},\n\nWe locked two factor login of your account for {} minutes after too many wrong codes.\n\nThe password was correct , so if this was not you , please reset your password right away",
+                    vars.first()
+                        .ok_or_else(|| HttpError::bad_request("user name missing"))?,
+                    vars.get(1)
+                        .ok_or_else(|| HttpError::bad_request("lock time missing"))?
+                ),
+                subject: "Two factor login of your RustAuth account is locked".to_string(),
+            };
+
+            Ok(data)
+        }
+        EmailType::ExistingAccountSignup => {
+            let data = EmailData {
+                content: format!(
+                    "Hi {},\n\nSomeone tried to sign up with this email , but you already have an account.\n\nIf this was you , please login or reset your password instead. If not , you can ignore this email",
+                    vars.first()
+                        .ok_or_else(|| HttpError::bad_request("user name missing"))?
+                ),
+                subject: "You already have a RustAuth account".to_string(),
+            };
+
+            Ok(data)
+        }
+        EmailType::PasswordChanged => {
+            let data = EmailData {
+                content: format!(
+                    "Hi {},\n\nThe password of your account was changed at {} from {}.\n\nAll your devices are logged out. If this was not you , please reset your password right away",
+                    vars.first()
+                        .ok_or_else(|| HttpError::bad_request("user name missing"))?,
+                    vars.get(1)
+                        .ok_or_else(|| HttpError::bad_request("change time missing"))?,
+                    vars.get(2)
+                        .ok_or_else(|| HttpError::bad_request("device missing"))?
+                ),
+                subject: "Your RustAuth password was changed".to_string(),
+            };
+
+            Ok(data)
+        }
+        EmailType::EmailChanged => {
+            let data = EmailData {
+                content: format!(
+                    "Hi {},\n\nThe email of your account was changed to {} at {}.\n\nAll your devices are logged out. If this was not you , please contact support right away",
+                    vars.first()
+                        .ok_or_else(|| HttpError::bad_request("user name missing"))?,
+                    vars.get(1)
+                        .ok_or_else(|| HttpError::bad_request("new email missing"))?,
+                    vars.get(2)
+                        .ok_or_else(|| HttpError::bad_request("change time missing"))?
+                ),
+                subject: "Your RustAuth email was changed".to_string(),
+            };
+
             Ok(data)
         }
     }
@@ -5,29 +5,42 @@ mod db;
 mod dtos;
 mod errors;
 mod handler;
+mod import;
 mod mail;
 mod middleware;
 mod models;
+mod rate_limit;
 mod schema;
 mod utils;
-use axum::{
-    Extension, Router,
-    http::{
-        HeaderValue, Method,
-        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
-    },
-    serve::Listener,
+use axum::http::{
+    HeaderValue, Method,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
 };
 mod routes;
 use diesel::{
-    Connection, PgConnection,
+    PgConnection,
     r2d2::{ConnectionManager, Pool},
 };
 use tower_http::cors::CorsLayer;
-
-use crate::{config::Config, routes::create_router};
+use webauthn_rs::prelude::{Url, Webauthn, WebauthnBuilder};
+
+use crate::{
+    config::Config,
+    db::{
+        mfa::MfaRepository, otp::OtpService, rate_limits::RateLimitRepository,
+        sessions::SessionRepository, throttle::ThrottleRepository, tokens::TokenRepository,
+        webauthn::WebauthnRepository,
+    },
+    routes::create_router,
+    utils::{
+        breached_passwords::BreachedPasswordChecker,
+        password::{PasswordHasherConfig, PasswordHashingService, init_password_hasher},
+        token::{REFRESH_TOKEN_MAXAGE_DAYS, TokenService},
+    },
+};
+use chrono::Utc;
 use dotenvy::dotenv;
-use std::{clone, env, sync::Arc};
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
 
 // parkinglot/pool of db connection
 // type for data base pool/collection_of_db_connection
@@ -37,6 +50,11 @@ pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 #[derive(Clone)]
 pub struct AppState {
     pub db: DbPool,
+    pub config: Arc<Config>,
+    pub token_service: Arc<TokenService>, // creates and checks access tokens (keys , lifetime , issuer , audience)
+    pub webauthn: Arc<Webauthn>,          // relying party config for passkey ceremonies
+    pub breached_passwords: Option<Arc<BreachedPasswordChecker>>, // None when no corpus is configured
+    pub password_hasher: Arc<PasswordHashingService>, // argon2 work of the handlers runs here , not on tokio workers
 }
 
 #[tokio::main]
@@ -58,24 +76,152 @@ async fn main() {
 
     println!("we got the db configs ");
 
+    // argon2 costs and pepper are set before any password is hashed
+    let pepper = config.password_pepper_file.as_ref().map(|path| {
+        std::fs::read(path)
+            .expect("cannot read PASSWORD_PEPPER_FILE")
+            .trim_ascii()
+            .to_vec()
+    });
+
+    let old_peppers = config
+        .password_old_pepper_files
+        .iter()
+        .map(|(id, path)| {
+            let pepper = std::fs::read(path)
+                .unwrap_or_else(|_| panic!("cannot read old pepper file of {}", id))
+                .trim_ascii()
+                .to_vec();
+            (id.clone(), pepper)
+        })
+        .collect();
+
+    init_password_hasher(PasswordHasherConfig {
+        memory_kib: config.argon2_memory_kib,
+        iterations: config.argon2_iterations,
+        parallelism: config.argon2_parallelism,
+        pepper,
+        pepper_id: config.password_pepper_id.clone(),
+        old_peppers,
+    })
+    .expect("invalid password hasher config");
+
+    // loading jwt keys and token settings once , server should not start with bad keys
+    let token_service = TokenService::from_config(&config).expect("failed to load jwt config");
+
+    // passkeys are bound to this rp id and origin
+    let rp_origin =
+        Url::parse(&config.webauthn_rp_origin).expect("WEBAUTHN_RP_ORIGIN must be a url");
+    let webauthn = WebauthnBuilder::new(&config.webauthn_rp_id, &rp_origin)
+        .and_then(|builder| builder.rp_name(&config.webauthn_rp_name).build())
+        .expect("failed to create webauthn config");
+
     // creating pool of db connection
-    let manager = ConnectionManager::<PgConnection>::new(config.database_url);
+    let manager = ConnectionManager::<PgConnection>::new(config.database_url.clone());
     let pool = Pool::builder()
         .max_size(3)
         .build(manager)
         .expect("failer to create database pool");
 
+    // backend import-users <file> => importing users of the old system , server is not started
+    if let Some(command) = env::args().nth(1) {
+        match (command.as_str(), env::args().nth(2)) {
+            ("import-users", Some(path)) => {
+                if let Err(e) = import::import_users(pool, &path).await {
+                    tracing::error!("import failed , {}", e);
+                    std::process::exit(1);
+                }
+            }
+            _ => {
+                tracing::error!("usage => backend [import-users <file.csv | file.jsonl>]");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
     // cors setup
     let cors = CorsLayer::new()
         .allow_origin("*".parse::<HeaderValue>().unwrap())
         .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE])
         // .allow_credentials(true)
-        .allow_methods([Method::GET, Method::POST, Method::PUT , Method::DELETE]);
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE]);
+
+    // deleting expired revoked tokens , refresh tokens , stale sessions , mfa challenges and webauthn ceremonies every hour , so these tables do not grow forever
+    let prune_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            let mut token_repo = TokenRepository::new(prune_pool.clone());
+            match token_repo.prune_expired_tokens().await {
+                Ok(count) => tracing::info!("pruned {} expired tokens", count),
+                Err(e) => tracing::error!("error while pruning expired tokens {}", e),
+            }
+
+            // a session not used for the whole refresh token life cannot be used anymore
+            let inactive_since = Utc::now() - chrono::Duration::days(REFRESH_TOKEN_MAXAGE_DAYS);
+            let mut session_repo = SessionRepository::new(prune_pool.clone());
+            match session_repo.prune_stale_sessions(inactive_since).await {
+                Ok(count) => tracing::info!("pruned {} stale sessions", count),
+                Err(e) => tracing::error!("error while pruning stale sessions {}", e),
+            }
+
+            let mut mfa_repo = MfaRepository::new(prune_pool.clone());
+            match mfa_repo.prune_expired_challenges().await {
+                Ok(count) => tracing::info!("pruned {} mfa challenges", count),
+                Err(e) => tracing::error!("error while pruning mfa challenges {}", e),
+            }
+
+            let mut webauthn_repo = WebauthnRepository::new(prune_pool.clone());
+            match webauthn_repo.prune_expired_ceremonies().await {
+                Ok(count) => tracing::info!("pruned {} webauthn ceremonies", count),
+                Err(e) => tracing::error!("error while pruning webauthn ceremonies {}", e),
+            }
+
+            let mut otp_service = OtpService::new(prune_pool.clone());
+            match otp_service.prune_expired_codes().await {
+                Ok(count) => tracing::info!("pruned {} one time codes", count),
+                Err(e) => tracing::error!("error while pruning one time codes {}", e),
+            }
+
+            let mut throttle_repo = ThrottleRepository::new(prune_pool.clone());
+            match throttle_repo
+                .prune_stale_throttles(Utc::now() - chrono::Duration::days(1))
+                .await
+            {
+                Ok(count) => tracing::info!("pruned {} stale throttles", count),
+                Err(e) => tracing::error!("error while pruning throttles {}", e),
+            }
+
+            let mut rate_limit_repo = RateLimitRepository::new(prune_pool.clone());
+            match rate_limit_repo.prune_expired_counters().await {
+                Ok(count) => tracing::info!("pruned {} rate limit counters", count),
+                Err(e) => tracing::error!("error while pruning rate limit counters {}", e),
+            }
+        }
+    });
+
+    // breached passwords corpus is mapped once , a wrong path should stop the server (passwords would go unchecked)
+    let breached_passwords = config.breached_passwords_path.as_ref().map(|path| {
+        let checker = BreachedPasswordChecker::open(path, config.breached_password_min_count)
+            .expect("cannot open BREACHED_PASSWORDS_PATH");
+        Arc::new(checker)
+    });
+
+    let password_hasher = PasswordHashingService::new(config.password_hash_concurrency)
+        .expect("failed to create password hashing pool");
 
     // creating app state
-    let app_state = AppState { db: pool };
+    let app_state = AppState {
+        db: pool,
+        config: Arc::new(config),
+        token_service: Arc::new(token_service),
+        webauthn: Arc::new(webauthn),
+        breached_passwords,
+        password_hasher: Arc::new(password_hasher),
+    };
 
-    let a = app_state.clone();
     // building the router
     let app = create_router(Arc::new(app_state.clone())).layer(cors.clone());
 
@@ -94,5 +240,11 @@ async fn main() {
         .await
         .expect("failed to start server");
 
-    axum::serve(listener, app).await.unwrap();
+    // connect info gives the client ip to the handlers (saved with the login session)
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -1,26 +1,99 @@
-use std::{f32::consts::E, sync::Arc};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
 use axum::{
     Extension,
-    extract::Request,
-    http::{StatusCode, header},
+    extract::{FromRequestParts, Request},
+    http::request::Parts,
     middleware::Next,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
 };
-use axum_extra::extract::CookieJar;
-use lettre::error;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tower::{Layer, Service};
 use uuid::Uuid;
 
 use crate::{
-    AppState, db::auth::AuthRepository, errors::HttpError, models::Users,
-    utils::token::decode_token,
+    AppState, DbPool,
+    db::{
+        auth::AuthRepository, mfa::MfaRepository, roles::RoleRepository,
+        sessions::SessionRepository, tokens::TokenRepository,
+    },
+    errors::HttpError,
+    models::{UserRole, Users},
+    utils::token::Claims,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 // this is the resp struct that we will return/attach to req header
 pub struct JwtAuthMiddleware {
     pub user: Users,
+    pub claims: Claims, // claims of the token used in this req , logout needs jti and exp
+    // permissions from the custom roles , loaded on the first permission check of the req and reused by the later ones
+    #[serde(skip)]
+    pub permissions: Arc<OnceCell<HashSet<String>>>,
+    // totp enabled or not , loaded on the first check of the req (RequireRole<Admin>)
+    #[serde(skip)]
+    pub mfa_enabled: Arc<OnceCell<bool>>,
+}
+
+impl JwtAuthMiddleware {
+    /**
+     * permissions the user gets from his roles , db is asked only once per req
+     */
+    pub async fn permissions(&self, db: &DbPool) -> Result<&HashSet<String>, HttpError> {
+        self.permissions
+            .get_or_try_init(|| async {
+                RoleRepository::new(db.clone())
+                    .get_user_permissions(self.user.id)
+                    .await
+            })
+            .await
+    }
+
+    /**
+     * whether the user has totp enabled , db is asked only once per req
+     */
+    pub async fn mfa_enabled(&self, db: &DbPool) -> Result<bool, HttpError> {
+        self.mfa_enabled
+            .get_or_try_init(|| async {
+                let mfa = MfaRepository::new(db.clone())
+                    .get_user_mfa(self.user.id)
+                    .await?;
+
+                Ok(mfa.is_some_and(|mfa| mfa.enabled_at.is_some()))
+            })
+            .await
+            .copied()
+    }
+
+    /**
+     * checking a permission in the handler , eg user_data.require_permission(&app_state.db, "notes:read:any").await?
+     * admins have every permission
+     * @result => 403 if none of the roles of the user has the permission
+     */
+    pub async fn require_permission(&self, db: &DbPool, permission: &str) -> Result<(), HttpError> {
+        if self.user.role == UserRole::Admin {
+            return Ok(());
+        }
+
+        if !self.permissions(db).await?.contains(permission) {
+            return Err(HttpError::forbidden(format!(
+                "{} permission is required",
+                permission
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 /**
@@ -53,24 +126,225 @@ pub async fn auth(
     // if token id then ok else throw error token not found
     let token = token_data.ok_or_else(|| HttpError::unauthorized("token not found"))?;
 
-    // calling decode function to decode token and get user id from it
-    let token_data =
-        decode_token(token.as_str()).map_err(|_| HttpError::unauthorized("invalid token"))?;
+    // calling decode function to decode token and get claims(user id , jti) from it
+    let claims = app_state
+        .token_service
+        .decode_token(token.as_str())
+        .map_err(|_| HttpError::unauthorized("invalid token"))?;
 
     // coverting string uuid(user id ) to uuid data type
     let user_id =
-        Uuid::parse_str(&token_data).map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+        Uuid::parse_str(&claims.sub).map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+
+    let jti = Uuid::parse_str(&claims.jti).map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+
+    // if this token is logged out , we will not let it in
+    let mut token_repo = TokenRepository::new(app_state.db.clone());
+
+    if token_repo.is_access_token_revoked(jti).await? {
+        return Err(HttpError::unauthorized("token has been revoked"));
+    }
 
     // calling db user function to get the user struct from user id(uuid)
-    let mut db_pool = app_state.db.clone();
+    let db_pool = app_state.db.clone();
     let mut auth_repo = AuthRepository::new(db_pool);
 
-    let user_data = auth_repo.get_user(user_id).await.map_err(|e| e)?;
+    let user_data = auth_repo.get_user(user_id).await?;
+
+    // suspend also bumps token_version , this catches the tokens which are checked before that commit
+    if user_data.suspended_at.is_some() {
+        return Err(HttpError::forbidden("account is suspended"));
+    }
+
+    // password changed or logged out from all devices after this token was created
+    if claims.ver != user_data.token_version {
+        return Err(HttpError::unauthorized(
+            "token is no longer valid , please login again",
+        ));
+    }
+
+    // session (device) of this token must not be revoked , we also update its last seen time here
+    let session_id =
+        Uuid::parse_str(&claims.sid).map_err(|_| HttpError::unauthorized("Invalid Token"))?;
+
+    SessionRepository::new(app_state.db.clone())
+        .touch_session(session_id, user_id)
+        .await?;
 
     // adding data to the req haspmap
     req.extensions_mut().insert(JwtAuthMiddleware {
         user: user_data.clone(),
+        claims,
+        permissions: Arc::default(),
+        mfa_enabled: Arc::default(),
     });
     // moving to the next request , (await because rew will give future , so we have to await)
     Ok(next.run(req).await)
 }
+
+/**
+ * layer for sensitive routes , put on a route behind the auth middleware
+ * eg .route("/password", put(handler).layer(RequireRecentAuth(Duration::minutes(10))))
+ * token whose auth_time is older than the duration gets 403 , client should call /api/user/reauthenticate and retry with the new token
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct RequireRecentAuth(pub Duration);
+
+impl<S> Layer<S> for RequireRecentAuth {
+    type Service = RequireRecentAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireRecentAuthService {
+            inner,
+            max_age: self.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequireRecentAuthService<S> {
+    inner: S,
+    max_age: Duration,
+}
+
+impl<S> Service<Request> for RequireRecentAuthService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // the ready inner service is used for this req , a clone is left for the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let checked = check_recent_auth(&req, self.max_age);
+
+        Box::pin(async move {
+            match checked {
+                Ok(()) => inner.call(req).await,
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
+
+/**
+ * @result => error if the req did not pass the auth middleware or its token is not recently authenticated
+ */
+fn check_recent_auth(req: &Request, max_age: Duration) -> Result<(), HttpError> {
+    let user_data = req
+        .extensions()
+        .get::<JwtAuthMiddleware>()
+        .ok_or_else(|| HttpError::unauthorized("token not found"))?;
+
+    let authenticated_since = Utc::now().timestamp() - user_data.claims.auth_time as i64;
+
+    if authenticated_since > max_age.num_seconds() {
+        return Err(HttpError::forbidden(
+            "recent authentication required , please reauthenticate",
+        ));
+    }
+
+    Ok(())
+}
+
+// roles which RequireRole can ask for , every role is a type so the check is written in the handler/router signature
+pub trait RequiredRole {
+    const NAME: &'static str;
+    const REQUIRES_MFA: bool = false; // role can be used only with totp enabled and passed in this session (mfa claim)
+
+    fn allows(role: &UserRole) -> bool;
+}
+
+// only admins , whose session passed totp (2fa is mandatory for admin accounts)
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    const NAME: &'static str = "admin";
+    const REQUIRES_MFA: bool = true;
+
+    fn allows(role: &UserRole) -> bool {
+        *role == UserRole::Admin
+    }
+}
+
+// every logged in user , admins are users too
+pub struct User;
+
+impl RequiredRole for User {
+    const NAME: &'static str = "user";
+
+    fn allows(role: &UserRole) -> bool {
+        matches!(role, UserRole::User | UserRole::Admin)
+    }
+}
+
+/**
+ * extractor which lets the req in only if the user (attached by auth middleware) has the role
+ * on one handler => async fn handler(RequireRole { user_data, .. }: RequireRole<Admin>)
+ * on a whole router => router.layer(axum::middleware::from_extractor::<RequireRole<Admin>>()) , added before the auth layer
+ * @result => 401 if auth middleware did not run , 403 if the role is not allowed
+ * or the role needs totp and it is not enabled or this session did not pass it (password reset , passkey login)
+ */
+pub struct RequireRole<R: RequiredRole> {
+    pub user_data: JwtAuthMiddleware,
+    role: PhantomData<R>,
+}
+
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    R: RequiredRole,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_data = parts
+            .extensions
+            .get::<JwtAuthMiddleware>()
+            .cloned()
+            .ok_or_else(|| HttpError::unauthorized("token not found"))?;
+
+        if !R::allows(&user_data.user.role) {
+            return Err(HttpError::forbidden(format!(
+                "{} role is required",
+                R::NAME
+            )));
+        }
+
+        if R::REQUIRES_MFA {
+            let app_state = parts
+                .extensions
+                .get::<Arc<AppState>>()
+                .ok_or_else(|| HttpError::server_error("app state is missing"))?;
+
+            if !user_data.mfa_enabled(&app_state.db).await? {
+                return Err(HttpError::forbidden(format!(
+                    "{} role needs two factor authentication , please enable totp",
+                    R::NAME
+                )));
+            }
+
+            // totp enrolled is not enough , this session must have given a totp/recovery code
+            if !user_data.claims.mfa {
+                return Err(HttpError::forbidden(format!(
+                    "{} role needs a session with two factor authentication , please reauthenticate with a totp code",
+                    R::NAME
+                )));
+            }
+        }
+
+        Ok(RequireRole {
+            user_data,
+            role: PhantomData,
+        })
+    }
+}
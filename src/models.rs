@@ -8,12 +8,14 @@ use diesel_derive_enum::DbEnum;
 // Bring in the table generated by Diesel:
 // NOT from db::users — only from schema::users!
 use crate::schema::{
-    user_email_verifications, user_notes, user_reset_pass_validations,
-    user_reset_password_email_verifications, users,
+    auth_throttles, mfa_challenges, mfa_recovery_codes, one_time_codes, password_history,
+    permissions, rate_limit_counters, refresh_tokens, revoked_tokens, role_permissions, roles,
+    user_mfa, user_notes, user_roles, user_sessions, users, webauthn_ceremonies,
+    webauthn_credentials,
 };
 
 // Bring in the SQL type Diesel generated:
-use crate::schema::sql_types::UserType;
+use crate::schema::sql_types::{OtpPurpose, UserType, WebauthnCeremonyType};
 
 // This enum will map to your Postgres enum
 #[derive(DbEnum, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -25,6 +27,30 @@ pub enum UserRole {
     User,
 }
 
+// webauthn ceremony which is started and not finished yet
+#[derive(DbEnum, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[ExistingTypePath = "WebauthnCeremonyType"]
+pub enum WebauthnCeremonyKind {
+    #[db_rename = "registration"]
+    Registration,
+    #[db_rename = "authentication"]
+    Authentication,
+}
+
+// what a one time code is for , same code can not be used for some other flow
+#[derive(DbEnum, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[ExistingTypePath = "OtpPurpose"]
+pub enum OtpPurposeKind {
+    #[db_rename = "email_verification"]
+    EmailVerification,
+    #[db_rename = "password_reset"]
+    PasswordReset,
+    #[db_rename = "password_reset_token"]
+    PasswordResetToken,
+    #[db_rename = "login_code"]
+    LoginCode,
+}
+
 // Now your User struct works
 #[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
 #[diesel(table_name = users)]
@@ -35,57 +61,162 @@ pub struct Users {
     pub email: String,
     pub verified: bool,
     pub password: String,
-    pub verification_token: Option<String>,
-    pub token_expires_at: Option<DateTime<Utc>>,
     pub role: UserRole,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub token_version: i32,
+    pub suspended_at: Option<DateTime<Utc>>, // set by admin , suspended user can not login or use his tokens
 }
 
 #[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
-#[diesel(table_name = user_email_verifications)]
+#[diesel(table_name = user_notes)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct UserEmailVerifications {
+pub struct UserNotes {
     pub id: Uuid,
-    pub user_email: String,
-    pub otp: String,
-    pub expires_at: Option<DateTime<Utc>>,
-    pub used: bool,
+    pub user_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
-#[diesel(table_name = user_reset_pass_validations)]
+#[diesel(table_name = refresh_tokens)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct UserResetPasswordValidations {
+pub struct RefreshTokens {
     pub id: Uuid,
-    pub user_email: String,
-    pub hashed_reset_token: String,
-    pub expires_at: Option<DateTime<Utc>>,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
     pub used: bool,
+    pub revoked: bool,
+    pub expires_at: DateTime<Utc>,
     pub created_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
-#[diesel(table_name = user_reset_password_email_verifications)]
+#[diesel(table_name = user_sessions)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct UserResetPasswordEmailVerifications {
+pub struct UserSessions {
     pub id: Uuid,
-    pub user_email: String,
-    pub otp: String,
-    pub expires_at: Option<DateTime<Utc>>,
+    pub user_id: Uuid,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub authenticated_at: DateTime<Utc>, // last login or reauthenticate of this session (auth_time claim)
+    pub mfa_authenticated_at: Option<DateTime<Utc>>, // last totp/recovery code of this session (mfa claim)
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = user_mfa)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserMfa {
+    pub user_id: Uuid,
+    pub totp_secret: String,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = mfa_recovery_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MfaRecoveryCodes {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code_hash: String,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = mfa_challenges)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MfaChallenges {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub device_name: Option<String>,
+    pub attempts: i32,
     pub used: bool,
-    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
-#[diesel(table_name = user_notes)]
+#[diesel(table_name = webauthn_credentials)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct UserNotes {
+pub struct WebauthnCredentials {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub title: String,
-    pub content: String,
-    pub created_at: Option<DateTime<Utc>>,
+    pub credential_id: String,
+    pub passkey: String,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = webauthn_ceremonies)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebauthnCeremonies {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ceremony_type: WebauthnCeremonyKind,
+    pub state: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = one_time_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OneTimeCodes {
+    pub id: Uuid,
+    pub purpose: OtpPurposeKind,
+    pub user_email: String,
+    pub code_hash: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub parent_id: Option<Uuid>, // code which was verified to get this code (eg reset otp of a reset token)
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = auth_throttles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuthThrottles {
+    pub id: Uuid,
+    pub scope: String,
+    pub throttle_key: String,
+    pub failed_attempts: i32,
+    pub lockout_count: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+// custom role made by an admin , its permissions are in role_permissions
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = roles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Roles {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, QueryableByName, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = permissions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Permissions {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
 }
 
 // if we want to add data to the db tables , we have to crete new* sturct for db table with fields that we have give (non autogenerated values)
@@ -101,37 +232,139 @@ pub struct NewUser {
 }
 
 #[derive(Insertable)]
-#[diesel(table_name = user_email_verifications)]
+#[diesel(table_name = user_notes)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct NewUserEmailVerifications {
-    pub user_email: String,
-    pub otp: String,
-    pub expires_at: Option<DateTime<Utc>>,
+pub struct NewUserNote {
+    pub user_id: Uuid,
+    pub title: String,
+    pub content: String,
 }
 
 #[derive(Insertable)]
-#[diesel(table_name = user_reset_pass_validations)]
+#[diesel(table_name = refresh_tokens)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct NewUserResetPasswordValidation {
-    pub user_email: String,
-    pub hashed_reset_token: String,
-    pub expires_at: Option<DateTime<Utc>>,
+pub struct NewRefreshToken {
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = revoked_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewRevokedToken {
+    pub jti: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewUserSession {
+    pub user_id: Uuid,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub mfa_authenticated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = mfa_recovery_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewMfaRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = mfa_challenges)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewMfaChallenge {
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub device_name: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webauthn_credentials)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewWebauthnCredential {
+    pub user_id: Uuid,
+    pub credential_id: String,
+    pub passkey: String,
+    pub name: Option<String>,
 }
 
 #[derive(Insertable)]
-#[diesel(table_name = user_reset_password_email_verifications)]
+#[diesel(table_name = webauthn_ceremonies)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct NewUserResetPasswordEmailVerifications {
+pub struct NewWebauthnCeremony {
+    pub user_id: Uuid,
+    pub ceremony_type: WebauthnCeremonyKind,
+    pub state: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = one_time_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewOneTimeCode {
+    pub purpose: OtpPurposeKind,
     pub user_email: String,
-    pub otp: String,
-    pub expires_at: Option<DateTime<Utc>>,
+    pub code_hash: String,
+    pub max_attempts: i32,
+    pub expires_at: DateTime<Utc>,
+    pub parent_id: Option<Uuid>,
 }
 
 #[derive(Insertable)]
-#[diesel(table_name = user_notes)]
+#[diesel(table_name = auth_throttles)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct NewUserNote {
+pub struct NewAuthThrottle {
+    pub scope: String,
+    pub throttle_key: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = rate_limit_counters)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewRateLimitCounter {
+    pub bucket_key: String,
+    pub hits: i32,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = password_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewPasswordHistory {
     pub user_id: Uuid,
-    pub title: String,
-    pub content: String,
+    pub password_hash: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = roles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewRole {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = role_permissions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewRolePermission {
+    pub role_id: Uuid,
+    pub permission_id: Uuid,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_roles)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewUserRole {
+    pub user_id: Uuid,
+    pub role_id: Uuid,
 }
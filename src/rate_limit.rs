@@ -0,0 +1,268 @@
+// rate limiting of the routes , every rule counts reqs of one key (ip , user or email) in a fixed time window
+// counters are kept in memory (single server) or in postgres (many server instances share them)
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{FromRequestParts, MatchedPath, Request},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tower::{Layer, Service};
+
+use crate::{
+    DbPool, config::Config, db::rate_limits::RateLimitRepository, errors::HttpError,
+    middleware::JwtAuthMiddleware, utils::client_info::ClientInfo,
+};
+
+// biggest body we read to find the email , bigger bodies are rejected on email rules
+const MAX_RATE_LIMIT_BODY_BYTES: usize = 64 * 1024;
+
+// in memory counters are cleaned (expired windows removed) when there are more than these
+const MEMORY_STORE_CLEANUP_SIZE: usize = 10_000;
+
+// whose reqs are counted together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitKey {
+    Ip,
+    User,  // user id from auth middleware , only for routes behind it
+    Email, // user_email/email field of the json body , eg whom the otp is sent to
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    pub path: &'static str, // route path as written in the router , eg /api/user/edit-user-note/{note_id}
+    pub key: RateLimitKey,
+    pub max_requests: i32,
+    pub window_seconds: i64,
+}
+
+impl RateLimitRule {
+    pub fn new(
+        path: &'static str,
+        key: RateLimitKey,
+        max_requests: i32,
+        window_seconds: i64,
+    ) -> Self {
+        RateLimitRule {
+            path,
+            key,
+            max_requests,
+            window_seconds,
+        }
+    }
+}
+
+// bucket => (hits , window end)
+type MemoryCounters = Arc<Mutex<HashMap<String, (i32, DateTime<Utc>)>>>;
+
+// where the counters are kept
+#[derive(Clone)]
+pub enum RateLimitStore {
+    Memory(MemoryCounters),
+    Postgres(DbPool),
+}
+
+impl RateLimitStore {
+    /**
+     * RATE_LIMIT_STORE=memory (default) or postgres
+     */
+    pub fn from_config(config: &Config, pool: DbPool) -> Result<RateLimitStore, String> {
+        match config.rate_limit_store.as_str() {
+            "memory" => Ok(RateLimitStore::Memory(Arc::new(Mutex::new(HashMap::new())))),
+            "postgres" => Ok(RateLimitStore::Postgres(pool)),
+            other => Err(format!("unsupported RATE_LIMIT_STORE {}", other)),
+        }
+    }
+
+    /**
+     * tower layer which applies the given rules on the routes of the router it is added to
+     */
+    pub fn layer(&self, rules: Vec<RateLimitRule>) -> RateLimitLayer {
+        RateLimitLayer {
+            store: self.clone(),
+            rules: Arc::new(rules),
+        }
+    }
+
+    /**
+     * counting one req in the bucket
+     * @result => reqs in this bucket including this one
+     */
+    async fn hit(&self, bucket_key: String, window_end: DateTime<Utc>) -> Result<i32, HttpError> {
+        match self {
+            RateLimitStore::Memory(counters) => {
+                let mut counters = counters
+                    .lock()
+                    .map_err(|_| HttpError::server_error("rate limit store is poisoned"))?;
+
+                if counters.len() > MEMORY_STORE_CLEANUP_SIZE {
+                    let now = Utc::now();
+                    counters.retain(|_, (_, expires_at)| *expires_at > now);
+                }
+
+                let counter = counters.entry(bucket_key).or_insert((0, window_end));
+                counter.0 += 1;
+
+                Ok(counter.0)
+            }
+            RateLimitStore::Postgres(pool) => {
+                let mut rate_limit_repo = RateLimitRepository::new(pool.clone());
+
+                rate_limit_repo.hit(bucket_key, window_end).await
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    store: RateLimitStore,
+    rules: Arc<Vec<RateLimitRule>>,
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            store: self.store.clone(),
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    store: RateLimitStore,
+    rules: Arc<Vec<RateLimitRule>>,
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // the ready inner service is used for this req , a clone is left for the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let store = self.store.clone();
+        let rules = self.rules.clone();
+
+        Box::pin(async move {
+            match check_rate_limits(&store, &rules, req).await {
+                Ok(req) => inner.call(req).await,
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
+
+/**
+ * counting the req in every rule of its route
+ * @result => same req (body is put back if we read it) , or 429 with Retry-After if some rule is over its limit
+ */
+async fn check_rate_limits(
+    store: &RateLimitStore,
+    rules: &[RateLimitRule],
+    req: Request,
+) -> Result<Request, HttpError> {
+    let Some(matched_path) = req.extensions().get::<MatchedPath>().cloned() else {
+        return Ok(req);
+    };
+
+    let route_rules: Vec<&RateLimitRule> = rules
+        .iter()
+        .filter(|rule| rule.path == matched_path.as_str())
+        .collect();
+
+    if route_rules.is_empty() {
+        return Ok(req);
+    }
+
+    let (mut parts, body) = req.into_parts();
+
+    let client = ClientInfo::from_request_parts(&mut parts, &()).await?;
+
+    let user_id = parts
+        .extensions
+        .get::<JwtAuthMiddleware>()
+        .map(|user_data| user_data.user.id.to_string());
+
+    // body can be read only once , so we read it fully and give the bytes back to the req
+    let mut email = None;
+    let body = if route_rules
+        .iter()
+        .any(|rule| rule.key == RateLimitKey::Email)
+    {
+        let bytes = to_bytes(body, MAX_RATE_LIMIT_BODY_BYTES)
+            .await
+            .map_err(|_| HttpError::bad_request("request body is too large"))?;
+
+        email = serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|json| {
+                json.get("user_email")
+                    .or_else(|| json.get("email"))
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.trim().to_lowercase())
+            });
+
+        Body::from(bytes)
+    } else {
+        body
+    };
+
+    let now = Utc::now();
+
+    for rule in route_rules {
+        let key = match rule.key {
+            RateLimitKey::Ip => client.ip_address.as_ref().map(|ip| format!("ip:{}", ip)),
+            RateLimitKey::User => user_id.as_ref().map(|id| format!("user:{}", id)),
+            RateLimitKey::Email => email.as_ref().map(|email| format!("email:{}", email)),
+        };
+
+        // nothing to count on (eg body without email) , handler will reject such req anyway
+        let Some(key) = key else {
+            continue;
+        };
+
+        let window = now.timestamp() / rule.window_seconds;
+        let window_end = DateTime::<Utc>::from_timestamp((window + 1) * rule.window_seconds, 0)
+            .ok_or_else(|| HttpError::server_error("cannot calculate rate limit window"))?;
+
+        let bucket_key = format!("{}:{}:{}", rule.path, key, window);
+
+        let hits = store.hit(bucket_key, window_end).await?;
+
+        if hits > rule.max_requests {
+            let retry_after = (window_end - now).num_seconds().max(1) as u64;
+
+            return Err(HttpError::too_many_requests(
+                "too many requests , please try again later",
+                retry_after,
+            ));
+        }
+    }
+
+    Ok(Request::from_parts(parts, body))
+}
@@ -5,16 +5,85 @@ use tower_http::trace::TraceLayer;
 
 use crate::{
     AppState,
-    handler::{auth::auth_handler, users::users_handler},
-    middleware::auth,
+    handler::{
+        admin::admin_handler, auth::auth_handler, users::users_handler,
+        well_known::well_known_handler,
+    },
+    middleware::{Admin, RequireRole, User, auth},
+    rate_limit::{RateLimitKey, RateLimitRule, RateLimitStore},
 };
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
+    let rate_limit_store = RateLimitStore::from_config(&app_state.config, app_state.db.clone())
+        .expect("failed to create rate limit store");
+
+    // apis which send emails are limited per target email also , so nobody can spam a mailbox
+    let auth_rate_limits = rate_limit_store.layer(vec![
+        RateLimitRule::new("/api/auth/register", RateLimitKey::Ip, 10, 60 * 60),
+        RateLimitRule::new("/api/auth/register", RateLimitKey::Email, 3, 15 * 60),
+        RateLimitRule::new("/api/auth/login", RateLimitKey::Ip, 30, 60),
+        RateLimitRule::new(
+            "/api/auth/login/email-code/send",
+            RateLimitKey::Ip,
+            20,
+            60 * 60,
+        ),
+        RateLimitRule::new(
+            "/api/auth/login/email-code/send",
+            RateLimitKey::Email,
+            3,
+            15 * 60,
+        ),
+        RateLimitRule::new(
+            "/api/auth/reset-password/send-otp",
+            RateLimitKey::Ip,
+            20,
+            60 * 60,
+        ),
+        RateLimitRule::new(
+            "/api/auth/reset-password/send-otp",
+            RateLimitKey::Email,
+            3,
+            15 * 60,
+        ),
+    ]);
+
+    // user apis are limited per user (auth middleware runs before this layer)
+    let user_rate_limits = rate_limit_store.layer(vec![
+        RateLimitRule::new("/api/user/create-user-note", RateLimitKey::User, 30, 60),
+        RateLimitRule::new(
+            "/api/user/edit-user-note/{note_id}",
+            RateLimitKey::User,
+            60,
+            60,
+        ),
+        RateLimitRule::new("/api/user/password", RateLimitKey::User, 5, 15 * 60), // old password guessing with a stolen token
+        RateLimitRule::new("/api/user/reauthenticate", RateLimitKey::User, 10, 15 * 60),
+        RateLimitRule::new("/api/user/email", RateLimitKey::User, 3, 60 * 60), // every change sends two mails
+    ]);
+
     let api_route = Router::new()
-        .nest("/auth", auth_handler())
-        .nest("/user", users_handler().layer(middleware::from_fn(auth))) // routes which will have auth middleware protection
+        .nest("/auth", auth_handler().layer(auth_rate_limits))
+        .nest(
+            "/user",
+            users_handler()
+                .layer(user_rate_limits)
+                .layer(middleware::from_extractor::<RequireRole<User>>())
+                .layer(middleware::from_fn(auth)),
+        ) // routes which will have auth middleware protection , every role which counts as user is let in
+        .nest(
+            "/admin",
+            admin_handler()
+                .layer(middleware::from_extractor::<RequireRole<Admin>>())
+                .layer(middleware::from_fn(auth)),
+        ) // admin apis , auth runs first and then the role check
         .layer(TraceLayer::new_for_http()) //see difference ki ky aa rha hai , with or without me
-        .layer(Extension(app_state));
+        .layer(Extension(app_state.clone()));
+
+    // jwks lives at the root , verifiers expect /.well-known/jwks.json
+    let well_known_route = well_known_handler().layer(Extension(app_state));
 
-    Router::new().nest("/api", api_route)
+    Router::new()
+        .nest("/api", api_route)
+        .nest("/.well-known", well_known_route)
 }
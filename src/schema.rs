@@ -1,61 +1,194 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "otp_purpose"))]
+    pub struct OtpPurpose;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "user_type"))]
     pub struct UserType;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "webauthn_ceremony_type"))]
+    pub struct WebauthnCeremonyType;
 }
 
 diesel::table! {
-    user_email_verifications (id) {
+    auth_throttles (id) {
         id -> Uuid,
+        #[max_length = 50]
+        scope -> Varchar,
         #[max_length = 255]
-        user_email -> Varchar,
-        #[max_length = 6]
-        otp -> Varchar,
-        expires_at -> Nullable<Timestamptz>,
+        throttle_key -> Varchar,
+        failed_attempts -> Int4,
+        lockout_count -> Int4,
+        locked_until -> Nullable<Timestamptz>,
+        last_failed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    mfa_challenges (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        #[max_length = 100]
+        device_name -> Nullable<Varchar>,
+        attempts -> Int4,
         used -> Bool,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
     }
 }
 
 diesel::table! {
-    user_notes (id) {
+    mfa_recovery_codes (id) {
         id -> Uuid,
         user_id -> Uuid,
-        #[max_length = 255]
-        title -> Varchar,
-        #[max_length = 1000]
-        content -> Varchar,
-        created_at -> Nullable<Timestamptz>,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
     }
 }
 
 diesel::table! {
-    user_reset_pass_validations (id) {
+    use diesel::sql_types::*;
+    use super::sql_types::OtpPurpose;
+
+    one_time_codes (id) {
         id -> Uuid,
+        purpose -> OtpPurpose,
         #[max_length = 255]
         user_email -> Varchar,
+        #[max_length = 64]
+        code_hash -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        expires_at -> Timestamptz,
+        consumed_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        parent_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    password_history (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        password_hash -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    permissions (id) {
+        id -> Uuid,
         #[max_length = 100]
-        hashed_reset_token -> Varchar,
-        expires_at -> Nullable<Timestamptz>,
+        name -> Varchar,
+        #[max_length = 255]
+        description -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    rate_limit_counters (bucket_key) {
+        #[max_length = 512]
+        bucket_key -> Varchar,
+        hits -> Int4,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Varchar,
         used -> Bool,
+        revoked -> Bool,
+        expires_at -> Timestamptz,
         created_at -> Nullable<Timestamptz>,
     }
 }
 
 diesel::table! {
-    user_reset_password_email_verifications (id) {
+    revoked_tokens (jti) {
+        jti -> Uuid,
+        user_id -> Uuid,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    role_permissions (role_id, permission_id) {
+        role_id -> Uuid,
+        permission_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    roles (id) {
         id -> Uuid,
+        #[max_length = 50]
+        name -> Varchar,
         #[max_length = 255]
-        user_email -> Varchar,
-        #[max_length = 6]
-        otp -> Varchar,
-        expires_at -> Nullable<Timestamptz>,
-        used -> Bool,
+        description -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_mfa (user_id) {
+        user_id -> Uuid,
+        totp_secret -> Text,
+        enabled_at -> Nullable<Timestamptz>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_notes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 255]
+        title -> Varchar,
+        #[max_length = 1000]
+        content -> Varchar,
         created_at -> Nullable<Timestamptz>,
     }
 }
 
+diesel::table! {
+    user_roles (user_id, role_id) {
+        user_id -> Uuid,
+        role_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 100]
+        device_name -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        last_seen_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+        authenticated_at -> Timestamptz,
+        mfa_authenticated_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::UserType;
@@ -67,23 +200,75 @@ diesel::table! {
         #[max_length = 255]
         email -> Varchar,
         verified -> Bool,
-        #[max_length = 100]
-        password -> Varchar,
         #[max_length = 255]
-        verification_token -> Nullable<Varchar>,
-        token_expires_at -> Nullable<Timestamptz>,
+        password -> Varchar,
         role -> UserType,
         created_at -> Nullable<Timestamptz>,
         updated_at -> Nullable<Timestamptz>,
+        token_version -> Int4,
+        suspended_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::WebauthnCeremonyType;
+
+    webauthn_ceremonies (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        ceremony_type -> WebauthnCeremonyType,
+        state -> Text,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    webauthn_credentials (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        credential_id -> Text,
+        passkey -> Text,
+        #[max_length = 100]
+        name -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
     }
 }
 
+diesel::joinable!(mfa_challenges -> users (user_id));
+diesel::joinable!(mfa_recovery_codes -> users (user_id));
+diesel::joinable!(password_history -> users (user_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(revoked_tokens -> users (user_id));
+diesel::joinable!(role_permissions -> permissions (permission_id));
+diesel::joinable!(role_permissions -> roles (role_id));
+diesel::joinable!(user_mfa -> users (user_id));
 diesel::joinable!(user_notes -> users (user_id));
+diesel::joinable!(user_roles -> roles (role_id));
+diesel::joinable!(user_roles -> users (user_id));
+diesel::joinable!(user_sessions -> users (user_id));
+diesel::joinable!(webauthn_ceremonies -> users (user_id));
+diesel::joinable!(webauthn_credentials -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
-    user_email_verifications,
+    auth_throttles,
+    mfa_challenges,
+    mfa_recovery_codes,
+    one_time_codes,
+    password_history,
+    permissions,
+    rate_limit_counters,
+    refresh_tokens,
+    revoked_tokens,
+    role_permissions,
+    roles,
+    user_mfa,
     user_notes,
-    user_reset_pass_validations,
-    user_reset_password_email_verifications,
+    user_roles,
+    user_sessions,
     users,
+    webauthn_ceremonies,
+    webauthn_credentials,
 );
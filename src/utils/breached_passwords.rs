@@ -0,0 +1,324 @@
+// offline check of passwords against the pwned passwords (hibp) corpus , we can not call the hibp api from our network
+// two layouts of the corpus are supported , both are what the hibp downloader writes :
+// range files => a directory with one file per sha1 prefix (ABCDE.txt) , lines are SUFFIX:COUNT (35 hex chars) sorted by suffix
+// single file => the "ordered by hash" file , lines are SHA1HEX:COUNT (40 hex chars) sorted by hash
+// files are memory mapped (os loads only the pages we touch) and binary searched , so a lookup reads only a few lines
+
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use sha1::{Digest, Sha1};
+
+use crate::utils::password_policy::PasswordViolation;
+
+// range files are named by the first 5 hex chars of the sha1 , same as the hibp range api
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+enum Corpus {
+    Ranges(PathBuf), // directory of range files
+    Single(Mmap),    // whole ordered by hash file
+}
+
+pub struct BreachedPasswordChecker {
+    corpus: Corpus,
+    min_count: u64, // password is rejected when it is seen in breaches at least these many times
+}
+
+impl BreachedPasswordChecker {
+    /**
+     * opening the corpus once at startup , it is shared by all reqs
+     * @inputs => directory of range files or the single ordered by hash file
+     */
+    pub fn open(path: impl AsRef<Path>, min_count: u64) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let corpus = if path.is_dir() {
+            Corpus::Ranges(path.to_path_buf())
+        } else {
+            let file = File::open(path)?;
+
+            // file is only read , it should not be changed while the server runs (replace it and restart instead)
+            Corpus::Single(unsafe { Mmap::map(&file)? })
+        };
+
+        Ok(BreachedPasswordChecker { corpus, min_count })
+    }
+
+    /**
+     * @result => how many times the password is seen in breaches , None if it is not in the corpus
+     */
+    pub fn breach_count(&self, password: &str) -> Option<u64> {
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+
+        match &self.corpus {
+            Corpus::Single(corpus) => find_line(corpus, hash.as_bytes()),
+            Corpus::Ranges(dir) => {
+                let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+
+                let range = match open_range(dir, prefix) {
+                    Ok(range) => range?,
+                    Err(e) => {
+                        tracing::error!(
+                            "error while reading breached passwords range {} {}",
+                            prefix,
+                            e
+                        );
+                        return None;
+                    }
+                };
+
+                find_line(&range, suffix.as_bytes())
+            }
+        }
+    }
+
+    /**
+     * @result => violation if the password is in the corpus (min_count or more times)
+     */
+    pub fn check(&self, password: &str) -> Option<PasswordViolation> {
+        let count = self.breach_count(password)?;
+
+        (count >= self.min_count).then(|| PasswordViolation {
+            rule: "breached",
+            message: "password is found in known data breaches , please choose another password"
+                .to_string(),
+        })
+    }
+}
+
+/**
+ * mapping the range file of the prefix (ABCDE.txt , lowercase name is also accepted)
+ * @result => None if there is no file for this prefix (no breached hash starts with it)
+ */
+fn open_range(dir: &Path, prefix: &str) -> io::Result<Option<Mmap>> {
+    let candidates = [
+        dir.join(format!("{}.txt", prefix)),
+        dir.join(format!("{}.txt", prefix.to_ascii_lowercase())),
+    ];
+
+    for path in candidates {
+        match File::open(&path) {
+            Ok(file) => return unsafe { Mmap::map(&file) }.map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(None)
+}
+
+/**
+ * binary search on bytes , every step jumps to the middle of the range and reads the line around it
+ * @inputs => sorted HASH:COUNT lines and the uppercase hex key (full hash or suffix , same length as the line hashes)
+ * @result => count of the line with this key
+ */
+fn find_line(data: &[u8], key: &[u8]) -> Option<u64> {
+    let mut low = 0;
+    let mut high = data.len();
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        let start = data[low..middle]
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map(|position| low + position + 1)
+            .unwrap_or(low);
+
+        let end = data[middle..high]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map(|position| middle + position)
+            .unwrap_or(high);
+
+        let line = &data[start..end];
+        let line_hash = line.split(|byte| *byte == b':').next().unwrap_or_default();
+
+        match compare_hash(line_hash.trim_ascii(), key) {
+            Ordering::Equal => return Some(parse_count(line)),
+            Ordering::Less => low = end + 1,
+            Ordering::Greater => high = start,
+        }
+    }
+
+    None
+}
+
+/**
+ * corpus is uppercase hex , lowercase lines are compared as uppercase
+ */
+fn compare_hash(line_hash: &[u8], hash: &[u8]) -> Ordering {
+    line_hash
+        .iter()
+        .map(|byte| byte.to_ascii_uppercase())
+        .cmp(hash.iter().copied())
+}
+
+/**
+ * count after the ':' , line may end with \r (windows line endings of the hibp files)
+ * line without count is treated as seen once
+ */
+fn parse_count(line: &[u8]) -> u64 {
+    line.split(|byte| *byte == b':')
+        .nth(1)
+        .and_then(|count| std::str::from_utf8(count).ok())
+        .and_then(|count| count.trim().parse::<u64>().ok())
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn sha1_hex(password: &str) -> String {
+        hex::encode_upper(Sha1::digest(password.as_bytes()))
+    }
+
+    // temp file/dir of this test , removed when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!(
+                "breached-{}-{}",
+                name,
+                uuid::Uuid::new_v4()
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    // single ordered by hash file with these (password , count) , lines sorted by hash
+    fn single_corpus(entries: &[(&str, &str)], line_ending: &str, lowercase: bool) -> TempPath {
+        let mut lines: Vec<String> = entries
+            .iter()
+            .map(|(password, count)| {
+                let hash = sha1_hex(password);
+                let hash = if lowercase { hash.to_lowercase() } else { hash };
+                if count.is_empty() {
+                    hash
+                } else {
+                    format!("{}:{}", hash, count)
+                }
+            })
+            .collect();
+        lines.sort_by_key(|line| line.to_uppercase());
+
+        let path = TempPath::new("single");
+        fs::write(&path.0, lines.join(line_ending)).unwrap();
+        path
+    }
+
+    fn checker(path: &TempPath, min_count: u64) -> BreachedPasswordChecker {
+        BreachedPasswordChecker::open(&path.0, min_count).unwrap()
+    }
+
+    const PASSWORDS: [(&str, &str); 5] = [
+        ("password", "9545824"),
+        ("123456", "37359195"),
+        ("qwerty", "10556095"),
+        ("letmein", "1000"),
+        ("dragon", "2"),
+    ];
+
+    #[test]
+    fn single_file_finds_every_line_including_first_and_last() {
+        let path = single_corpus(&PASSWORDS, "\n", false);
+        let checker = checker(&path, 1);
+
+        for (password, count) in PASSWORDS {
+            assert_eq!(
+                checker.breach_count(password),
+                Some(count.parse().unwrap()),
+                "{}",
+                password
+            );
+        }
+
+        assert_eq!(checker.breach_count("correct horse battery staple"), None);
+    }
+
+    #[test]
+    fn single_file_handles_crlf_and_lowercase_hex() {
+        let path = single_corpus(&PASSWORDS, "\r\n", true);
+        let checker = checker(&path, 1);
+
+        for (password, count) in PASSWORDS {
+            assert_eq!(
+                checker.breach_count(password),
+                Some(count.parse().unwrap()),
+                "{}",
+                password
+            );
+        }
+
+        assert_eq!(checker.breach_count("not in the corpus"), None);
+    }
+
+    #[test]
+    fn line_without_count_counts_once_and_min_count_is_applied() {
+        let path = single_corpus(&[("password", ""), ("dragon", "2")], "\n", false);
+
+        assert_eq!(checker(&path, 1).breach_count("password"), Some(1));
+        assert!(checker(&path, 2).check("password").is_none());
+        assert!(checker(&path, 2).check("dragon").is_some());
+    }
+
+    #[test]
+    fn empty_and_one_line_files() {
+        let empty = single_corpus(&[], "\n", false);
+        assert_eq!(checker(&empty, 1).breach_count("password"), None);
+
+        let one = single_corpus(&[("password", "3")], "\n", false);
+        assert_eq!(checker(&one, 1).breach_count("password"), Some(3));
+        assert_eq!(checker(&one, 1).breach_count("dragon"), None);
+    }
+
+    #[test]
+    fn range_files_are_searched_by_prefix() {
+        let dir = TempPath::new("ranges");
+        fs::create_dir(&dir.0).unwrap();
+
+        // every password of the list goes in the range file of its prefix , with a fake suffix around it
+        for (password, count) in PASSWORDS {
+            let hash = sha1_hex(password);
+            let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+
+            let mut lines = vec![
+                format!("{}:{}", "0".repeat(35), 5),
+                format!("{}:{}", suffix, count),
+                format!("{}:{}", "F".repeat(35), 7),
+            ];
+            lines.sort();
+
+            fs::write(dir.0.join(format!("{}.txt", prefix)), lines.join("\r\n")).unwrap();
+        }
+
+        let checker = checker(&dir, 1);
+
+        for (password, count) in PASSWORDS {
+            assert_eq!(
+                checker.breach_count(password),
+                Some(count.parse().unwrap()),
+                "{}",
+                password
+            );
+        }
+
+        // no range file for this prefix
+        assert_eq!(checker.breach_count("correct horse battery staple"), None);
+    }
+}
@@ -0,0 +1,142 @@
+// details of the client (device) which is sending the req
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use ipnet::IpNet;
+
+use crate::{AppState, errors::HttpError};
+
+// ip and user agent of the caller , we save these with the login session
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/**
+ * real client ip behind our proxies
+ * @inputs => socket (peer) ip , x-forwarded-for header and trusted proxies from config
+ * @result => peer ip if it is not a trusted proxy , else the right most x-forwarded-for hop which is not a trusted proxy
+ * every proxy appends the ip it got the req from , so hops left of the first untrusted one are written by the client and can be fake
+ */
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let mut client = peer;
+
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        // a broken entry means we cannot trust anything left of it , last good hop is the client
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+
+        client = ip;
+
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+
+    client
+}
+
+/**
+ * extractor , so handlers can just take ClientInfo as input
+ * ip is the socket address , x-forwarded-for is used only when the req comes from a trusted proxy (TRUSTED_PROXIES)
+ */
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let trusted_proxies = parts
+            .extensions
+            .get::<Arc<AppState>>()
+            .map(|app_state| app_state.config.trusted_proxies.as_slice())
+            .unwrap_or_default();
+
+        let forwarded_for = parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok());
+
+        let ip_address =
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| {
+                    client_ip(addr.ip(), forwarded_for, trusted_proxies).to_string()
+                });
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        Ok(ClientInfo {
+            ip_address,
+            user_agent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn proxies() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap()]
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_forwarded_for() {
+        let client = client_ip(ip("203.0.113.7"), Some("198.51.100.1"), &proxies());
+
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn trusted_peer_gives_right_most_untrusted_hop() {
+        let client = client_ip(
+            ip("10.0.0.2"),
+            Some("1.1.1.1, 198.51.100.1, 10.0.0.5"),
+            &proxies(),
+        );
+
+        assert_eq!(client, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn trusted_peer_without_header_is_the_client() {
+        assert_eq!(client_ip(ip("10.0.0.2"), None, &proxies()), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn broken_hop_stops_at_last_good_ip() {
+        let client = client_ip(
+            ip("10.0.0.2"),
+            Some("198.51.100.1, junk, 10.0.0.5"),
+            &proxies(),
+        );
+
+        assert_eq!(client, ip("10.0.0.5"));
+    }
+}
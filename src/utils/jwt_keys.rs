@@ -0,0 +1,265 @@
+// keys used to sign and verify our jwt tokens
+// HS256 uses the shared JWT_SECRET , RS256/EdDSA use pem files and publish public keys in jwks
+// in RS256/EdDSA mode JWT_SECRET tokens (they have no kid) are rejected , unless JWT_LEGACY_HS256_UNTIL is set
+// for the move from HS256 , then they are accepted only until that time
+
+use std::{collections::HashMap, fs};
+
+use chrono::{DateTime, Utc};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use rsa::{
+    RsaPrivateKey, RsaPublicKey,
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    traits::PublicKeyParts,
+};
+
+use crate::config::Config;
+
+// one key which can verify tokens
+struct VerificationKey {
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+}
+
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    signing_kid: Option<String>, // HS256 tokens have no kid
+    encoding_key: EncodingKey,
+    verification_keys: HashMap<String, VerificationKey>, // kid => key , current key + older keys still accepted
+    secret_key: Option<DecodingKey>,                     // for tokens without kid (HS256)
+    secret_key_until: Option<DateTime<Utc>>, // secret_key is a legacy key , not accepted after this time
+    jwks: JwkSet,                            // public keys , served on /.well-known/jwks.json
+}
+
+impl JwtKeys {
+    /**
+     * loading keys at startup from config , so we do not read env/files on every token
+     * @result => error message if some key is missing or not valid
+     */
+    pub fn from_config(config: &Config) -> Result<JwtKeys, String> {
+        let algorithm = match config.jwt_algorithm.as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            other => return Err(format!("unsupported JWT_ALGORITHM {}", other)),
+        };
+
+        let mut verification_keys = HashMap::new();
+        let mut jwks = JwkSet { keys: vec![] };
+
+        // older asymmetric keys are accepted in every mode , so we can move from one key/algorithm to other
+        for (kid, path) in &config.jwt_verification_keys {
+            let jwk = public_jwk_from_pem_file(kid, path)?;
+            add_verification_key(&mut verification_keys, &mut jwks, jwk)?;
+        }
+
+        if algorithm == Algorithm::HS256 {
+            let secret = config
+                .jwt_secret
+                .as_ref()
+                .ok_or_else(|| "JWT_SECRET must be set for HS256".to_string())?;
+
+            return Ok(JwtKeys {
+                algorithm,
+                signing_kid: None,
+                encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+                verification_keys,
+                secret_key: Some(DecodingKey::from_secret(secret.as_bytes())),
+                secret_key_until: None,
+                jwks,
+            });
+        }
+
+        let kid = config
+            .jwt_signing_key_id
+            .clone()
+            .ok_or_else(|| "JWT_SIGNING_KEY_ID must be set for asymmetric keys".to_string())?;
+        let path = config
+            .jwt_signing_key_path
+            .clone()
+            .ok_or_else(|| "JWT_SIGNING_KEY_PATH must be set for asymmetric keys".to_string())?;
+
+        let pem = fs::read(&path).map_err(|e| format!("cannot read {} : {}", path, e))?;
+
+        let encoding_key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(&pem),
+            _ => EncodingKey::from_ed_pem(&pem),
+        }
+        .map_err(|e| format!("signing key {} is not valid : {}", path, e))?;
+
+        // public part of the signing key is also a verification key
+        let jwk = public_jwk_from_pem_file(&kid, &path)?;
+
+        if jwk.common.key_algorithm != Some(key_algorithm(algorithm)) {
+            return Err(format!(
+                "signing key {} is not a {} key",
+                path, config.jwt_algorithm
+            ));
+        }
+
+        add_verification_key(&mut verification_keys, &mut jwks, jwk)?;
+
+        // JWT_SECRET alone is not enough to keep accepting HS256 tokens , it has to be asked for with an end time
+        let secret_key = match config.jwt_legacy_hs256_until {
+            Some(until) => {
+                let secret = config.jwt_secret.as_ref().ok_or_else(|| {
+                    "JWT_SECRET must be set when JWT_LEGACY_HS256_UNTIL is set".to_string()
+                })?;
+
+                tracing::warn!("legacy HS256 tokens are accepted until {}", until);
+
+                Some(DecodingKey::from_secret(secret.as_bytes()))
+            }
+            None => None,
+        };
+
+        Ok(JwtKeys {
+            algorithm,
+            signing_kid: Some(kid),
+            encoding_key,
+            verification_keys,
+            secret_key,
+            secret_key_until: config.jwt_legacy_hs256_until,
+            jwks,
+        })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn signing_kid(&self) -> Option<String> {
+        self.signing_kid.clone()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    /**
+     * finding the key (and its algorithm) for the kid in the token header
+     * token without kid can only be an HS256 token signed with our secret , in HS256 mode or in the legacy window
+     */
+    pub fn decoding_key(&self, kid: Option<&str>) -> Option<(Algorithm, &DecodingKey)> {
+        match kid {
+            Some(kid) => self
+                .verification_keys
+                .get(kid)
+                .map(|key| (key.algorithm, &key.decoding_key)),
+            None => self
+                .secret_key
+                .as_ref()
+                .filter(|_| self.secret_key_until.is_none_or(|until| Utc::now() < until))
+                .map(|key| (Algorithm::HS256, key)),
+        }
+    }
+
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::RS256 => KeyAlgorithm::RS256,
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::HS256,
+    }
+}
+
+fn add_verification_key(
+    verification_keys: &mut HashMap<String, VerificationKey>,
+    jwks: &mut JwkSet,
+    jwk: Jwk,
+) -> Result<(), String> {
+    let kid = jwk.common.key_id.clone().unwrap_or_default();
+
+    if verification_keys.contains_key(&kid) {
+        return Err(format!("duplicate jwt key id {}", kid));
+    }
+
+    let algorithm = match jwk.common.key_algorithm {
+        Some(KeyAlgorithm::RS256) => Algorithm::RS256,
+        _ => Algorithm::EdDSA,
+    };
+
+    let decoding_key =
+        DecodingKey::from_jwk(&jwk).map_err(|e| format!("jwt key {} is not valid : {}", kid, e))?;
+
+    verification_keys.insert(
+        kid,
+        VerificationKey {
+            algorithm,
+            decoding_key,
+        },
+    );
+    jwks.keys.push(jwk);
+
+    Ok(())
+}
+
+/**
+ * reading a pem file (public or private , RSA or Ed25519) and building the public jwk out of it
+ * private keys are never put in the jwk , only their public part
+ */
+fn public_jwk_from_pem_file(kid: &str, path: &str) -> Result<Jwk, String> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("cannot read {} : {}", path, e))?;
+
+    let rsa_key = RsaPublicKey::from_public_key_pem(&pem)
+        .or_else(|_| RsaPublicKey::from_pkcs1_pem(&pem))
+        .ok()
+        .or_else(|| {
+            RsaPrivateKey::from_pkcs8_pem(&pem)
+                .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+                .ok()
+                .map(|key| key.to_public_key())
+        });
+
+    if let Some(rsa_key) = rsa_key {
+        return Ok(Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(KeyAlgorithm::RS256),
+                key_id: Some(kid.to_string()),
+                ..Default::default()
+            },
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(rsa_key.n().to_bytes_be()),
+                e: URL_SAFE_NO_PAD.encode(rsa_key.e().to_bytes_be()),
+            }),
+        });
+    }
+
+    let ed_key = ed25519_dalek::VerifyingKey::from_public_key_pem(&pem)
+        .ok()
+        .or_else(|| {
+            ed25519_dalek::SigningKey::from_pkcs8_pem(&pem)
+                .ok()
+                .map(|key| key.verifying_key())
+        })
+        .ok_or_else(|| format!("{} is not an RSA or Ed25519 pem key", path))?;
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(KeyAlgorithm::EdDSA),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(ed_key.as_bytes()),
+        }),
+    })
+}
@@ -1,2 +1,7 @@
+pub mod breached_passwords;
+pub mod client_info;
+pub mod jwt_keys;
 pub mod password;
-pub mod token;
\ No newline at end of file
+pub mod password_policy;
+pub mod token;
+pub mod totp;
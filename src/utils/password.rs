@@ -1,24 +1,132 @@
 // hashing and veryfying of passwords
+// argon2 params and optional pepper come from config (init_password_hasher at startup)
+// pepper is the argon2 secret key , hashes made with it have keyid=<pepper id> in the hash string
+// so we know which hashes need the pepper and which are older (unpeppered) hashes
+// after a pepper rotation the old peppers are kept (by id) for verifying , such hashes are rehashed with the current one on login
+// users imported from the old system still have bcrypt , scrypt or pbkdf2-sha256 hashes , these are only verified ,
+// new hashes are always argon2id and the legacy hash is replaced on the first successful login (needs_rehash)
 
-use core::error;
+use std::{
+    io,
+    sync::{Arc, OnceLock},
+};
 
 use argon2::{
-    Argon2,
-    password_hash::{
-        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
-        rand_core::{Error, OsRng},
-    },
+    Algorithm, Argon2, KeyId, Params, ParamsBuilder, Version,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
+use pbkdf2::Pbkdf2;
 use rand::Rng;
+use scrypt::Scrypt;
+use tokio::{runtime::Runtime, sync::Semaphore};
 
-use crate::errors::{ErrorMessage, HttpError};
+use crate::errors::ErrorMessage;
 
 // maxxing the size of the password
 const MAX_PASSWORD_LENGTH: usize = 64;
 
+// phc algorithm ids of the hashes we can verify
+const ARGON2_IDENTS: [&str; 3] = ["argon2id", "argon2i", "argon2d"];
+const SCRYPT_IDENT: &str = "scrypt";
+const PBKDF2_SHA256_IDENT: &str = "pbkdf2-sha256";
+
+// bcrypt is not a phc string , it is $2b$<cost>$<salt+hash>
+const BCRYPT_PREFIXES: [&str; 4] = ["$2a$", "$2b$", "$2x$", "$2y$"];
+
+// argon2 settings for new hashes
+#[derive(Clone)]
+pub struct PasswordHasherConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub pepper: Option<Vec<u8>>, // secret from PASSWORD_PEPPER_FILE , never saved in db
+    pub pepper_id: String,       // saved as keyid in the hash , change it when the pepper changes
+    pub old_peppers: Vec<(String, Vec<u8>)>, // (pepper id , pepper) of rotated out peppers , only used for verifying
+}
+
+impl Default for PasswordHasherConfig {
+    fn default() -> Self {
+        PasswordHasherConfig {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+            pepper: None,
+            pepper_id: "p1".to_string(),
+            old_peppers: Vec::new(),
+        }
+    }
+}
+
+static HASHER_CONFIG: OnceLock<PasswordHasherConfig> = OnceLock::new();
+
+/**
+ * setting argon2 params and pepper once at startup , before any password is hashed
+ * @result => error if params are not valid argon2 params or it was already set
+ */
+pub fn init_password_hasher(config: PasswordHasherConfig) -> Result<(), String> {
+    Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )
+    .map_err(|e| format!("invalid argon2 params {}", e))?;
+
+    KeyId::new(config.pepper_id.as_bytes()).map_err(|e| format!("invalid pepper id {}", e))?;
+
+    for (old_id, _) in &config.old_peppers {
+        KeyId::new(old_id.as_bytes()).map_err(|e| format!("invalid old pepper id {}", e))?;
+
+        if config.pepper.is_some() && *old_id == config.pepper_id {
+            return Err(format!(
+                "old pepper id {} is the id of the current pepper",
+                old_id
+            ));
+        }
+    }
+
+    HASHER_CONFIG
+        .set(config)
+        .map_err(|_| "password hasher is already initialized".to_string())
+}
+
+fn hasher_config() -> &'static PasswordHasherConfig {
+    HASHER_CONFIG.get_or_init(PasswordHasherConfig::default)
+}
+
+/**
+ * argon2id with the params of the config , keyed with the pepper if there is one
+ */
+fn configured_hasher(config: &PasswordHasherConfig) -> Result<Argon2<'_>, ErrorMessage> {
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(config.memory_kib)
+        .t_cost(config.iterations)
+        .p_cost(config.parallelism);
+
+    if config.pepper.is_some() {
+        builder.keyid(
+            KeyId::new(config.pepper_id.as_bytes()).map_err(|_| ErrorMessage::HashingError)?,
+        );
+    }
+
+    let params = builder.build().map_err(|_| ErrorMessage::HashingError)?;
+
+    match &config.pepper {
+        Some(pepper) => {
+            Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, params)
+                .map_err(|_| ErrorMessage::HashingError)
+        }
+        None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+    }
+}
+
 // hashing function
 pub fn hash_pass(pass: impl Into<String>) -> Result<String, ErrorMessage> {
-    let pass = pass.into();
+    hash_with(hasher_config(), pass.into())
+}
+
+fn hash_with(config: &PasswordHasherConfig, pass: String) -> Result<String, ErrorMessage> {
     let salt = SaltString::generate(&mut OsRng); //random salt everytime
 
     if pass.is_empty() {
@@ -28,12 +136,12 @@ pub fn hash_pass(pass: impl Into<String>) -> Result<String, ErrorMessage> {
         return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_PASSWORD_LENGTH));
     }
 
-    let hashed_password = Argon2::default()
+    let hashed_password = configured_hasher(config)?
         .hash_password(pass.as_bytes(), &salt)
         .map_err(|_| ErrorMessage::HashingError)?
         .to_string();
 
-    return Ok(hashed_password);
+    Ok(hashed_password)
 }
 
 // validate password
@@ -47,9 +155,14 @@ pub fn validate_pas(
     hashed_user_pass: impl Into<String>,
 ) -> Result<bool, ErrorMessage> {
     // converting into actual Strings
-    let pass = pass.into();
-    let hashed_user_pass = hashed_user_pass.into();
+    validate_with(hasher_config(), pass.into(), hashed_user_pass.into())
+}
 
+fn validate_with(
+    config: &PasswordHasherConfig,
+    pass: String,
+    hashed_user_pass: String,
+) -> Result<bool, ErrorMessage> {
     if pass.is_empty() {
         return Err(ErrorMessage::EmptyPassword);
     }
@@ -58,14 +171,255 @@ pub fn validate_pas(
         return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_PASSWORD_LENGTH));
     }
 
+    if is_bcrypt_hash(&hashed_user_pass) {
+        return bcrypt::verify(&pass, &hashed_user_pass)
+            .map_err(|_| ErrorMessage::InvalidHashFormat);
+    }
+
     let parsed_hash =
         PasswordHash::new(&hashed_user_pass).map_err(|_| ErrorMessage::InvalidHashFormat)?;
 
-    let res = Argon2::default()
+    // legacy hashes carry their own params (cost , salt) , none of them is peppered
+    match parsed_hash.algorithm.as_str() {
+        SCRYPT_IDENT => {
+            return Ok(Scrypt
+                .verify_password(pass.as_bytes(), &parsed_hash)
+                .is_ok());
+        }
+        PBKDF2_SHA256_IDENT => {
+            return Ok(Pbkdf2
+                .verify_password(pass.as_bytes(), &parsed_hash)
+                .is_ok());
+        }
+        ident if !ARGON2_IDENTS.contains(&ident) => return Err(ErrorMessage::InvalidHashFormat),
+        _ => {}
+    }
+
+    // params of the hash itself are used for verifying , only the pepper comes from config
+    // hash with keyid needs the pepper of that id (current or an old one) , without the pepper it can never match
+    let keyid = Params::try_from(&parsed_hash)
+        .map_err(|_| ErrorMessage::InvalidHashFormat)?
+        .keyid()
+        .to_vec();
+
+    let verifier = if keyid.is_empty() {
+        Argon2::default()
+    } else {
+        let pepper = pepper_for_keyid(config, &keyid).ok_or(ErrorMessage::UnknownPepper)?;
+
+        Argon2::new_with_secret(
+            pepper,
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::default(),
+        )
+        .map_err(|_| ErrorMessage::HashingError)?
+    };
+
+    let res = verifier
         .verify_password(pass.as_bytes(), &parsed_hash)
-        .map_or(false, |_| true); //when we want to do something on error(false) and on correct value (true value) 
+        .is_ok();
+
+    Ok(res)
+}
+
+/**
+ * @result => current pepper or an old (rotated) one whose id is the keyid of the hash
+ */
+fn pepper_for_keyid<'a>(config: &'a PasswordHasherConfig, keyid: &[u8]) -> Option<&'a [u8]> {
+    if let Some(pepper) = &config.pepper
+        && keyid == config.pepper_id.as_bytes()
+    {
+        return Some(pepper);
+    }
+
+    config
+        .old_peppers
+        .iter()
+        .find(|(old_id, _)| keyid == old_id.as_bytes())
+        .map(|(_, pepper)| pepper.as_slice())
+}
+
+fn is_bcrypt_hash(hashed_user_pass: &str) -> bool {
+    BCRYPT_PREFIXES
+        .iter()
+        .any(|prefix| hashed_user_pass.starts_with(prefix))
+}
+
+/**
+ * checking a hash which is brought from outside (user import) before it is saved
+ * @result => true if validate_pas can verify it => argon2 (unpeppered) , bcrypt , scrypt or pbkdf2-sha256
+ */
+pub fn is_supported_hash(hashed_user_pass: &str) -> bool {
+    if is_bcrypt_hash(hashed_user_pass) {
+        return hashed_user_pass.parse::<bcrypt::HashParts>().is_ok();
+    }
+
+    let Ok(parsed_hash) = PasswordHash::new(hashed_user_pass) else {
+        return false;
+    };
+
+    match parsed_hash.algorithm.as_str() {
+        SCRYPT_IDENT => scrypt::Params::try_from(&parsed_hash).is_ok(),
+        PBKDF2_SHA256_IDENT => pbkdf2::Params::try_from(&parsed_hash).is_ok(),
+        ident if ARGON2_IDENTS.contains(&ident) => {
+            Params::try_from(&parsed_hash).is_ok_and(|params| params.keyid().is_empty())
+        }
+        _ => false,
+    }
+}
+
+/**
+ * true when the hash is not made with the current config (older params , algorithm , legacy bcrypt/scrypt/pbkdf2 , or pepper added/changed/removed)
+ * such hash is replaced with a new one after a successful login , so raising the costs needs no password resets
+ */
+pub fn needs_rehash(hashed_user_pass: &str) -> bool {
+    needs_rehash_with(hasher_config(), hashed_user_pass)
+}
+
+fn needs_rehash_with(config: &PasswordHasherConfig, hashed_user_pass: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(hashed_user_pass) else {
+        return true;
+    };
+
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    let Ok(params) = Params::try_from(&parsed_hash) else {
+        return true;
+    };
+
+    let expected_keyid: &[u8] = match config.pepper {
+        Some(_) => config.pepper_id.as_bytes(),
+        None => &[],
+    };
+
+    params.m_cost() != config.memory_kib
+        || params.t_cost() != config.iterations
+        || params.p_cost() != config.parallelism
+        || params.keyid() != expected_keyid
+}
+
+/**
+ * verifying the password against a hash of a random password , result is thrown away
+ * used when the user is not found , so login of unknown email takes same time as a wrong password
+ */
+pub fn verify_dummy_password(pass: &str) {
+    // hashed once (same argon2 params as real hashes) and reused for every unknown user
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+    let dummy_hash = DUMMY_HASH.get_or_init(|| hash_pass(generate_otp()).unwrap_or_default());
+
+    let _ = validate_pas(pass, dummy_hash.as_str());
+}
+
+/**
+ * runs hashing and verifying of passwords away from the tokio workers which serve the reqs
+ * argon2 takes ~19 MiB and tens of ms of cpu per hash , on a worker thread a burst of logins would stall every other req
+ * jobs run on a blocking pool of its own (db calls keep the default one) and the semaphore lets only
+ * PASSWORD_HASH_CONCURRENCY jobs run at once , others wait (async) for a permit , so memory used by argon2 is bounded
+ */
+pub struct PasswordHashingService {
+    pool: Option<Runtime>, // Option only so drop can take it out , it is always Some
+    permits: Arc<Semaphore>,
+}
+
+impl PasswordHashingService {
+    pub fn new(concurrency: usize) -> io::Result<Self> {
+        let concurrency = concurrency.max(1);
+
+        // runtime is used only for its blocking threads , one worker thread is the least tokio allows
+        let pool = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .max_blocking_threads(concurrency)
+            .thread_name("password-hashing")
+            .build()?;
+
+        Ok(PasswordHashingService {
+            pool: Some(pool),
+            permits: Arc::new(Semaphore::new(concurrency)),
+        })
+    }
+
+    /**
+     * running a cpu heavy password job (hash , verify , history check) on the hashing pool
+     * @result => result of the job , HashingError if the job could not run (panicked or service is shutting down)
+     */
+    pub async fn run<T, F>(&self, job: F) -> Result<T, ErrorMessage>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| ErrorMessage::HashingError)?;
+
+        let pool = self.pool.as_ref().ok_or(ErrorMessage::HashingError)?;
+
+        pool.spawn_blocking(move || {
+            // permit is given back when the job is done , not when the waiting req goes away
+            let _permit = permit;
+            job()
+        })
+        .await
+        .map_err(|_| ErrorMessage::HashingError)
+    }
+
+    pub async fn hash(&self, pass: impl Into<String>) -> Result<String, ErrorMessage> {
+        let pass = pass.into();
+
+        self.run(move || hash_pass(pass)).await?
+    }
+
+    /**
+     * same as validate_pas , but a hash whose pepper is not configured (removed without PASSWORD_OLD_PEPPER_FILES)
+     * counts as wrong password and not as server error , such user has to reset his password
+     */
+    pub async fn verify(
+        &self,
+        pass: impl Into<String>,
+        hashed_user_pass: impl Into<String>,
+    ) -> Result<bool, ErrorMessage> {
+        let pass = pass.into();
+        let hashed_user_pass = hashed_user_pass.into();
 
-    return Ok(res);
+        match self
+            .run(move || validate_pas(pass, hashed_user_pass))
+            .await?
+        {
+            Err(ErrorMessage::UnknownPepper) => {
+                tracing::warn!(
+                    "password hash needs a pepper which is not configured , counted as wrong password"
+                );
+                Ok(false)
+            }
+            res => res,
+        }
+    }
+
+    /**
+     * same as verify_dummy_password , but on the hashing pool
+     */
+    pub async fn verify_dummy(&self, pass: impl Into<String>) {
+        let pass = pass.into();
+
+        let _ = self.run(move || verify_dummy_password(&pass)).await;
+    }
+}
+
+impl Drop for PasswordHashingService {
+    fn drop(&mut self) {
+        // a runtime can not be dropped (blocking) inside async code , so it is shut down without waiting
+        if let Some(pool) = self.pool.take() {
+            pool.shutdown_background();
+        }
+    }
 }
 
 /**
@@ -75,5 +429,265 @@ pub fn generate_otp() -> String {
     // 6 digit random integer (1 lak to 10 lakh -1)
     let random_number = rand::rng().random_range(100000..=999999);
 
-    return random_number.to_string();
+    random_number.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // smallest argon2 costs , so the tests stay fast
+    fn cheap_config() -> PasswordHasherConfig {
+        PasswordHasherConfig {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+            ..PasswordHasherConfig::default()
+        }
+    }
+
+    fn peppered_config(pepper_id: &str, old_peppers: &[&str]) -> PasswordHasherConfig {
+        PasswordHasherConfig {
+            pepper: Some(format!("pepper-{}", pepper_id).into_bytes()),
+            pepper_id: pepper_id.to_string(),
+            old_peppers: old_peppers
+                .iter()
+                .map(|id| (id.to_string(), format!("pepper-{}", id).into_bytes()))
+                .collect(),
+            ..cheap_config()
+        }
+    }
+
+    fn hash(config: &PasswordHasherConfig, pass: &str) -> String {
+        hash_with(config, pass.to_string()).unwrap()
+    }
+
+    fn validate(
+        config: &PasswordHasherConfig,
+        pass: &str,
+        hash: &str,
+    ) -> Result<bool, ErrorMessage> {
+        validate_with(config, pass.to_string(), hash.to_string())
+    }
+
+    #[test]
+    fn argon2_hash_round_trip() {
+        let config = cheap_config();
+        let hashed = hash(&config, "correct horse");
+
+        assert!(hashed.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+        assert!(validate(&config, "correct horse", &hashed).unwrap());
+        assert!(!validate(&config, "wrong horse", &hashed).unwrap());
+    }
+
+    #[test]
+    fn hash_of_current_config_needs_no_rehash() {
+        let config = cheap_config();
+
+        assert!(!needs_rehash_with(&config, &hash(&config, "correct horse")));
+
+        let config = peppered_config("p2", &["p1"]);
+        assert!(!needs_rehash_with(&config, &hash(&config, "correct horse")));
+    }
+
+    #[test]
+    fn outdated_params_need_rehash() {
+        let config = cheap_config();
+        let hashed = hash(&config, "correct horse");
+
+        for newer in [
+            PasswordHasherConfig {
+                memory_kib: 128,
+                ..cheap_config()
+            },
+            PasswordHasherConfig {
+                iterations: 2,
+                ..cheap_config()
+            },
+            PasswordHasherConfig {
+                parallelism: 2,
+                ..cheap_config()
+            },
+        ] {
+            assert!(needs_rehash_with(&newer, &hashed));
+        }
+    }
+
+    #[test]
+    fn other_algorithms_need_rehash() {
+        let config = cheap_config();
+
+        let params = Params::new(64, 1, 1, None).unwrap();
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, params)
+            .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+
+        assert!(needs_rehash_with(&config, &argon2i));
+        assert!(needs_rehash_with(
+            &config,
+            &bcrypt::hash("correct horse", 4).unwrap()
+        ));
+        assert!(needs_rehash_with(&config, "not a hash"));
+    }
+
+    #[test]
+    fn peppered_hash_has_the_pepper_id_and_needs_the_pepper() {
+        let config = peppered_config("p1", &[]);
+        let hashed = hash(&config, "correct horse");
+
+        assert!(hashed.contains("keyid="));
+        assert!(validate(&config, "correct horse", &hashed).unwrap());
+        assert!(!validate(&config, "wrong horse", &hashed).unwrap());
+
+        // older unpeppered hash (no keyid) still verifies after the pepper is added
+        let unpeppered = hash(&cheap_config(), "correct horse");
+        assert!(validate(&config, "correct horse", &unpeppered).unwrap());
+        assert_ne!(hashed, unpeppered);
+
+        // server without this pepper can not verify it at all
+        assert!(matches!(
+            validate(&cheap_config(), "correct horse", &hashed),
+            Err(ErrorMessage::UnknownPepper)
+        ));
+    }
+
+    #[test]
+    fn pepper_is_picked_by_the_keyid_of_the_hash() {
+        let config = peppered_config("p3", &["p1", "p2"]);
+
+        assert_eq!(pepper_for_keyid(&config, b"p3"), Some(&b"pepper-p3"[..]));
+        assert_eq!(pepper_for_keyid(&config, b"p1"), Some(&b"pepper-p1"[..]));
+        assert_eq!(pepper_for_keyid(&config, b"p2"), Some(&b"pepper-p2"[..]));
+        assert_eq!(pepper_for_keyid(&config, b"p4"), None);
+
+        // pepper id without a pepper (pepper turned off) is not a key
+        assert_eq!(pepper_for_keyid(&cheap_config(), b"p1"), None);
+    }
+
+    #[test]
+    fn hash_of_rotated_pepper_still_verifies_and_needs_rehash() {
+        let old_hash = hash(&peppered_config("p1", &[]), "correct horse");
+        let rotated = peppered_config("p2", &["p1"]);
+
+        assert!(validate(&rotated, "correct horse", &old_hash).unwrap());
+        assert!(!validate(&rotated, "wrong horse", &old_hash).unwrap());
+        assert!(needs_rehash_with(&rotated, &old_hash));
+
+        // unpeppered hash needs rehash once a pepper is set , peppered one once it is removed
+        assert!(needs_rehash_with(
+            &rotated,
+            &hash(&cheap_config(), "correct horse")
+        ));
+        assert!(needs_rehash_with(&cheap_config(), &old_hash));
+
+        // old pepper which is dropped from config makes the hash unverifiable
+        assert!(matches!(
+            validate(&peppered_config("p2", &[]), "correct horse", &old_hash),
+            Err(ErrorMessage::UnknownPepper)
+        ));
+    }
+
+    // hashes of the old system , made outside this crate (python hashlib , crypt_blowfish test vectors)
+    const LEGACY_PASSWORD: &str = "legacy Pass 1!";
+    const PBKDF2_HASH: &str = "$pbkdf2-sha256$i=1000,l=32$bGVnYWN5LXNhbHQtMDAwMQ$wTsQYfxsR6U7QFO53qr23fW0DOrcyHuI2bIltc+R4xc";
+    const SCRYPT_HASH: &str =
+        "$scrypt$ln=4,r=8,p=1$bGVnYWN5LXNhbHQtMDAwMQ$ehneUjvHqJX+/ejO1JNTG80dw4litUU5c1QHtZlFBDA";
+    const BCRYPT_VECTORS: [(&str, &str); 3] = [
+        (
+            "U*U",
+            "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW",
+        ),
+        (
+            "U*U*",
+            "$2a$05$CCCCCCCCCCCCCCCCCCCCC.VGOzA784oUp/Z0DY336zx7pLYAy0lwK",
+        ),
+        (
+            "U*U*U",
+            "$2a$05$XXXXXXXXXXXXXXXXXXXXXOAcXxm9kjPGEMsLznoKqmqw7tc8WCx4a",
+        ),
+    ];
+
+    #[test]
+    fn legacy_pbkdf2_and_scrypt_hashes_verify() {
+        let config = cheap_config();
+
+        for hashed in [PBKDF2_HASH, SCRYPT_HASH] {
+            assert!(
+                validate(&config, LEGACY_PASSWORD, hashed).unwrap(),
+                "{}",
+                hashed
+            );
+            assert!(
+                !validate(&config, "legacy Pass 2!", hashed).unwrap(),
+                "{}",
+                hashed
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_bcrypt_hashes_verify() {
+        let config = cheap_config();
+
+        for (password, hashed) in BCRYPT_VECTORS {
+            assert!(validate(&config, password, hashed).unwrap(), "{}", hashed);
+            assert!(!validate(&config, "U*U*U*", hashed).unwrap(), "{}", hashed);
+        }
+
+        // $2b$ and $2y$ are the same scheme
+        let hashed = bcrypt::hash(LEGACY_PASSWORD, 4).unwrap();
+        assert!(hashed.starts_with("$2b$"));
+        assert!(validate(&config, LEGACY_PASSWORD, &hashed).unwrap());
+        assert!(
+            validate(
+                &config,
+                LEGACY_PASSWORD,
+                &hashed.replacen("$2b$", "$2y$", 1)
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn only_hashes_we_can_verify_are_imported() {
+        for hashed in [PBKDF2_HASH, SCRYPT_HASH, BCRYPT_VECTORS[0].1] {
+            assert!(is_supported_hash(hashed), "{}", hashed);
+        }
+        assert!(is_supported_hash(&hash(&cheap_config(), LEGACY_PASSWORD)));
+
+        // peppered hash of another system can never be verified here
+        assert!(!is_supported_hash(&hash(
+            &peppered_config("p1", &[]),
+            LEGACY_PASSWORD
+        )));
+
+        for hashed in [
+            "",
+            "plain text",
+            "$1$md5salt$hash",
+            "$pbkdf2-sha512$i=1000$bGVnYWN5$aGFzaA",
+            "$2b$05$short",
+        ] {
+            assert!(!is_supported_hash(hashed), "{}", hashed);
+        }
+    }
+
+    #[test]
+    fn imported_hash_is_upgraded_to_argon2id_after_login() {
+        let config = peppered_config("p1", &[]);
+
+        for hashed in [PBKDF2_HASH, SCRYPT_HASH] {
+            // login with the old hash works and asks for a new hash
+            assert!(validate(&config, LEGACY_PASSWORD, hashed).unwrap());
+            assert!(needs_rehash_with(&config, hashed));
+
+            let upgraded = hash(&config, LEGACY_PASSWORD);
+            assert!(upgraded.starts_with("$argon2id$"));
+            assert!(validate(&config, LEGACY_PASSWORD, &upgraded).unwrap());
+            assert!(!needs_rehash_with(&config, &upgraded));
+        }
+
+        assert!(needs_rehash_with(&config, BCRYPT_VECTORS[0].1));
+    }
 }
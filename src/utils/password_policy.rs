@@ -0,0 +1,704 @@
+// password policy , configured at startup from env (see config.rs)
+// every rule which the password breaks is returned (not only the first one) , so the frontend can show all of them
+// strength is a zxcvbn like estimate , password is split into parts (common passwords , sequences , repeats , keyboard runs , personal info)
+// guesses of every part are multiplied and the total guesses give the score 0 (very weak) to 4 (very strong)
+
+use serde::Serialize;
+
+use crate::utils::password::validate_pas;
+
+// one broken rule , rule is a fixed id (eg min_length) and message is for the user
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordViolation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize, // hash_pass never takes more than 64 chars , whatever this is
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub min_strength_score: u8, // 0 to 4 like zxcvbn , 0 turns the strength check off
+    pub reject_personal_info: bool, // email (before @) and name should not be inside the password
+    pub history_size: usize, // last N passwords (current one included) can not be used again , 0 turns it off
+}
+
+impl PasswordPolicy {
+    /**
+     * rules which do not need the older passwords of the user
+     * @inputs => password and personal info of the user (email , name)
+     * @result => every broken rule , empty if the password is okay
+     */
+    pub fn check(&self, password: &str, personal_info: &[&str]) -> Vec<PasswordViolation> {
+        let mut violations = Vec::new();
+
+        let mut violation = |rule: &'static str, message: String| {
+            violations.push(PasswordViolation { rule, message });
+        };
+
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            violation(
+                "min_length",
+                format!(
+                    "password should have at least {} characters",
+                    self.min_length
+                ),
+            );
+        }
+
+        if length > self.max_length {
+            violation(
+                "max_length",
+                format!(
+                    "password should have at most {} characters",
+                    self.max_length
+                ),
+            );
+        }
+
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            violation(
+                "lowercase",
+                "password should have a lowercase letter".to_string(),
+            );
+        }
+
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            violation(
+                "uppercase",
+                "password should have an uppercase letter".to_string(),
+            );
+        }
+
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violation("digit", "password should have a digit".to_string());
+        }
+
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+            violation("symbol", "password should have a symbol".to_string());
+        }
+
+        let personal_tokens = personal_tokens(personal_info);
+
+        if self.reject_personal_info {
+            let lower = password.to_lowercase();
+
+            if personal_tokens
+                .iter()
+                .any(|token| lower.contains(token.as_str()))
+            {
+                violation(
+                    "personal_info",
+                    "password should not contain your email or name".to_string(),
+                );
+            }
+        }
+
+        if self.min_strength_score > 0 {
+            let score = strength_score(password, &personal_tokens);
+
+            if score < self.min_strength_score {
+                violation(
+                    "strength",
+                    format!(
+                        "password is too easy to guess (strength {} of 4 , at least {} is needed)",
+                        score, self.min_strength_score
+                    ),
+                );
+            }
+        }
+
+        violations
+    }
+
+    /**
+     * @inputs => password and hashes of the current and older passwords of the user (newest first)
+     * @result => violation if the password is one of the last history_size passwords
+     */
+    pub fn check_history(
+        &self,
+        password: &str,
+        password_hashes: &[String],
+    ) -> Option<PasswordViolation> {
+        let reused = password_hashes
+            .iter()
+            .take(self.history_size)
+            .any(|hash| validate_pas(password, hash).unwrap_or(false));
+
+        reused.then(|| PasswordViolation {
+            rule: "history",
+            message: format!(
+                "password should not be one of your last {} passwords",
+                self.history_size
+            ),
+        })
+    }
+}
+
+/**
+ * lowercase words (3 or more chars) of the personal info , for email only the part before @ is used
+ */
+fn personal_tokens(personal_info: &[&str]) -> Vec<String> {
+    personal_info
+        .iter()
+        .map(|info| info.split('@').next().unwrap_or_default().to_lowercase())
+        .flat_map(|info| {
+            info.split(|c: char| !c.is_alphanumeric())
+                .filter(|token| token.chars().count() >= 3)
+                .map(|token| token.to_string())
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+// most used passwords and password words , lower index is guessed first
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "fuckme",
+    "2000",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "klaster",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "1111",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "pass",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "biteme",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "admin",
+    "welcome",
+    "login",
+    "passw",
+    "secret",
+    "hello",
+    "test",
+    "guest",
+    "root",
+    "changeme",
+    "default",
+    "winter",
+    "spring",
+    "autumn",
+    "flower",
+    "orange",
+    "banana",
+    "apple",
+    "cookie",
+    "qwerty123",
+    "password1",
+    "abcdef",
+    "abcd",
+    "user",
+    "money",
+    "family",
+    "friend",
+    "lovely",
+    "angel",
+    "baby",
+    "blessed",
+    "jesus",
+    "god",
+    "super",
+    "power",
+    "pokemon",
+    "naruto",
+    "samsung",
+    "google",
+    "internet",
+    "server",
+    "system",
+    "manager",
+    "office",
+    "company",
+    "secure",
+    "private",
+    "letme",
+    "whatever",
+];
+
+// keyboard rows , chars next to each other in a row are easy to type (qwerty , asdf)
+const KEYBOARD_ROWS: &[&str] = &[
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+/**
+ * same char for common l33t replacements (p@ssw0rd => password)
+ */
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        '8' => 'b',
+        other => other,
+    }
+}
+
+/**
+ * guesses needed for one char when nothing is known about it
+ */
+fn bruteforce_log10(c: char) -> f64 {
+    if c.is_ascii_digit() {
+        10_f64.log10()
+    } else if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+        26_f64.log10()
+    } else {
+        33_f64.log10()
+    }
+}
+
+/**
+ * zxcvbn like score 0-4 from log10 of guesses (10^3 , 10^6 , 10^8 , 10^10)
+ * @inputs => password and personal tokens (they are guessed first , like the most common password)
+ */
+pub fn strength_score(password: &str, personal_tokens: &[String]) -> u8 {
+    let guesses = estimate_log10_guesses(password, personal_tokens);
+
+    match guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+/**
+ * splitting the password from left to right , at every position we take the pattern which saves most guesses
+ * compared to bruteforce of the same chars , chars with no pattern are bruteforced one by one
+ * @result => log10 of total guesses
+ */
+fn estimate_log10_guesses(password: &str, personal_tokens: &[String]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+
+    // to_lowercase can change the length for some unicode chars , then we only bruteforce
+    if lower.len() != chars.len() {
+        return chars.iter().map(|c| bruteforce_log10(*c)).sum();
+    }
+
+    let unleeted: Vec<char> = lower.iter().map(|c| unleet(*c)).collect();
+
+    let mut total = 0.0;
+    let mut parts = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let mut best: Option<(usize, f64)> = None;
+        let mut best_saving = 0.0;
+
+        for (length, guesses) in pattern_matches(&chars, &lower, &unleeted, i, personal_tokens) {
+            let bruteforce: f64 = chars[i..i + length]
+                .iter()
+                .map(|c| bruteforce_log10(*c))
+                .sum();
+            let saving = bruteforce - guesses;
+
+            if saving > best_saving {
+                best_saving = saving;
+                best = Some((length, guesses));
+            }
+        }
+
+        let (length, guesses) = best.unwrap_or((1, bruteforce_log10(chars[i])));
+
+        total += guesses;
+        parts += 1;
+        i += length;
+    }
+
+    // order of the parts also has to be guessed
+    let order: f64 = (2..=parts).map(|k| (k as f64).log10()).sum();
+
+    total + order
+}
+
+/**
+ * every pattern which starts at position i
+ * @result => (length of the pattern , log10 guesses of the pattern)
+ */
+fn pattern_matches(
+    chars: &[char],
+    lower: &[char],
+    unleeted: &[char],
+    i: usize,
+    personal_tokens: &[String],
+) -> Vec<(usize, f64)> {
+    let mut matches = Vec::new();
+
+    // common passwords and personal info , personal info is guessed first (rank 1)
+    let words = personal_tokens
+        .iter()
+        .map(|token| (1, token.as_str()))
+        .chain(
+            COMMON_PASSWORDS
+                .iter()
+                .enumerate()
+                .map(|(index, word)| (index + 1, *word)),
+        );
+
+    for (rank, word) in words {
+        let word: Vec<char> = word.chars().collect();
+        let end = i + word.len();
+
+        if word.len() < 3 || end > chars.len() || unleeted[i..end] != word[..] {
+            continue;
+        }
+
+        let mut guesses = (rank as f64).log10();
+
+        // Password / PASSWORD are tried early , random uppercase chars cost more
+        let uppercase = chars[i..end].iter().filter(|c| c.is_uppercase()).count();
+        if uppercase > 0 {
+            let first_only = uppercase == 1 && chars[i].is_uppercase();
+            let all = uppercase == word.len();
+            guesses += if first_only || all {
+                2_f64.log10()
+            } else {
+                uppercase as f64 * 2_f64.log10()
+            };
+        }
+
+        // p@ssw0rd is tried soon after password
+        if lower[i..end] != unleeted[i..end] {
+            guesses += 2_f64.log10();
+        }
+
+        matches.push((word.len(), guesses));
+    }
+
+    // same char again and again (aaaa , 1111)
+    let repeat = lower[i..].iter().take_while(|c| **c == lower[i]).count();
+    if repeat >= 3 {
+        matches.push((repeat, bruteforce_log10(chars[i]) + (repeat as f64).log10()));
+    }
+
+    // sequences (abcd , 4321)
+    for step in [1_i32, -1] {
+        let length = 1 + lower[i..]
+            .windows(2)
+            .take_while(|pair| pair[1] as i32 - pair[0] as i32 == step)
+            .count();
+
+        if length >= 3 {
+            let start = lower[i];
+            let base: f64 = if matches!(start, 'a' | 'z' | '0' | '1' | '9') {
+                4.0
+            } else if start.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction: f64 = if step < 0 { 2.0 } else { 1.0 };
+
+            matches.push((length, (base * length as f64 * direction).log10()));
+        }
+    }
+
+    // keyboard runs in one row (qwerty , asdf , 7890)
+    for row in KEYBOARD_ROWS {
+        let row: Vec<char> = row.chars().collect();
+        let position = |c: char| row.iter().position(|key| *key == c);
+
+        let length = 1 + lower[i..]
+            .windows(2)
+            .take_while(|pair| match (position(pair[0]), position(pair[1])) {
+                (Some(a), Some(b)) => a.abs_diff(b) == 1,
+                _ => false,
+            })
+            .count();
+
+        if length >= 3 && position(lower[i]).is_some() {
+            matches.push((length, (10.0 * length as f64).log10()));
+        }
+    }
+
+    // years (1990 , 2024)
+    if i + 4 <= lower.len() {
+        let year: String = lower[i..i + 4].iter().collect();
+
+        if year.chars().all(|c| c.is_ascii_digit())
+            && (year.starts_with("19") || year.starts_with("20"))
+        {
+            matches.push((4, 120_f64.log10()));
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every rule on , like a strict production config
+    fn strict_policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 12,
+            max_length: 64,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            min_strength_score: 3,
+            reject_personal_info: true,
+            history_size: 3,
+        }
+    }
+
+    const PERSONAL_INFO: [&str; 2] = ["john.smith@example.com", "John Smith"];
+
+    fn broken_rules(policy: &PasswordPolicy, password: &str) -> Vec<&'static str> {
+        policy
+            .check(password, &PERSONAL_INFO)
+            .into_iter()
+            .map(|violation| violation.rule)
+            .collect()
+    }
+
+    #[test]
+    fn strong_password_breaks_no_rule() {
+        assert!(broken_rules(&strict_policy(), "vK8#qTz2!mWp9rL").is_empty());
+    }
+
+    #[test]
+    fn length_rules() {
+        let policy = strict_policy();
+
+        assert!(broken_rules(&policy, "vK8#qT").contains(&"min_length"));
+        assert!(broken_rules(&policy, &"vK8#qTz2!mWp9rL".repeat(5)).contains(&"max_length"));
+
+        // length is counted in chars , not bytes
+        let policy = PasswordPolicy {
+            min_length: 4,
+            ..strict_policy()
+        };
+        assert!(!broken_rules(&policy, "äöüß").contains(&"min_length"));
+    }
+
+    #[test]
+    fn every_missing_char_class_is_a_violation() {
+        let policy = strict_policy();
+
+        assert!(broken_rules(&policy, "VK8#QTZ2!MWP9RL").contains(&"lowercase"));
+        assert!(broken_rules(&policy, "vk8#qtz2!mwp9rl").contains(&"uppercase"));
+        assert!(broken_rules(&policy, "vKx#qTz!!mWp?rL").contains(&"digit"));
+        assert!(broken_rules(&policy, "vK8xqTz2kmWp9rL").contains(&"symbol"));
+
+        // all of them are returned together
+        let rules = broken_rules(&policy, "          ");
+        for rule in ["min_length", "lowercase", "uppercase", "digit"] {
+            assert!(rules.contains(&rule), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn class_rules_which_are_off_are_not_checked() {
+        let policy = PasswordPolicy {
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            ..strict_policy()
+        };
+
+        assert!(broken_rules(&policy, "vkxbqtzhkmwpfrl").is_empty());
+    }
+
+    #[test]
+    fn email_or_name_inside_the_password_is_a_violation() {
+        let policy = strict_policy();
+
+        // part of the email before @ and words of the name , case insensitive
+        assert!(broken_rules(&policy, "Smith#8qTz2!mWp").contains(&"personal_info"));
+        assert!(broken_rules(&policy, "vK8#JOHNz2!mWp9").contains(&"personal_info"));
+
+        // domain of the email is not personal info
+        assert!(!broken_rules(&policy, "vK8#example2!mWp9").contains(&"personal_info"));
+
+        let policy = PasswordPolicy {
+            reject_personal_info: false,
+            ..strict_policy()
+        };
+        assert!(!broken_rules(&policy, "Smith#8qTz2!mWp").contains(&"personal_info"));
+    }
+
+    #[test]
+    fn easy_to_guess_password_is_a_violation() {
+        let policy = strict_policy();
+
+        // has every char class and length , but it is a common password with a keyboard run and a year
+        assert!(broken_rules(&policy, "Password123!2024").contains(&"strength"));
+        assert!(broken_rules(&policy, "Qwertyuiop1!").contains(&"strength"));
+        assert!(!broken_rules(&policy, "vK8#qTz2!mWp9rL").contains(&"strength"));
+
+        let policy = PasswordPolicy {
+            min_strength_score: 0,
+            ..strict_policy()
+        };
+        assert!(!broken_rules(&policy, "Password123!2024").contains(&"strength"));
+    }
+
+    #[test]
+    fn strength_scores() {
+        assert_eq!(strength_score("password", &[]), 0);
+        assert_eq!(strength_score("aaaaaaaa", &[]), 0);
+        assert!(strength_score("P@ssw0rd", &[]) <= 1);
+        assert_eq!(strength_score("vK8#qTz2!mWp9rL", &[]), 4);
+
+        // personal info is guessed first
+        let tokens = personal_tokens(&PERSONAL_INFO);
+        assert!(strength_score("smithjohn", &tokens) < strength_score("smithjohn", &[]));
+    }
+
+    #[test]
+    fn recent_password_is_a_history_violation() {
+        let policy = strict_policy();
+
+        // cheap bcrypt hashes , newest first
+        let hashes: Vec<String> = [
+            "current-Pass1!",
+            "older-Pass2!",
+            "oldest-Pass3!",
+            "too-old-Pass4!",
+        ]
+        .iter()
+        .map(|password| bcrypt::hash(password, 4).unwrap())
+        .collect();
+
+        let violation = policy.check_history("older-Pass2!", &hashes).unwrap();
+        assert_eq!(violation.rule, "history");
+
+        assert!(policy.check_history("current-Pass1!", &hashes).is_some());
+        assert!(policy.check_history("brand-new-Pass5!", &hashes).is_none());
+
+        // only the last history_size passwords count
+        assert!(policy.check_history("too-old-Pass4!", &hashes).is_none());
+    }
+
+    #[test]
+    fn every_violation_goes_in_the_error_body() {
+        let violations = strict_policy().check("password", &PERSONAL_INFO);
+
+        // body of the 400 is the serialized error
+        let body = serde_json::to_value(crate::errors::HttpError::password_policy(
+            violations.clone(),
+        ))
+        .unwrap();
+
+        assert_eq!(body["status"], 400);
+
+        let rules: Vec<&str> = body["violations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|violation| violation["rule"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            rules,
+            violations
+                .iter()
+                .map(|violation| violation.rule)
+                .collect::<Vec<_>>()
+        );
+
+        for rule in ["min_length", "uppercase", "digit", "symbol", "strength"] {
+            assert!(rules.contains(&rule), "{}", rule);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// totp (authenticator app codes) and recovery codes for two factor login
+
+use rand::{Rng, distr::Alphanumeric};
+use totp_rs::{Builder, Secret, Totp};
+
+// name shown in the authenticator app
+const TOTP_ISSUER: &str = "rust_auth_full";
+
+// number of recovery codes given when totp is enabled
+pub const RECOVERY_CODES_COUNT: usize = 10;
+
+/**
+ * new random totp secret (160 bits) in base32 , this is what we save and put in the otpauth uri
+ */
+pub fn generate_totp_secret() -> String {
+    Secret::generate().to_base32()
+}
+
+// sha1 , 6 digits , 30 sec step and 1 step skew , every authenticator app supports these defaults
+fn build_totp(secret: &str, account_name: &str) -> Result<Totp, String> {
+    let secret = Secret::try_from_base32(secret).map_err(|e| e.to_string())?;
+
+    Builder::new()
+        .with_secret(secret)
+        .with_account_name(account_name)
+        .with_issuer(Some(TOTP_ISSUER))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/**
+ * otpauth://totp/... uri , frontend shows it as qr code for the authenticator app
+ */
+pub fn totp_uri(secret: &str, account_name: &str) -> Result<String, String> {
+    build_totp(secret, account_name)?
+        .to_url()
+        .map_err(|e| e.to_string())
+}
+
+/**
+ * checking the code against the current time (one step before/after is also fine for clock drift)
+ * @result => time step of the matched code , caller must save it so the same code is not accepted twice
+ * none if code is wrong or its step is not newer than the last used step
+ */
+pub fn verify_totp_code(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let totp = build_totp(secret, TOTP_ISSUER).ok()?;
+
+    let step = totp.check_current(code.trim())? as i64;
+
+    match last_used_step {
+        Some(last_step) if step <= last_step => None,
+        _ => Some(step),
+    }
+}
+
+/**
+ * random one time recovery codes (xxxxx-xxxxx) , shown to the user only once
+ */
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            let code: String = rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| (c as char).to_ascii_lowercase())
+                .collect();
+
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}